@@ -0,0 +1,93 @@
+//! Parsing of whole search pages with serde_json, and row by row with serde_json by
+//! `SearchPage::parse`, as searches do (compared with reading the rows into `serde_json::Value`s
+//! first, for `read_rows`):
+//!
+//! ```bash
+//! cargo bench --bench search_page
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hypothesis::annotations::{read_rows, Annotation, SearchPage};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Only parsed, never read
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct SearchResult {
+    rows: Vec<Annotation>,
+    total: usize,
+}
+
+/// Rows read into `serde_json::Value`s first, as for `read_rows`
+#[derive(Deserialize)]
+struct ValueRows {
+    rows: Vec<serde_json::Value>,
+}
+
+/// Body of a search response with `rows` annotations, like the API returns them
+fn search_page(rows: usize) -> String {
+    let rows: Vec<_> = (0..rows)
+        .map(|i| {
+            json!({
+                "id": format!("annotation{:06}", i),
+                "created": "2023-01-02T10:00:00.000000+00:00",
+                "updated": "2023-01-02T10:00:00.000000+00:00",
+                "user": "acct:alice@hypothes.is",
+                "uri": format!("https://example.com/posts/{}", i % 50),
+                "text": "A longer comment on the highlighted passage, with a [link](https://example.com) and some *emphasis*. ".repeat(3),
+                "tags": ["reading", "week-3", "methods"],
+                "group": "__world__",
+                "permissions": {
+                    "read": ["group:__world__"],
+                    "admin": ["acct:alice@hypothes.is"],
+                    "update": ["acct:alice@hypothes.is"],
+                    "delete": ["acct:alice@hypothes.is"],
+                },
+                "target": [{
+                    "source": format!("https://example.com/posts/{}", i % 50),
+                    "selector": [
+                        {"type": "RangeSelector", "endOffset": 120, "startOffset": 10,
+                         "endContainer": "/main[1]/p[3]", "startContainer": "/main[1]/p[3]"},
+                        {"type": "TextPositionSelector", "end": 2410, "start": 2300},
+                        {"type": "TextQuoteSelector", "exact": "the passage that was highlighted by the reader",
+                         "prefix": "some text before ", "suffix": " and some text after"},
+                    ],
+                }],
+                "document": {"title": [format!("Post {}", i % 50)]},
+                "links": {
+                    "html": format!("https://hypothes.is/a/annotation{:06}", i),
+                    "incontext": format!("https://hyp.is/annotation{:06}/example.com/posts/{}", i, i % 50),
+                    "json": format!("https://hypothes.is/api/annotations/annotation{:06}", i),
+                },
+                "user_info": {"display_name": "Alice"},
+                "flagged": false,
+                "hidden": false,
+            })
+        })
+        .collect();
+    json!({"total": rows.len(), "rows": rows}).to_string()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search_page");
+    for rows in [200, 2000] {
+        let page = search_page(rows);
+        group.throughput(Throughput::Bytes(page.len() as u64));
+        group.bench_with_input(BenchmarkId::new("serde_json", rows), &page, |b, page| {
+            b.iter(|| serde_json::from_str::<SearchResult>(page).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("read_rows", rows), &page, |b, page| {
+            b.iter(|| read_rows(serde_json::from_str::<ValueRows>(page).unwrap().rows))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("SearchPage::parse", rows),
+            &page,
+            |b, page| b.iter(|| SearchPage::parse(page).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
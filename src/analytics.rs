@@ -0,0 +1,361 @@
+//! Participation statistics over a set of annotations: activity per user, tag frequencies,
+//! the websites annotated, the busiest days, how much people write and activity over time
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::analytics::analyze;
+//! use hypothesis::annotations::SearchQuery;
+//! let api = Hypothesis::from_env()?;
+//! let mut query = SearchQuery::builder().group("classGroupId").build()?;
+//! let annotations = api.search_annotations_return_all(&mut query).await?;
+//! analyze(&annotations).write_tables(std::io::stdout())?;
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+use url::Url;
+
+use crate::annotations::Annotation;
+use crate::errors::HypothesisError;
+use crate::facets::{count, Facet};
+use crate::output::{write_records, OutputFormat};
+use crate::UserAccountID;
+
+/// Dates as "YYYY-MM-DD"
+pub(crate) mod date {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use time::format_description::FormatItem;
+    use time::Date;
+
+    fn format() -> Vec<FormatItem<'static>> {
+        time::format_description::parse("[year]-[month]-[day]").expect("valid date format")
+    }
+
+    pub fn serialize<S: Serializer>(date: &Date, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(date)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Date, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        Date::parse(&date, &format()).map_err(D::Error::custom)
+    }
+}
+
+/// What one user did
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserActivity {
+    pub user: UserAccountID,
+    /// Display name, or the username if there is none
+    pub name: String,
+    /// All annotations, including replies
+    pub annotations: usize,
+    pub replies: usize,
+    /// Number of distinct documents annotated
+    pub documents: usize,
+    #[serde(with = "time::serde::rfc3339")]
+    pub first: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last: OffsetDateTime,
+}
+
+/// Number of annotations created on a day (UTC)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DayActivity {
+    #[serde(with = "date")]
+    pub date: Date,
+    pub annotations: usize,
+}
+
+/// Length of the periods of an [`activity_series`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    /// Days (UTC)
+    Day,
+    /// Weeks starting on Monday (UTC)
+    Week,
+}
+
+impl Bucket {
+    /// First day of the period that `date` falls in
+    pub fn start(self, date: Date) -> Date {
+        match self {
+            Bucket::Day => date,
+            Bucket::Week => {
+                date - time::Duration::days(i64::from(date.weekday().number_days_from_monday()))
+            }
+        }
+    }
+
+    fn days(self) -> i64 {
+        match self {
+            Bucket::Day => 1,
+            Bucket::Week => 7,
+        }
+    }
+}
+
+/// Number of annotations created in the period starting on `start`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActivityPoint {
+    #[serde(with = "date")]
+    pub start: Date,
+    pub annotations: usize,
+}
+
+/// Statistics of a set of annotations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Analytics {
+    pub total: usize,
+    /// Most annotations first, ties by username
+    pub users: Vec<UserActivity>,
+    /// Most frequent first, ties alphabetically
+    pub tags: Vec<Facet<String>>,
+    /// Hosts of annotated web pages without "www.", or the scheme of other URIs (e.g. "urn"),
+    /// most frequent first
+    pub domains: Vec<Facet<String>>,
+    /// Days with annotations, busiest first, ties in chronological order
+    pub busiest_days: Vec<DayActivity>,
+    /// Mean length of the annotations' text in characters, 0 without annotations
+    pub average_text_length: f64,
+}
+
+/// Host of a web `uri` without "www.", else its scheme
+fn domain(uri: &str) -> String {
+    match Url::parse(uri.trim()) {
+        Ok(url) => match url.host_str() {
+            Some(host) => host.trim_start_matches("www.").to_owned(),
+            None => url.scheme().to_owned(),
+        },
+        Err(_) => "unknown".to_owned(),
+    }
+}
+
+/// Statistics of `annotations`
+///
+/// # Example
+/// ```
+/// use hypothesis::UserAccountID;
+/// use hypothesis::analytics::analyze;
+/// # fn annotation(user: &str, created: &str, uri: &str, text: &str, tags: &[&str], references: &[&str]) -> hypothesis::annotations::Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": created, "created": created, "updated": created,
+/// #         "user": format!("acct:{}@hypothes.is", user), "uri": uri, "text": text, "tags": tags,
+/// #         "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "references": references, "target": [],
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("alice", "2023-01-02T09:00:00Z", "https://www.example.com/a", "Why?", &["question"], &[]),
+///     annotation("bob", "2023-01-02T10:00:00Z", "https://www.example.com/a", "Because", &[], &["x"]),
+///     annotation("alice", "2023-01-03T10:00:00Z", "https://docs.rs/time", "", &["question"], &[]),
+/// ];
+/// let analytics = analyze(&annotations);
+/// assert_eq!(analytics.total, 3);
+/// assert_eq!(analytics.users[0].user, UserAccountID::new("alice", "hypothes.is"));
+/// assert_eq!(analytics.users[0].annotations, 2);
+/// assert_eq!(analytics.users[0].documents, 2);
+/// assert_eq!(analytics.users[1].replies, 1);
+/// assert_eq!(analytics.tags[0].value, "question");
+/// assert_eq!(analytics.domains[0].value, "example.com");
+/// assert_eq!(analytics.busiest_days[0].date.to_string(), "2023-01-02");
+/// assert_eq!(analytics.average_text_length, 11.0 / 3.0);
+/// ```
+pub fn analyze(annotations: &[Annotation]) -> Analytics {
+    let mut users: HashMap<&UserAccountID, (UserActivity, HashSet<&str>)> = HashMap::new();
+    for annotation in annotations {
+        let (activity, documents) = users.entry(&annotation.user).or_insert_with(|| {
+            let activity = UserActivity {
+                user: annotation.user.clone(),
+                name: annotation.user.username().to_owned(),
+                annotations: 0,
+                replies: 0,
+                documents: 0,
+                first: annotation.created,
+                last: annotation.created,
+            };
+            (activity, HashSet::new())
+        });
+        activity.annotations += 1;
+        if !annotation.references.is_empty() {
+            activity.replies += 1;
+        }
+        if let Some(name) = annotation
+            .user_info
+            .as_ref()
+            .and_then(|info| info.display_name.as_ref())
+        {
+            activity.name = name.clone();
+        }
+        activity.first = activity.first.min(annotation.created);
+        activity.last = activity.last.max(annotation.created);
+        documents.insert(&annotation.uri);
+    }
+    let mut users: Vec<UserActivity> = users
+        .into_values()
+        .map(|(mut activity, documents)| {
+            activity.documents = documents.len();
+            activity
+        })
+        .collect();
+    users.sort_by(|a, b| {
+        b.annotations
+            .cmp(&a.annotations)
+            .then_with(|| a.user.0.cmp(&b.user.0))
+    });
+
+    let domains: Vec<String> = annotations.iter().map(|a| domain(&a.uri)).collect();
+    let mut days: HashMap<Date, usize> = HashMap::new();
+    for annotation in annotations {
+        *days.entry(annotation.created.date()).or_insert(0) += 1;
+    }
+    let mut days: Vec<DayActivity> = days
+        .into_iter()
+        .map(|(date, annotations)| DayActivity { date, annotations })
+        .collect();
+    days.sort_by(|a, b| b.annotations.cmp(&a.annotations).then(a.date.cmp(&b.date)));
+
+    let text_length: usize = annotations.iter().map(|a| a.text.chars().count()).sum();
+    Analytics {
+        total: annotations.len(),
+        users,
+        tags: count(annotations.iter().flat_map(|a| &a.tags), |tag| tag),
+        domains: count(domains.iter(), |domain| domain),
+        busiest_days: days,
+        average_text_length: if annotations.is_empty() {
+            0.0
+        } else {
+            text_length as f64 / annotations.len() as f64
+        },
+    }
+}
+
+/// Writes `records` as a table under `title`, unless there are none
+fn section<T: Serialize>(
+    mut writer: impl Write,
+    title: &str,
+    records: &[T],
+    fields: &[&str],
+) -> Result<(), HypothesisError> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    writeln!(writer, "\n{}\n", title)?;
+    write_records(records, OutputFormat::Table, fields, writer)
+}
+
+/// Annotations created per period, from the period of the first annotation to that of the last,
+/// including periods without any, for charting
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use hypothesis::analytics::{activity_series, write_series_csv, Bucket};
+/// # fn annotation(created: &str) -> hypothesis::annotations::Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": created, "created": created, "updated": created,
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "", "tags": [],
+/// #         "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #     })).unwrap()
+/// # }
+/// // a Wednesday, the Sunday after and the Tuesday two weeks later
+/// let annotations = vec![
+///     annotation("2023-01-04T09:00:00Z"),
+///     annotation("2023-01-08T23:00:00Z"),
+///     annotation("2023-01-17T10:00:00Z"),
+/// ];
+/// let mut csv = Vec::new();
+/// write_series_csv(&activity_series(&annotations, Bucket::Week), &mut csv)?;
+/// assert_eq!(String::from_utf8(csv).unwrap(), "\
+/// start,annotations
+/// 2023-01-02,2
+/// 2023-01-09,0
+/// 2023-01-16,1
+/// ");
+/// assert_eq!(activity_series(&annotations, Bucket::Day).len(), 14);
+/// # Ok(())
+/// # }
+/// ```
+pub fn activity_series(annotations: &[Annotation], bucket: Bucket) -> Vec<ActivityPoint> {
+    let mut counts: HashMap<Date, usize> = HashMap::new();
+    for annotation in annotations {
+        *counts
+            .entry(bucket.start(annotation.created.date()))
+            .or_insert(0) += 1;
+    }
+    let (Some(&first), Some(&last)) = (counts.keys().min(), counts.keys().max()) else {
+        return Vec::new();
+    };
+    std::iter::successors(Some(first), |start| {
+        Some(*start + time::Duration::days(bucket.days())).filter(|next| *next <= last)
+    })
+    .map(|start| ActivityPoint {
+        start,
+        annotations: counts.get(&start).copied().unwrap_or_default(),
+    })
+    .collect()
+}
+
+/// Writes `series` as CSV with "start" and "annotations" columns
+pub fn write_series_csv(
+    series: &[ActivityPoint],
+    writer: impl Write,
+) -> Result<(), HypothesisError> {
+    write_records(series, OutputFormat::Csv, &["start", "annotations"], writer)
+}
+
+impl Analytics {
+    /// Writes the statistics as titled tables, for a terminal or a plain text report
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::analytics::analyze;
+    /// let mut report = Vec::new();
+    /// analyze(&[]).write_tables(&mut report)?;
+    /// assert_eq!(String::from_utf8(report).unwrap(), "\
+    /// 0 annotations, 0.0 characters of text on average
+    /// ");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_tables(&self, mut writer: impl Write) -> Result<(), HypothesisError> {
+        writeln!(
+            writer,
+            "{} annotations, {:.1} characters of text on average",
+            self.total, self.average_text_length
+        )?;
+        section(
+            &mut writer,
+            "Users",
+            &self.users,
+            &[
+                "name",
+                "annotations",
+                "replies",
+                "documents",
+                "first",
+                "last",
+            ],
+        )?;
+        section(&mut writer, "Tags", &self.tags, &["value", "count"])?;
+        section(&mut writer, "Domains", &self.domains, &["value", "count"])?;
+        section(
+            &mut writer,
+            "Busiest days",
+            &self.busiest_days,
+            &["date", "annotations"],
+        )?;
+        Ok(())
+    }
+}
@@ -1,13 +1,15 @@
 //! Objects related to the "annotations" endpoint
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "cli")]
 use structopt::StructOpt;
 use time::OffsetDateTime;
 
-use crate::{errors, is_default, UserAccountID};
+use crate::hooks::SkippedRow;
+use crate::{errors, is_default, AnnotationID, GroupID, UserAccountID};
 
 #[cfg_attr(feature = "cli", derive(StructOpt))]
 #[cfg_attr(
@@ -41,10 +43,19 @@ use crate::{errors, is_default, UserAccountID};
 ///                                             "suffix of text")]).build()?)
 ///     .tags(vec!["tag1".into(), "tag2".into()])
 ///     .build()?;
+///
+/// // Loaded from a template, e.g. in a configuration file
+/// let annotation_loaded: InputAnnotation = serde_json::from_str(
+///     r#"{"uri": "https://www.example.com", "text": "My new annotation"}"#,
+/// )?;
+/// assert_eq!(annotation_loaded, annotation_simple);
+/// let json = serde_json::to_string(&annotation_complex)?;
+/// assert_eq!(serde_json::from_str::<InputAnnotation>(&json)?, annotation_complex);
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Serialize, Debug, Default, Clone, Builder, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Builder, PartialEq)]
+#[serde(default)]
 #[builder(default, build_fn(name = "builder"))]
 pub struct InputAnnotation {
     /// URI that this annotation is attached to.
@@ -57,13 +68,16 @@ pub struct InputAnnotation {
     pub uri: String,
     /// Annotation text / comment given by user
     ///
-    /// This is NOT the selected text on the web-page
-    #[serde(skip_serializing_if = "is_default")]
-    #[cfg_attr(feature = "cli", structopt(default_value, long))]
-    #[builder(setter(into))]
-    pub text: String,
+    /// This is NOT the selected text on the web-page.
+    /// None leaves the text unchanged on update, `Some("")` clears it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "cli", structopt(long))]
+    #[builder(setter(into, strip_option), default)]
+    pub text: Option<String>,
     /// Tags attached to the annotation
-    #[serde(skip_serializing_if = "is_default")]
+    ///
+    /// None leaves the tags unchanged on update, `Some(vec![])` removes all tags.
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[cfg_attr(feature = "cli", structopt(long))]
     #[builder(setter(strip_option), default)]
     pub tags: Option<Vec<String>>,
@@ -80,7 +94,7 @@ pub struct InputAnnotation {
     /// replies belong to the same group as their parent annotations.
     #[cfg_attr(feature = "cli", structopt(default_value, long))]
     #[builder(setter(into))]
-    pub group: String,
+    pub group: GroupID,
     /// Which part of the document does the annotation target?
     ///
     /// If left as default then the annotation is linked to the whole page.
@@ -88,32 +102,287 @@ pub struct InputAnnotation {
     #[cfg_attr(feature = "cli", structopt(skip))]
     pub target: Target,
     /// Annotation IDs for any annotations this annotation references (e.g. is a reply to)
-    #[serde(skip_serializing_if = "is_default")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     #[cfg_attr(feature = "cli", structopt(long))]
-    pub references: Vec<String>,
+    #[builder(setter(strip_option), default)]
+    pub references: Option<Vec<AnnotationID>>,
+    /// Who can read / update / delete the annotation.
+    ///
+    /// Use [`Permissions::private`] or [`Permissions::shared`] instead of building this by hand.
+    /// If left as default the annotation is shared with its group.
+    #[serde(skip_serializing_if = "is_default")]
+    #[cfg_attr(feature = "cli", structopt(skip))]
+    #[builder(setter(strip_option), default)]
+    pub permissions: Option<Permissions>,
 }
 
+/// Longest tag accepted by [`InputAnnotation::validate`]
+pub const MAX_TAG_LENGTH: usize = 256;
+
 impl InputAnnotation {
     pub fn builder() -> InputAnnotationBuilder {
         InputAnnotationBuilder::default()
     }
+
+    /// Checks the fields that are set, so mistakes are reported before the API rejects the
+    /// annotation with a bare "400 Bad Request":
+    /// - `uri` must be a URL or URN (e.g. "urn:x-pdf:..."),
+    /// - `group` must be a group ID like "__world__" or "Qe7fpc5Z",
+    /// - tags must not be blank or longer than [`MAX_TAG_LENGTH`] characters,
+    /// - the target's source must be `uri`, it can have at most one quote, which can't be
+    ///   empty, and text positions must not end before they start,
+    /// - references must not be empty IDs.
+    ///
+    /// Called by [`InputAnnotationBuilder::build`].
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::InputAnnotation;
+    /// let mut annotation = InputAnnotation::builder()
+    ///     .uri("https://example.com")
+    ///     .tags(vec!["rust".into()])
+    ///     .build()
+    ///     .unwrap();
+    /// annotation.tags = Some(vec!["rust".into(), " ".into()]);
+    /// assert!(annotation.validate().is_err());
+    /// assert!(InputAnnotation::builder().uri("example dot com").build().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), errors::HypothesisError> {
+        let invalid = |message: String| Err(errors::HypothesisError::ValidationError(message));
+        if !self.uri.is_empty() && url::Url::parse(&self.uri).is_err() {
+            return invalid(format!("uri {:?} is not a URL or URN", self.uri));
+        }
+        if !self.group.is_empty()
+            && !self
+                .group
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return invalid(format!("group {:?} is not a group ID", self.group.0));
+        }
+        for tag in self.tags.iter().flatten() {
+            if tag.trim().is_empty() {
+                return invalid("tags can't be blank".into());
+            }
+            if tag.chars().count() > MAX_TAG_LENGTH {
+                return invalid(format!(
+                    "tag {:?} is longer than {} characters",
+                    tag, MAX_TAG_LENGTH
+                ));
+            }
+        }
+        let target = &self.target;
+        if !target.source.is_empty() && !self.uri.is_empty() && target.source != self.uri {
+            return invalid(format!(
+                "target source {:?} is not the annotation's uri {:?}",
+                target.source, self.uri
+            ));
+        }
+        let mut quotes = 0;
+        for selector in &target.selector {
+            match selector {
+                Selector::TextQuoteSelector(quote) => {
+                    quotes += 1;
+                    if quote.exact.is_empty() {
+                        return invalid("quoted text can't be empty".into());
+                    }
+                }
+                Selector::TextPositionSelector(position) if position.end < position.start => {
+                    return invalid(format!(
+                        "text position ends ({}) before it starts ({})",
+                        position.end, position.start
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if quotes > 1 {
+            return invalid("target has more than one quote".into());
+        }
+        if self.references.iter().flatten().any(|id| id.is_empty()) {
+            return invalid("references can't be empty IDs".into());
+        }
+        Ok(())
+    }
+
+    /// A reply with `text` to `parent`, on the same document and in the same group
+    pub fn reply_to(parent: &Annotation, text: &str) -> Self {
+        let mut references = parent.references.clone();
+        references.push(parent.id.to_owned());
+        Self {
+            uri: parent.uri.to_owned(),
+            text: Some(text.to_owned()),
+            group: parent.group.to_owned(),
+            references: Some(references),
+            ..Default::default()
+        }
+    }
+}
+
+/// Copies text, tags, uri, group, target, references and permissions, for fetch-edit-update
+/// workflows
+///
+/// Left out are the document metadata, which the API only takes when creating an annotation, and
+/// any targets after the first: annotations made through the API have one, and an
+/// [`InputAnnotation`] can only have one.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::InputAnnotation;
+/// # let annotation: hypothesis::annotations::Annotation = serde_json::from_value(serde_json::json!({
+/// #     "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #     "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "old text",
+/// #     "tags": ["rust"], "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #     "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+/// # })).unwrap();
+/// let mut input = InputAnnotation::from(&annotation);
+/// input.text = Some("new text".into());
+/// assert_eq!(input.uri, "https://example.com");
+/// assert_eq!(input.tags, Some(vec!["rust".to_string()]));
+/// assert_eq!(input.permissions, Some(annotation.permissions.clone()));
+/// ```
+impl From<&Annotation> for InputAnnotation {
+    fn from(annotation: &Annotation) -> Self {
+        Self {
+            uri: annotation.uri.to_owned(),
+            text: Some(annotation.text.to_owned()),
+            tags: Some(annotation.tags.clone()),
+            group: annotation.group.to_owned(),
+            target: annotation.target.first().cloned().unwrap_or_default(),
+            references: Some(annotation.references.clone()),
+            permissions: Some(annotation.permissions.clone()),
+            ..Default::default()
+        }
+    }
 }
 
 impl InputAnnotationBuilder {
+    /// Annotate the document with DOI `doi`: adds it to the document metadata (see
+    /// [`Document::add_doi`]) and uses its "doi:" URI as `uri` unless that is set.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::InputAnnotation;
+    /// let annotation = InputAnnotation::builder()
+    ///     .doi("10.1000/xyz123")
+    ///     .text("Key result in table 2")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(annotation.uri, "doi:10.1000/xyz123");
+    /// assert_eq!(annotation.document.unwrap().highwire.unwrap().doi, vec!["10.1000/xyz123"]);
+    /// ```
+    pub fn doi(&mut self, doi: &str) -> &mut Self {
+        if self.uri.as_ref().is_none_or(String::is_empty) {
+            self.uri = Some(doi_uri(doi));
+        }
+        self.document
+            .get_or_insert(None)
+            .get_or_insert_with(Document::default)
+            .add_doi(doi);
+        self
+    }
+
+    /// Fill in the defaults of `template`: its group unless one is set, its tags in addition to
+    /// those set, and permissions private to `user` if the template asks for that and none are set.
+    ///
+    /// See [`AnnotationTemplate`](../template/struct.AnnotationTemplate.html) for an example.
+    pub fn apply_template(
+        &mut self,
+        template: &crate::template::AnnotationTemplate,
+        user: &UserAccountID,
+    ) -> &mut Self {
+        if let Some(group) = &template.group {
+            if self.group.as_ref().is_none_or(|group| group.is_empty()) {
+                self.group = Some(group.clone());
+            }
+        }
+        if !template.tags.is_empty() {
+            let tags = self.tags.get_or_insert(None).get_or_insert_with(Vec::new);
+            for tag in &template.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        if template.private && self.permissions.as_ref().is_none_or(Option::is_none) {
+            self.permissions = Some(Some(Permissions::private(user)));
+        }
+        self
+    }
+
     /// Builds a new `InputAnnotation`.
     pub fn build(&self) -> Result<InputAnnotation, errors::HypothesisError> {
-        self.builder()
-            .map_err(|e| errors::HypothesisError::BuilderError(e.to_string()))
+        let annotation = self
+            .builder()
+            .map_err(|e| errors::HypothesisError::BuilderError(e.to_string()))?;
+        annotation.validate()?;
+        Ok(annotation)
     }
 }
 
 impl Annotation {
+    /// True if only the creator can see this annotation
+    pub fn is_private(&self) -> bool {
+        self.permissions.is_private()
+    }
+
+    /// True if this annotation is visible to its group
+    pub fn is_shared(&self) -> bool {
+        self.permissions.is_shared()
+    }
+
+    /// Start of the highlighted text in the document, from its `TextPositionSelector`
+    pub fn position(&self) -> Option<u64> {
+        self.target
+            .iter()
+            .flat_map(|target| &target.selector)
+            .find_map(|selector| match selector {
+                Selector::TextPositionSelector(position) => Some(position.start),
+                _ => None,
+            })
+    }
+
+    /// The annotation's `html` link from the API, or its [`permalink`](crate::links::permalink)
+    pub fn permalink(&self) -> String {
+        self.links
+            .html
+            .clone()
+            .unwrap_or_else(|| crate::links::permalink(&self.id))
+    }
+
+    /// The annotation's `incontext` link from the API, or one made with
+    /// [`incontext`](crate::links::incontext)
+    pub fn incontext_link(&self) -> String {
+        self.links
+            .incontext
+            .clone()
+            .unwrap_or_else(|| crate::links::incontext(&self.uri, &self.id))
+    }
+
+    /// An [`InputAnnotation`] with the same text, tags, uri, group, target, references and
+    /// permissions (only the first target, and no document metadata)
+    pub fn to_input(&self) -> InputAnnotation {
+        InputAnnotation::from(self)
+    }
+
+    /// Applies the fields set in `annotation`. A set `target` replaces all targets.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::{InputAnnotation, Permissions};
+    /// use hypothesis::testing::fixtures;
+    /// let mut annotation = fixtures::annotation("a");
+    /// let private = Permissions::private(&fixtures::user());
+    /// annotation.update(InputAnnotation::builder().permissions(private.clone()).build().unwrap());
+    /// assert_eq!(annotation.permissions, private);
+    /// assert_eq!(annotation.text, fixtures::annotation("a").text);
+    /// ```
     pub fn update(&mut self, annotation: InputAnnotation) {
         if !annotation.uri.is_empty() {
             self.uri = annotation.uri;
         }
-        if !annotation.text.is_empty() {
-            self.text = annotation.text;
+        if let Some(text) = annotation.text {
+            self.text = text;
         }
         if let Some(tags) = annotation.tags {
             self.tags = tags;
@@ -121,10 +390,381 @@ impl Annotation {
         if !annotation.group.is_empty() {
             self.group = annotation.group;
         }
-        if annotation.references.is_empty() {
-            self.references = annotation.references;
+        if let Some(references) = annotation.references {
+            self.references = references;
+        }
+        if !is_default(&annotation.target) {
+            self.target = vec![annotation.target];
+        }
+        if let Some(document) = annotation.document {
+            self.document = Some(document);
+        }
+        if let Some(permissions) = annotation.permissions {
+            self.permissions = permissions;
+        }
+    }
+}
+
+/// Reads the `rows` of a search response, leaving out (and returning separately) those that
+/// aren't valid annotations, e.g. rows of deleted annotations missing most of their fields
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::read_rows;
+/// let rows = vec![
+///     serde_json::json!({
+///         "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+///         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "", "tags": [],
+///         "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+///         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+///     }),
+///     serde_json::json!({"id": "deleted", "uri": "https://example.com"}),
+/// ];
+/// let (annotations, skipped) = read_rows(rows);
+/// assert_eq!(annotations.len(), 1);
+/// assert_eq!(skipped[0].id.as_deref(), Some("deleted"));
+/// assert!(skipped[0].error.contains("missing field"));
+/// ```
+pub fn read_rows(rows: Vec<serde_json::Value>) -> (Vec<Annotation>, Vec<SkippedRow>) {
+    let mut annotations = Vec::with_capacity(rows.len());
+    let mut skipped = Vec::new();
+    for row in rows {
+        match Annotation::deserialize(&row) {
+            Ok(annotation) => annotations.push(annotation),
+            Err(error) => skipped.push(skipped_row(row, error)),
+        }
+    }
+    (annotations, skipped)
+}
+
+fn skipped_row(row: serde_json::Value, error: serde_json::Error) -> SkippedRow {
+    SkippedRow {
+        id: row.get("id").and_then(|id| id.as_str()).map(String::from),
+        error: error.to_string(),
+        row,
+    }
+}
+
+/// A page of search results
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchPage {
+    /// Number of annotations matching the query, on all pages
+    pub total: usize,
+    pub annotations: Vec<Annotation>,
+    /// Rows that aren't valid annotations, see [`read_rows`]
+    pub skipped: Vec<SkippedRow>,
+}
+
+/// Body of a search response, with the rows left unparsed in the body
+#[derive(Deserialize)]
+struct RawSearchPage<'a> {
+    #[serde(borrow)]
+    rows: Vec<&'a serde_json::value::RawValue>,
+    #[serde(default)]
+    total: usize,
+}
+
+impl SearchPage {
+    /// Reads the body of a search response row by row, deserializing each row straight into an
+    /// [`Annotation`]. Unlike reading the `rows` into `serde_json::Value`s first (as for
+    /// [`read_rows`]), no intermediate copy of the page is made, which keeps peak memory down
+    /// when many pages are fetched at once. Only skipped rows are read as `Value`s.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::SearchPage;
+    /// let body = r#"{"total": 12, "rows": [
+    ///     {"id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    ///      "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "", "tags": [],
+    ///      "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+    ///      "permissions": {"read": [], "delete": [], "admin": [], "update": []}},
+    ///     {"id": "deleted", "uri": "https://example.com"}
+    /// ]}"#;
+    /// let page = SearchPage::parse(body)?;
+    /// assert_eq!(page.total, 12);
+    /// assert_eq!(page.annotations[0].id, "a".into());
+    /// assert_eq!(page.skipped[0].id.as_deref(), Some("deleted"));
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, serde_json::Error> {
+        let page: RawSearchPage = serde_json::from_str(text)?;
+        let mut annotations = Vec::with_capacity(page.rows.len());
+        let mut skipped = Vec::new();
+        for row in page.rows {
+            match serde_json::from_str::<Annotation>(row.get()) {
+                Ok(annotation) => annotations.push(annotation),
+                Err(error) => skipped.push(skipped_row(serde_json::from_str(row.get())?, error)),
+            }
+        }
+        Ok(Self {
+            total: page.total,
+            annotations,
+            skipped,
+        })
+    }
+
+    /// Number of rows on the page, including skipped ones
+    pub fn rows(&self) -> usize {
+        self.annotations.len() + self.skipped.len()
+    }
+}
+
+/// A page of only valid annotations
+impl From<Vec<Annotation>> for SearchPage {
+    fn from(annotations: Vec<Annotation>) -> Self {
+        Self {
+            total: annotations.len(),
+            annotations,
+            skipped: Vec::new(),
+        }
+    }
+}
+
+/// Groups of annotations by the same user in the same group with the same uri, quoted text and
+/// text, e.g. from an import that ran twice. Each group is sorted oldest first and has at least
+/// two annotations.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::find_duplicates;
+/// use hypothesis::testing::fixtures;
+/// let annotation = |id: &str, created: &str, text: &str| {
+///     let mut annotation = fixtures::annotation(id);
+///     annotation.created = time::OffsetDateTime::parse(created, &time::format_description::well_known::Rfc3339).unwrap();
+///     annotation.text = text.into();
+///     annotation
+/// };
+/// let mut by_bob = annotation("d", "2021-01-01T00:00:00Z", "imported");
+/// by_bob.user = "acct:bob@hypothes.is".parse().unwrap();
+/// let mut elsewhere = annotation("e", "2021-01-01T00:00:00Z", "imported");
+/// elsewhere.group = "Qe7fpc5Z".into();
+/// let annotations = vec![
+///     annotation("b", "2021-02-01T00:00:00Z", "imported"),
+///     annotation("a", "2021-01-01T00:00:00Z", "imported"),
+///     annotation("c", "2021-01-01T00:00:00Z", "unique"),
+///     by_bob,
+///     elsewhere,
+/// ];
+/// let duplicates = find_duplicates(&annotations);
+/// assert_eq!(duplicates.len(), 1);
+/// let ids: Vec<_> = duplicates[0].iter().map(|a| a.id.0.as_str()).collect();
+/// assert_eq!(ids, ["a", "b"]);
+/// ```
+pub fn find_duplicates(annotations: &[Annotation]) -> Vec<Vec<&Annotation>> {
+    let mut groups: Vec<Vec<&Annotation>> = Vec::new();
+    let mut index: HashMap<(&str, &str, &str, Option<&str>, &str), usize> = HashMap::new();
+    for annotation in annotations {
+        // other people's annotations, or copies in other groups, are never duplicates
+        let key = (
+            annotation.user.0.as_str(),
+            annotation.group.0.as_str(),
+            annotation.uri.as_str(),
+            crate::toc::quote(annotation),
+            annotation.text.as_str(),
+        );
+        match index.get(&key) {
+            Some(&i) => groups[i].push(annotation),
+            None => {
+                index.insert(key, groups.len());
+                groups.push(vec![annotation]);
+            }
         }
     }
+    groups.retain(|group| group.len() > 1);
+    for group in &mut groups {
+        group.sort_by_key(|annotation| annotation.created);
+    }
+    groups
+}
+
+/// Sorts annotations in document order, by the start of their [`Annotation::position`].
+/// Annotations without a position (e.g. page notes) come last, oldest first.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::sort_by_position;
+/// # fn annotation(id: &str, created: &str, start: Option<u64>) -> hypothesis::annotations::Annotation {
+/// #     let selector = match start {
+/// #         Some(start) => serde_json::json!([{"type": "TextPositionSelector", "start": start, "end": start + 10}]),
+/// #         None => serde_json::json!([]),
+/// #     };
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": created, "updated": created,
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "",
+/// #         "tags": [], "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "target": [{"source": "https://example.com", "selector": selector}],
+/// #     })).unwrap()
+/// # }
+/// let mut annotations = vec![
+///     annotation("note", "2021-01-01T00:00:00Z", None),
+///     annotation("end", "2021-01-02T00:00:00Z", Some(900)),
+///     annotation("start", "2021-01-03T00:00:00Z", Some(12)),
+/// ];
+/// sort_by_position(&mut annotations);
+/// let ids: Vec<_> = annotations.iter().map(|a| a.id.to_string()).collect();
+/// assert_eq!(ids, ["start", "end", "note"]);
+/// ```
+pub fn sort_by_position(annotations: &mut [Annotation]) {
+    annotations.sort_by_key(|annotation| {
+        let position = annotation.position();
+        (position.is_none(), position, annotation.created)
+    });
+}
+
+/// Annotations on one document, see [`group_by_document`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DocumentGroup {
+    /// URI of the first annotation on the document
+    pub uri: String,
+    /// Most common title in the annotations' document metadata (falling back to e.g. Open Graph
+    /// titles), or `uri` if they have none
+    pub title: String,
+    /// In document order, see [`sort_by_position`]
+    pub annotations: Vec<Annotation>,
+}
+
+/// Annotations by document, with URIs of the same document (see
+/// [`crate::uri::equivalent`]) in one group, in order of first appearance
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::group_by_document;
+/// # fn annotation(id: &str, uri: &str, title: &str, start: u64) -> hypothesis::annotations::Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": uri, "text": "", "tags": [],
+/// #         "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "document": {"title": [title]},
+/// #         "target": [{"source": uri, "selector": [
+/// #             {"type": "TextPositionSelector", "start": start, "end": start + 10}]}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("a", "https://example.com/post?utm_source=feed", "A post", 500),
+///     annotation("b", "https://example.org/", "Elsewhere", 0),
+///     annotation("c", "http://example.com/post/", "A post", 20),
+/// ];
+/// let documents = group_by_document(&annotations);
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents[0].title, "A post");
+/// let ids: Vec<_> = documents[0].annotations.iter().map(|a| a.id.to_string()).collect();
+/// assert_eq!(ids, ["c", "a"]);
+/// ```
+pub fn group_by_document(annotations: &[Annotation]) -> Vec<DocumentGroup> {
+    let mut groups: Vec<DocumentGroup> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for annotation in annotations {
+        let key = crate::uri::normalize_uri(&annotation.uri);
+        let i = *index.entry(key).or_insert_with(|| {
+            groups.push(DocumentGroup {
+                uri: annotation.uri.to_owned(),
+                title: String::new(),
+                annotations: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[i].annotations.push(annotation.clone());
+    }
+    for group in &mut groups {
+        group.title = best_title(&group.annotations).unwrap_or_else(|| group.uri.clone());
+        sort_by_position(&mut group.annotations);
+    }
+    groups
+}
+
+/// The most common non-blank title, the first one seen on ties
+fn best_title(annotations: &[Annotation]) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    let titles = annotations
+        .iter()
+        .filter_map(|a| {
+            let document = a.document.as_ref()?;
+            // e.g. {"facebook": {"title": ["..."]}} from Open Graph tags
+            document.main_title().or_else(|| {
+                document
+                    .metadata
+                    .values()
+                    .find_map(|meta| meta.get("title")?.get(0)?.as_str())
+            })
+        })
+        .map(str::trim)
+        .filter(|title| !title.is_empty());
+    for title in titles {
+        match counts.iter_mut().find(|(t, _)| *t == title) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((title, 1)),
+        }
+    }
+    let max = counts.iter().map(|(_, count)| *count).max()?;
+    counts
+        .into_iter()
+        .find(|(_, count)| *count == max)
+        .map(|(title, _)| title.to_owned())
+}
+
+/// Reads annotation IDs to act on, e.g. from stdin in a pipeline, given
+/// - one per line,
+/// - as a JSON array of IDs or of annotations,
+/// - or as JSON lines of annotations, like a search written in the `jsonl`
+///   [output format](../output/enum.OutputFormat.html).
+///
+/// Blank lines are skipped.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::read_annotation_ids;
+/// let ids = read_annotation_ids("abc\n\n def \n".as_bytes()).unwrap();
+/// assert_eq!(ids, vec!["abc".into(), "def".into()]);
+/// let ids = read_annotation_ids(r#"["abc", {"id": "def", "text": "..."}]"#.as_bytes()).unwrap();
+/// assert_eq!(ids, vec!["abc".into(), "def".into()]);
+/// let ids = read_annotation_ids("{\"id\": \"abc\"}\n{\"id\": \"def\"}\n".as_bytes()).unwrap();
+/// assert_eq!(ids, vec!["abc".into(), "def".into()]);
+/// ```
+pub fn read_annotation_ids(
+    mut reader: impl std::io::Read,
+) -> Result<Vec<AnnotationID>, errors::HypothesisError> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Id(AnnotationID),
+        Annotation { id: AnnotationID },
+    }
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    let entries: Vec<Entry> = if input.trim_start().starts_with('[') {
+        serde_json::from_str(&input)?
+    } else {
+        input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                if line.starts_with('{') {
+                    serde_json::from_str(line)
+                } else {
+                    Ok(Entry::Id(line.into()))
+                }
+            })
+            .collect::<Result<_, _>>()?
+    };
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Id(id) | Entry::Annotation { id } => id,
+        })
+        .collect())
+}
+
+/// What [`Hypothesis::dedupe`](../struct.Hypothesis.html#method.dedupe) does with duplicates.
+/// The oldest annotation of each group is always kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeStrategy {
+    /// Delete the newer duplicates
+    Delete,
+    /// Add the tags of the newer duplicates to the oldest one, then delete them
+    Merge,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Builder)]
@@ -140,12 +780,119 @@ pub struct Document {
     pub highwire: Option<HighWire>,
     #[serde(skip_serializing_if = "is_default", default)]
     pub link: Vec<Link>,
+    /// URL of the page's icon
+    #[serde(skip_serializing_if = "is_default", default)]
+    #[builder(setter(strip_option), default)]
+    pub favicon: Option<String>,
+    /// Any other metadata of the page, e.g. "facebook", "twitter" or "prism"
+    #[serde(flatten, default)]
+    #[builder(default)]
+    pub metadata: HashMap<String, serde_json::Value>,
 }
 
 impl Document {
     pub fn builder() -> DocumentBuilder {
         DocumentBuilder::default()
     }
+
+    /// The first of the page's titles
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::Document;
+    /// let document: Document = serde_json::from_str(r#"{
+    ///     "title": ["Example Domain"],
+    ///     "favicon": "https://example.com/favicon.ico",
+    ///     "twitter": {"card": ["summary"]}
+    /// }"#).unwrap();
+    /// assert_eq!(document.main_title(), Some("Example Domain"));
+    /// assert_eq!(document.favicon.as_deref(), Some("https://example.com/favicon.ico"));
+    /// assert!(document.metadata.contains_key("twitter"));
+    /// ```
+    pub fn main_title(&self) -> Option<&str> {
+        self.title.first().map(String::as_str)
+    }
+
+    /// Metadata of the document with DOI `doi`, see [`Document::add_doi`]
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::Document;
+    /// let document = Document::from_doi("https://doi.org/10.1000/xyz123");
+    /// assert_eq!(document.dc.unwrap().identifier, vec!["doi:10.1000/xyz123"]);
+    /// assert_eq!(document.highwire.unwrap().doi, vec!["10.1000/xyz123"]);
+    /// assert_eq!(document.link[0].href, "doi:10.1000/xyz123");
+    /// ```
+    pub fn from_doi(doi: &str) -> Self {
+        let mut document = Self::default();
+        document.add_doi(doi);
+        document
+    }
+
+    /// Records `doi` (bare like "10.1000/xyz123", or as a "doi:" URI or doi.org URL) the way
+    /// the Hypothesis client does: as "doi:..." in `dc.identifier` and `link`, and bare in
+    /// `highwire.doi`. DOIs already present aren't added again.
+    pub fn add_doi(&mut self, doi: &str) {
+        let doi = bare_doi(doi);
+        let uri = doi_uri(doi);
+        let identifiers = &mut self.dc.get_or_insert_with(Dc::default).identifier;
+        if !identifiers.contains(&uri) {
+            identifiers.push(uri.clone());
+        }
+        let dois = &mut self.highwire.get_or_insert_with(HighWire::default).doi;
+        if !dois.iter().any(|d| d == doi) {
+            dois.push(doi.to_owned());
+        }
+        if !self.link.iter().any(|link| link.href == uri) {
+            self.link.push(Link {
+                href: uri,
+                link_type: String::new(),
+            });
+        }
+    }
+
+    /// The first DOI of the document (bare, like "10.1000/xyz123"), from `highwire.doi`,
+    /// "doi:" identifiers in `dc.identifier` or "doi:" links
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::Document;
+    /// assert_eq!(Document::from_doi("doi:10.1000/xyz123").doi(), Some("10.1000/xyz123"));
+    /// assert_eq!(Document::default().doi(), None);
+    /// ```
+    pub fn doi(&self) -> Option<&str> {
+        let highwire = self.highwire.iter().flat_map(|highwire| &highwire.doi);
+        let identifiers = self.dc.iter().flat_map(|dc| &dc.identifier);
+        let links = self.link.iter().map(|link| &link.href);
+        highwire
+            .map(|doi| bare_doi(doi))
+            .chain(
+                identifiers
+                    .chain(links)
+                    .filter_map(|uri| uri.trim().strip_prefix("doi:")),
+            )
+            .find(|doi| !doi.is_empty())
+    }
+}
+
+/// `doi` without a "doi:" or doi.org prefix
+fn bare_doi(doi: &str) -> &str {
+    let doi = doi.trim();
+    [
+        "doi:",
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+    ]
+    .iter()
+    .find_map(|prefix| doi.strip_prefix(prefix))
+    .unwrap_or(doi)
+}
+
+/// The "doi:" URI of a DOI, e.g. "doi:10.1000/xyz123" for "10.1000/xyz123"
+pub fn doi_uri(doi: &str) -> String {
+    format!("doi:{}", bare_doi(doi))
 }
 
 impl DocumentBuilder {
@@ -178,10 +925,29 @@ pub struct Dc {
 }
 
 /// Full representation of an Annotation resource and applicable relationships.
+///
+/// Fields the API adds that aren't known here are kept in `extra`, so annotations round-trip
+/// through serialization unchanged.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// let json = serde_json::json!({
+///     "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+///     "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "", "tags": [],
+///     "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+///     "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+///     "references": [], "document": {}, "user_info": {"display_name": null},
+///     "mentions": [{"userid": "acct:bob@hypothes.is"}],
+/// });
+/// let annotation: Annotation = serde_json::from_value(json.clone()).unwrap();
+/// assert_eq!(annotation.extra["mentions"][0]["userid"], "acct:bob@hypothes.is");
+/// assert_eq!(serde_json::to_value(&annotation).unwrap(), json);
+/// ```
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Annotation {
     /// Annotation ID
-    pub id: String,
+    pub id: AnnotationID,
     /// Date of creation
     #[serde(with = "time::serde::rfc3339")]
     pub created: OffsetDateTime,
@@ -197,12 +963,12 @@ pub struct Annotation {
     /// Tags attached to annotation
     pub tags: Vec<String>,
     /// The unique identifier for the annotation's group
-    pub group: String,
+    pub group: GroupID,
     pub permissions: Permissions,
     /// Which part of the document does the annotation target.
     pub target: Vec<Target>,
     /// An object containing hypermedia links for this annotation
-    pub links: HashMap<String, String>,
+    pub links: AnnotationLinks,
     /// Whether this annotation is hidden from public view
     pub hidden: bool,
     /// Whether this annotation has one or more flags for moderation
@@ -212,19 +978,84 @@ pub struct Annotation {
     pub document: Option<Document>,
     /// Annotation IDs for any annotations this annotation references (e.g. is a reply to)
     #[serde(default)]
-    pub references: Vec<String>,
+    pub references: Vec<AnnotationID>,
     #[serde(default)]
     pub user_info: Option<UserInfo>,
+    /// Only included for moderators of the annotation's group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<Moderation>,
+    /// Any other fields of the API's response, kept so that serializing the annotation again
+    /// loses nothing
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// Hypermedia links of an annotation
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::AnnotationLinks;
+/// let links: AnnotationLinks = serde_json::from_value(serde_json::json!({
+///     "html": "https://hypothes.is/a/NkyAIJ4gEeqHTQ",
+///     "incontext": "https://hyp.is/NkyAIJ4gEeqHTQ/example.com/post",
+///     "json": "https://hypothes.is/api/annotations/NkyAIJ4gEeqHTQ",
+///     "oembed": "https://hypothes.is/api/oembed?url=...",
+/// })).unwrap();
+/// assert_eq!(links.incontext.as_deref(), Some("https://hyp.is/NkyAIJ4gEeqHTQ/example.com/post"));
+/// assert_eq!(links.get("oembed"), Some("https://hypothes.is/api/oembed?url=..."));
+/// assert_eq!(serde_json::to_value(&links).unwrap()["oembed"], "https://hypothes.is/api/oembed?url=...");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnnotationLinks {
+    /// Page of the annotation on hypothes.is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// Link that opens the annotation on its document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incontext: Option<String>,
+    /// The annotation in the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<String>,
+    /// Any other links, by name
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
+}
+
+impl AnnotationLinks {
+    /// The link called `name`, whether it has a field or not
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "html" => self.html.as_deref(),
+            "incontext" => self.incontext.as_deref(),
+            "json" => self.json.as_deref(),
+            name => self.other.get(name).map(String::as_str),
+        }
+    }
+}
+
+/// Moderation status of an annotation
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Moderation {
+    /// How many users flagged the annotation
+    #[serde(rename = "flagCount", default)]
+    pub flag_count: u64,
+    /// Any other fields
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct UserInfo {
     /// The annotation creator's display name
     pub display_name: Option<String>,
+    /// Any other fields
+    #[serde(flatten, default)]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 /// > While the API accepts arbitrary Annotation selectors in the target.selector property,
 /// > the Hypothesis client currently supports TextQuoteSelector, RangeSelector and TextPositionSelector selector.
+///
 /// [Hypothesis API v1.0.0](https://h.readthedocs.io/en/latest/api-reference/v1/#tag/annotations/paths/~1annotations/post)
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Builder)]
 #[builder(build_fn(name = "builder"))]
@@ -256,6 +1087,7 @@ impl TargetBuilder {
 /// > Many Annotations refer to part of a resource, rather than all of it, as the Target.
 /// > We call that part of the resource a Segment (of Interest). A Selector is used to describe how
 /// > to determine the Segment from within the Source resource.
+///
 /// [Web Annotation Data Model - Selectors](https://www.w3.org/TR/annotation-model/#selectors)
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
@@ -268,16 +1100,36 @@ pub enum Selector {
     /// > using the most appropriate selection mechanisms, and then linked together to form the selection.
     /// > The selection consists of everything from the beginning of the starting selector through to the
     /// > beginning of the ending selector, but not including it.
+    ///
     /// [Web Annotation Data Model - Range Selector](https://www.w3.org/TR/annotation-model/#range-selector)
+    ///
     /// NOTE - the Hypothesis API doesn't seem to follow this standard for RangeSelector so this just returns a HashMap for now
     TextPositionSelector(TextPositionSelector),
     /// TODO: make Selectors into structs
     RangeSelector(HashMap<String, serde_json::Value>),
     FragmentSelector(HashMap<String, serde_json::Value>),
-    CssSelector(HashMap<String, serde_json::Value>),
+    CssSelector(CssSelector),
+    PageSelector(PageSelector),
     XPathSelector(HashMap<String, serde_json::Value>),
     DataPositionSelector(HashMap<String, serde_json::Value>),
     SvgSelector(HashMap<String, serde_json::Value>),
+    /// A selector of any other type (or of a known type that doesn't parse), e.g. from a newer
+    /// client, kept as it is
+    ///
+    /// ```
+    /// use hypothesis::annotations::Selector;
+    /// let json = r#"{"type":"MediaTimeSelector","start":12.5}"#;
+    /// let selector: Selector = serde_json::from_str(json).unwrap();
+    /// assert!(matches!(&selector, Selector::Other { type_name, .. } if type_name == "MediaTimeSelector"));
+    /// assert_eq!(serde_json::to_string(&selector).unwrap(), json);
+    /// ```
+    #[serde(untagged)]
+    Other {
+        #[serde(rename = "type")]
+        type_name: String,
+        #[serde(flatten)]
+        data: HashMap<String, serde_json::Value>,
+    },
 }
 
 impl Selector {
@@ -296,6 +1148,7 @@ impl Selector {
 ///
 /// > For example, if the document were again "abcdefghijklmnopqrstuvwxyz", one could select
 /// > "efg" by a prefix of "abcd", the match of "efg" and a suffix of "hijk".
+///
 /// [Web Annotation Data Model - Text Quote Selector](https://www.w3.org/TR/annotation-model/#text-quote-selector)
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TextQuoteSelector {
@@ -307,6 +1160,54 @@ pub struct TextQuoteSelector {
     pub suffix: String,
 }
 
+/// > CSS Selectors allow for a wide variety of well supported ways to describe the path to an
+/// > element in a web page.
+///
+/// [Web Annotation Data Model - CSS Selector](https://www.w3.org/TR/annotation-model/#css-selector)
+///
+/// ```
+/// use hypothesis::annotations::{CssSelector, Selector};
+/// let selector: Selector = serde_json::from_str(r##"{"type": "CssSelector", "value": "#intro > p"}"##).unwrap();
+/// assert_eq!(selector, Selector::CssSelector(CssSelector { value: "#intro > p".into(), refined_by: None }));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CssSelector {
+    /// The CSS selection path to the segment
+    pub value: String,
+    /// A selector within the selected element
+    #[serde(rename = "refinedBy", default, skip_serializing_if = "Option::is_none")]
+    pub refined_by: Option<Box<Selector>>,
+}
+
+/// Page of a PDF, added by the Hypothesis client to annotations made in its PDF viewer
+///
+/// ```
+/// use hypothesis::annotations::{PageSelector, Selector};
+/// let json = r#"{"type":"PageSelector","index":11,"label":"xii"}"#;
+/// let selector: Selector = serde_json::from_str(json).unwrap();
+/// let page = PageSelector { index: 11, label: Some("xii".into()) };
+/// assert_eq!(page.page_number(), "xii");
+/// assert_eq!(selector, Selector::PageSelector(page));
+/// assert_eq!(serde_json::to_string(&selector).unwrap(), json);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PageSelector {
+    /// Index of the page, starting at 0
+    pub index: u64,
+    /// The page's label as shown in the viewer, e.g. "xii" for front matter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl PageSelector {
+    /// The label, or else the 1-based page number
+    pub fn page_number(&self) -> String {
+        self.label
+            .clone()
+            .unwrap_or_else(|| (self.index + 1).to_string())
+    }
+}
+
 /// >  This Selector describes a range of text by recording the start and end positions of the
 /// > selection in the stream. Position 0 would be immediately before the first character, position
 /// > 1 would be immediately before the second character, and so on. The start character is thus
@@ -314,6 +1215,7 @@ pub struct TextQuoteSelector {
 ///
 /// > For example, if the document was "abcdefghijklmnopqrstuvwxyz", the start was 4, and the end
 /// > was 7, then the selection would be "efg".
+///
 /// [Web Annotation Data Model - Text Position Selector](https://www.w3.org/TR/annotation-model/#text-position-selector)
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TextPositionSelector {
@@ -324,44 +1226,49 @@ pub struct TextPositionSelector {
     pub end: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Sort {
     Created,
+    #[default]
     Updated,
     Id,
     Group,
     User,
 }
 
-impl Default for Sort {
-    fn default() -> Self {
-        Self::Updated
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Order {
     Asc,
+    #[default]
     Desc,
 }
 
-impl Default for Order {
-    fn default() -> Self {
-        Self::Desc
-    }
-}
-
 /// Options to filter and sort search results. See [the Hypothesis API docs](https://h.readthedocs.io/en/latest/api-reference/v1/#tag/annotations/paths/~1search/get) for more details on using these fields
+///
+/// Queries can be loaded from configuration files, fields that are left out take the defaults
+/// of [`SearchQuery::builder`].
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::SearchQuery;
+/// let query: SearchQuery = serde_json::from_str(r#"{"tags": ["rust"], "uri.parts": "rust-lang"}"#).unwrap();
+/// assert_eq!(query.limit, 20);
+/// assert_eq!(query, SearchQuery::builder().tags(vec!["rust".into()]).uri_parts("rust-lang").build().unwrap());
+/// let json = serde_json::to_string(&query).unwrap();
+/// assert_eq!(serde_json::from_str::<SearchQuery>(&json).unwrap(), query);
+/// ```
 #[cfg_attr(feature = "cli", derive(StructOpt))]
-#[derive(Serialize, Debug, Clone, PartialEq, Builder, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Builder, Default)]
+#[serde(default)]
 #[builder(build_fn(name = "builder"), default)]
 pub struct SearchQuery {
     /// The maximum number of annotations to return.
     ///
     /// Default: 20. Range: [ 0 .. 200 ]
     #[builder(default = "20")]
+    #[serde(default = "default_limit")]
     #[cfg_attr(feature = "cli", structopt(default_value = "20", long))]
     pub limit: u8,
     /// The field by which annotations should be sorted
@@ -419,7 +1326,7 @@ pub struct SearchQuery {
     #[serde(skip_serializing_if = "is_default")]
     #[cfg_attr(feature = "cli", structopt(default_value, long))]
     #[builder(setter(into))]
-    pub group: String,
+    pub group: GroupID,
     /// Limit the results to annotations tagged with the specified value.
     #[serde(skip_serializing_if = "is_default")]
     #[cfg_attr(feature = "cli", structopt(default_value, long))]
@@ -444,7 +1351,7 @@ pub struct SearchQuery {
     #[serde(skip_serializing_if = "is_default")]
     #[cfg_attr(feature = "cli", structopt(default_value, long))]
     #[builder(setter(into))]
-    pub references: String,
+    pub references: AnnotationID,
     /// Limit the results to annotations that contain this text in their textual body.
     #[serde(skip_serializing_if = "is_default")]
     #[cfg_attr(feature = "cli", structopt(default_value, long))]
@@ -452,10 +1359,183 @@ pub struct SearchQuery {
     pub text: String,
 }
 
+fn default_limit() -> u8 {
+    20
+}
+
 impl SearchQuery {
     pub fn builder() -> SearchQueryBuilder {
         SearchQueryBuilder::default()
     }
+
+    /// Parses a query written as in the hypothes.is search bar: `tag:`, `group:`, `user:`,
+    /// `uri:` (or `url:`), `text:` and `quote:` filters, `since:` a date (YYYY-MM-DD or RFC 3339),
+    /// and any other words or 'quoted phrases' matching anywhere.
+    /// Values with spaces can be quoted, e.g. `tag:"machine learning"`.
+    ///
+    /// Users can be given by username (of the hypothes.is authority) or as "acct:..." IDs.
+    /// Use [`SearchQuery::parse_as`] to allow `user:me`.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::{Order, SearchQuery};
+    /// let query = SearchQuery::parse("tag:rust group:abc 'exact phrase' user:alice since:2023-01-01").unwrap();
+    /// assert_eq!(query.tags, vec!["rust"]);
+    /// assert_eq!(&*query.group, "abc");
+    /// assert_eq!(query.any, "exact phrase");
+    /// assert_eq!(query.user, "acct:alice@hypothes.is");
+    /// assert_eq!(query.search_after, "2023-01-01T00:00:00Z");
+    /// assert_eq!(query.order, Order::Asc);
+    /// assert!(SearchQuery::parse("tag:'unterminated").is_err());
+    /// ```
+    pub fn parse(input: &str) -> Result<Self, errors::HypothesisError> {
+        Self::parse_with(input, None)
+    }
+
+    /// Like [`SearchQuery::parse`], with `user:me` meaning `me`, and usernames of the same authority
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::UserAccountID;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let me = UserAccountID::new("bot", "lms.example.com");
+    /// assert_eq!(SearchQuery::parse_as("user:me", &me).unwrap().user, "acct:bot@lms.example.com");
+    /// assert_eq!(SearchQuery::parse_as("user:bob", &me).unwrap().user, "acct:bob@lms.example.com");
+    /// ```
+    pub fn parse_as(input: &str, me: &UserAccountID) -> Result<Self, errors::HypothesisError> {
+        Self::parse_with(input, Some(me))
+    }
+
+    fn parse_with(
+        input: &str,
+        me: Option<&UserAccountID>,
+    ) -> Result<Self, errors::HypothesisError> {
+        let invalid = |message: String| errors::HypothesisError::QueryError(message);
+        let mut query = Self::builder().build()?;
+        let mut any = Vec::new();
+        for term in search_terms(input)? {
+            let Some((key, value)) = term.split_once(':').filter(|(_, value)| !value.is_empty())
+            else {
+                any.push(term);
+                continue;
+            };
+            let value = unquote(value);
+            match key {
+                "tag" => query.tags.push(value.to_owned()),
+                "group" => query.group = value.into(),
+                "uri" | "url" => query.uri = value.to_owned(),
+                "text" => query.text = value.to_owned(),
+                "quote" => query.quote = value.to_owned(),
+                "user" => {
+                    query.user = match (value, me) {
+                        ("me", Some(me)) => me.0.to_owned(),
+                        ("me", None) => {
+                            return Err(invalid(
+                                "user:me needs the current user, see SearchQuery::parse_as".into(),
+                            ))
+                        }
+                        (user, _) if user.starts_with("acct:") => user.to_owned(),
+                        (user, me) => {
+                            UserAccountID::new(
+                                user,
+                                me.map_or(crate::DEFAULT_AUTHORITY, |me| me.authority()),
+                            )
+                            .0
+                        }
+                    }
+                }
+                "since" => {
+                    let since = if value.len() == 10 {
+                        format!("{}T00:00:00Z", value)
+                    } else {
+                        value.to_owned()
+                    };
+                    OffsetDateTime::parse(&since, &time::format_description::well_known::Rfc3339)
+                        .map_err(|_| invalid(format!("since:{} is not a date", value)))?;
+                    query.search_after = since;
+                    query.sort = Sort::Updated;
+                    query.order = Order::Asc;
+                }
+                // unknown prefixes are searched for like the hypothes.is search bar does
+                _ => any.push(term),
+            }
+        }
+        query.any = any.into_iter().map(unquote).collect::<Vec<_>>().join(" ");
+        Ok(query)
+    }
+
+    /// The filters set in this query as "field=value" pairs (without paging and sorting), e.g. for logs
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::SearchQuery;
+    /// let query = SearchQuery::builder().group("abc").tag("rust").limit(50).build().unwrap();
+    /// assert_eq!(query.summary(), "group=abc tag=rust");
+    /// ```
+    pub fn summary(&self) -> String {
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) else {
+            return String::new();
+        };
+        fields
+            .into_iter()
+            .filter(|(field, value)| {
+                !matches!(
+                    field.as_str(),
+                    "limit" | "sort" | "search_after" | "offset" | "order"
+                ) && !value.is_null()
+                    && value != ""
+                    && value.as_array().is_none_or(|values| !values.is_empty())
+            })
+            .map(|(field, value)| match value {
+                serde_json::Value::String(value) => format!("{}={}", field, value),
+                value => format!("{}={}", field, value),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Splits `input` at whitespace outside of single or double quotes
+pub(crate) fn search_terms(input: &str) -> Result<Vec<&str>, errors::HypothesisError> {
+    let mut terms = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                start.get_or_insert(i);
+            }
+            (None, c) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    terms.push(&input[start..i]);
+                }
+            }
+            (None, _) => {
+                start.get_or_insert(i);
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(errors::HypothesisError::QueryError(format!(
+            "unterminated quote in {:?}",
+            input
+        )));
+    }
+    if let Some(start) = start {
+        terms.push(&input[start..]);
+    }
+    Ok(terms)
+}
+
+/// `value` without surrounding quotes
+pub(crate) fn unquote(value: &str) -> &str {
+    ['\'', '"']
+        .iter()
+        .find_map(|&q| value.strip_prefix(q)?.strip_suffix(q))
+        .unwrap_or(value)
 }
 
 impl SearchQueryBuilder {
@@ -466,10 +1546,358 @@ impl SearchQueryBuilder {
     }
 }
 
+/// Page size tuning for
+/// [`Hypothesis::search_annotations_adaptive`](../struct.Hypothesis.html#method.search_annotations_adaptive):
+/// pages are made smaller after slow or timed out requests and larger after fast ones
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AdaptivePaging {
+    /// Smallest page size, requests timing out at this size fail
+    pub min_limit: u8,
+    /// Largest page size, at most 200
+    pub max_limit: u8,
+    /// Pages taking longer than this are made smaller, pages taking less than half of it larger
+    pub target_latency: Duration,
+    /// Requests taking longer are abandoned and retried with a smaller page
+    pub timeout: Duration,
+}
+
+impl Default for AdaptivePaging {
+    fn default() -> Self {
+        Self {
+            min_limit: 10,
+            max_limit: 200,
+            target_latency: Duration::from_secs(2),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl AdaptivePaging {
+    /// Page size to use after a page of size `limit` took `latency` (None if it timed out)
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use hypothesis::annotations::AdaptivePaging;
+    /// let paging = AdaptivePaging::default();
+    /// assert_eq!(paging.next_limit(50, None), 25);
+    /// assert_eq!(paging.next_limit(50, Some(Duration::from_secs(3))), 25);
+    /// assert_eq!(paging.next_limit(50, Some(Duration::from_millis(500))), 100);
+    /// assert_eq!(paging.next_limit(150, Some(Duration::from_millis(500))), 200);
+    /// assert_eq!(paging.next_limit(50, Some(Duration::from_millis(1500))), 50);
+    /// ```
+    pub fn next_limit(&self, limit: u8, latency: Option<Duration>) -> u8 {
+        let max_limit = self.max_limit.min(200);
+        let limit = match latency {
+            Some(latency) if latency < self.target_latency / 2 => limit.saturating_mul(2),
+            Some(latency) if latency <= self.target_latency => limit,
+            _ => limit / 2,
+        };
+        limit.clamp(self.min_limit.min(max_limit).max(1), max_limit)
+    }
+}
+
+/// An annotation returned by a [`PageCursor`], with its time in the sort order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Returned {
+    id: AnnotationID,
+    #[serde(with = "time::serde::rfc3339")]
+    at: OffsetDateTime,
+}
+
+/// Moves a search query's `search_after` from page to page without skipping or repeating
+/// annotations that share a timestamp.
+///
+/// Setting `search_after` to the time of the last annotation of a page skips the annotations
+/// at the same time that didn't fit on the page. The cursor instead steps back a millisecond
+/// from that time (forward, in descending order), so that the next page overlaps the previous
+/// one, and leaves out the annotations it already returned. If more annotations share a time
+/// than fit on a page, it pages through them with `offset`.
+///
+/// Works with queries sorted by `updated` or `created`.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::{Annotation, Order, PageCursor, SearchQuery};
+/// use time::format_description::well_known::Rfc3339;
+/// use time::OffsetDateTime;
+/// # fn annotation(id: usize, updated: &str) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id.to_string(), "created": updated, "updated": updated,
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "", "tags": [],
+/// #         "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #     })).unwrap()
+/// # }
+/// // 12 annotations, of which 5 were updated at the same time
+/// let times = [1, 2, 3, 3, 3, 3, 3, 4, 5, 5, 6, 7];
+/// let all: Vec<Annotation> = times
+///     .iter()
+///     .enumerate()
+///     .map(|(id, second)| annotation(id, &format!("2023-01-01T00:00:0{}Z", second)))
+///     .collect();
+/// // what the search API returns: sorted by update, strictly after `search_after`
+/// let search = |query: &SearchQuery| -> Vec<Annotation> {
+///     let after = OffsetDateTime::parse(&query.search_after, &Rfc3339).ok();
+///     let mut sorted = all.clone();
+///     if query.order == Order::Desc {
+///         sorted.reverse();
+///     }
+///     sorted
+///         .into_iter()
+///         .filter(|a| match (after, &query.order) {
+///             (None, _) => true,
+///             (Some(after), Order::Asc) => a.updated > after,
+///             (Some(after), Order::Desc) => a.updated < after,
+///         })
+///         .skip(query.offset)
+///         .take(usize::from(query.limit))
+///         .collect()
+/// };
+/// for order in [Order::Asc, Order::Desc] {
+///     let mut query = SearchQuery::builder().limit(3).order(order.clone()).build()?;
+///     let mut cursor = PageCursor::default();
+///     let mut ids = Vec::new();
+///     loop {
+///         let page = search(&query);
+///         match cursor.advance(&mut query, page.into())? {
+///             Some(page) => ids.extend(page.into_iter().map(|a| a.id.0)),
+///             None => break,
+///         }
+///     }
+///     let mut expected: Vec<String> = (0..12).map(|id| id.to_string()).collect();
+///     if order == Order::Desc {
+///         expected.reverse();
+///     }
+///     assert_eq!(ids, expected);
+/// }
+/// # Ok::<(), hypothesis::errors::HypothesisError>(())
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PageCursor {
+    /// Returned annotations that the next page can overlap with
+    recent: Vec<Returned>,
+}
+
+impl PageCursor {
+    /// Takes `page`, the results of `query`, and moves `query` on to the next page.
+    ///
+    /// Returns the annotations of the page not returned before, or None once there are no more
+    /// results. Skipped rows count towards the page, so a page of only rows that aren't valid
+    /// annotations is paged past rather than taken for the end of the results.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::{PageCursor, SearchPage, SearchQuery};
+    /// use hypothesis::hooks::SkippedRow;
+    /// let mut query = SearchQuery::builder().limit(2).build()?;
+    /// let invalid = || SkippedRow {
+    ///     id: None,
+    ///     row: serde_json::json!({}),
+    ///     error: "missing field `id`".into(),
+    /// };
+    /// let page = SearchPage {
+    ///     total: 3,
+    ///     annotations: Vec::new(),
+    ///     skipped: vec![invalid(), invalid()],
+    /// };
+    /// let mut cursor = PageCursor::default();
+    /// assert_eq!(cursor.advance(&mut query, page)?, Some(Vec::new()));
+    /// assert_eq!(query.offset, 2);
+    /// assert_eq!(cursor.advance(&mut query, SearchPage::from(Vec::new()))?, None);
+    /// # Ok::<(), hypothesis::errors::HypothesisError>(())
+    /// ```
+    pub fn advance(
+        &mut self,
+        query: &mut SearchQuery,
+        page: SearchPage,
+    ) -> Result<Option<Vec<Annotation>>, errors::HypothesisError> {
+        let fetched = page.rows();
+        if fetched == 0 {
+            return Ok(None);
+        }
+        let full = fetched >= usize::from(query.limit.max(1));
+        let new = self.unseen(page.annotations);
+        if new.is_empty() {
+            if !full {
+                return Ok(None);
+            }
+            // a page of only annotations returned before or skipped rows: more share a time than
+            // fit on a page, or the rows aren't annotations
+            query.offset += fetched;
+            return Ok(Some(new));
+        }
+        self.note(&new, query);
+        match self.search_after(query)? {
+            Some(search_after) if search_after != query.search_after => {
+                query.search_after = search_after;
+                query.offset = 0;
+            }
+            _ => query.offset += fetched,
+        }
+        Ok(Some(new))
+    }
+
+    /// The annotations of `page` that weren't returned before
+    pub(crate) fn unseen(&self, page: Vec<Annotation>) -> Vec<Annotation> {
+        page.into_iter()
+            .filter(|annotation| !self.recent.iter().any(|seen| seen.id == annotation.id))
+            .collect()
+    }
+
+    /// Records `annotations`, the next ones returned for `query`, keeping those the next page
+    /// can overlap with
+    pub(crate) fn note(&mut self, annotations: &[Annotation], query: &SearchQuery) {
+        self.recent
+            .extend(annotations.iter().map(|annotation| Returned {
+                id: annotation.id.clone(),
+                at: match query.sort {
+                    Sort::Created => annotation.created,
+                    _ => annotation.updated,
+                },
+            }));
+        if let Some(boundary) = self.boundary(query) {
+            self.recent.retain(|seen| match query.order {
+                Order::Asc => seen.at > boundary,
+                Order::Desc => seen.at < boundary,
+            });
+        }
+    }
+
+    /// Moves `query` to the annotations from `at` on, in its order, including those at `at`,
+    /// which setting `search_after` to `at` leaves out.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::{PageCursor, SearchQuery};
+    /// use hypothesis::testing::fixtures;
+    /// let mut query = SearchQuery::builder().build()?;
+    /// PageCursor::start_at(&mut query, fixtures::annotation("a").updated)?;
+    /// assert_eq!(query.search_after, "2023-01-02T10:00:00.001Z");
+    /// # Ok::<(), hypothesis::errors::HypothesisError>(())
+    /// ```
+    pub fn start_at(
+        query: &mut SearchQuery,
+        at: OffsetDateTime,
+    ) -> Result<(), errors::HypothesisError> {
+        query.search_after = format_time(before(at, &query.order))?;
+        query.offset = 0;
+        Ok(())
+    }
+
+    /// A millisecond before the last returned annotation, in the order of `query`
+    fn boundary(&self, query: &SearchQuery) -> Option<OffsetDateTime> {
+        Some(before(self.recent.last()?.at, &query.order))
+    }
+
+    /// `search_after` for the page after the annotations returned so far, None before the first
+    pub(crate) fn search_after(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<Option<String>, errors::HypothesisError> {
+        self.boundary(query).map(format_time).transpose()
+    }
+}
+
+/// A millisecond before `at` in `order`
+fn before(at: OffsetDateTime, order: &Order) -> OffsetDateTime {
+    let step = time::Duration::milliseconds(1);
+    match order {
+        Order::Asc => at - step,
+        Order::Desc => at + step,
+    }
+}
+
+fn format_time(at: OffsetDateTime) -> Result<String, errors::HypothesisError> {
+    at.format(&time::format_description::well_known::Rfc3339)
+        .map_err(|e| time::Error::Format(e).into())
+}
+
+/// Outcome of [`Hypothesis::delete_annotation`](../struct.Hypothesis.html#method.delete_annotation)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeletionResult {
+    pub id: AnnotationID,
+    pub deleted: bool,
+    /// HTTP status of the response
+    #[serde(skip)]
+    pub status: u16,
+}
+
+/// Moderation actions on a single annotation
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    Flag,
+    Hide,
+    Show,
+}
+
+/// Outcome of flagging, hiding or showing an annotation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModerationResult {
+    pub id: AnnotationID,
+    pub action: ModerationAction,
+    /// HTTP status of the response
+    pub status: u16,
+}
+
+/// Lists of principals allowed to perform each action on an annotation,
+/// either user account IDs ("acct:{username}@{authority}") or groups ("group:{group ID}")
+///
+/// # Example
+/// ```
+/// use hypothesis::UserAccountID;
+/// use hypothesis::annotations::{InputAnnotation, Permissions};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let me: UserAccountID = "my_username".parse()?;
+/// // Only visible to me
+/// let annotation = InputAnnotation::builder()
+///     .uri("https://www.example.com")
+///     .text("Note to self")
+///     .permissions(Permissions::private(&me))
+///     .build()?;
+/// assert!(annotation.permissions.unwrap().is_private());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Permissions {
     pub read: Vec<String>,
     pub delete: Vec<String>,
     pub admin: Vec<String>,
     pub update: Vec<String>,
 }
+
+impl Permissions {
+    fn owned_by(user: &UserAccountID, read: String) -> Self {
+        Self {
+            read: vec![read],
+            delete: vec![user.0.to_owned()],
+            admin: vec![user.0.to_owned()],
+            update: vec![user.0.to_owned()],
+        }
+    }
+
+    /// Readable only by `user`
+    pub fn private(user: &UserAccountID) -> Self {
+        Self::owned_by(user, user.0.to_owned())
+    }
+
+    /// Readable by everyone in `group` ("__world__" for public annotations), editable by `user`
+    pub fn shared(group: &GroupID, user: &UserAccountID) -> Self {
+        Self::owned_by(user, format!("group:{}", group))
+    }
+
+    /// True if any group can read the annotation
+    pub fn is_shared(&self) -> bool {
+        self.read
+            .iter()
+            .any(|principal| principal.starts_with("group:"))
+    }
+
+    /// True if only individual users (usually just the creator) can read the annotation
+    pub fn is_private(&self) -> bool {
+        !self.is_shared()
+    }
+}
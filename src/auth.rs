@@ -0,0 +1,407 @@
+//! Authorization: developer API keys, OAuth 2.0 access tokens and authority grant tokens
+//!
+//! See [the Hypothesis docs](https://h.readthedocs.io/en/latest/publishers/authorization-grant-tokens/)
+//! for how apps obtain OAuth tokens on behalf of users.
+
+use std::fmt;
+
+use jsonwebtoken::{EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::HypothesisError;
+use crate::{serde_parse, UserAccountID};
+
+/// Access tokens are refreshed this long before they expire
+const REFRESH_MARGIN: Duration = Duration::seconds(30);
+
+/// Grant tokens are only exchanged right after minting, so they can be short-lived
+const GRANT_TOKEN_LIFETIME: Duration = Duration::minutes(10);
+
+const JWT_BEARER_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// Shown instead of tokens and keys in `Debug` output, so they don't end up in logs
+const REDACTED: &str = "<redacted>";
+
+/// Response of the `POST /token` endpoint
+///
+/// Its `Debug` output leaves out the access and refresh tokens.
+///
+/// # Example
+/// ```
+/// use hypothesis::auth::OAuthToken;
+/// let token = OAuthToken {
+///     access_token: "5768-mSgpSXOUMb8A4FsPYQT_xhV0l1iKJcDfQZ1n10z9vFf".into(),
+///     token_type: "Bearer".into(),
+///     expires_in: Some(3600),
+///     refresh_token: Some("4657-dkJGNdVn8dmaSuaT5Wa9nzQ6F2GCIiTGBd1RixsTVEA".into()),
+/// };
+/// let debug = format!("{:?}", token);
+/// assert!(!debug.contains("5768") && !debug.contains("4657"));
+/// assert!(debug.contains("3600"));
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct OAuthToken {
+    pub access_token: String,
+    /// "Bearer"
+    #[serde(default)]
+    pub token_type: String,
+    /// Lifetime of the access token in seconds
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+    /// Used to get a new access token once this one expires
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+impl fmt::Debug for OAuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthToken")
+            .field("access_token", &REDACTED)
+            .field("token_type", &self.token_type)
+            .field("expires_in", &self.expires_in)
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| REDACTED),
+            )
+            .finish()
+    }
+}
+
+/// Credentials of an OAuth client registered for a third-party authority with the
+/// `jwt_bearer` grant type, used to act on behalf of the authority's own users
+/// (e.g. an LMS integration impersonating its students).
+///
+/// Pass to [`Hypothesis::from_authority_client`](../struct.Hypothesis.html#method.from_authority_client).
+///
+/// The client secret is left out of its `Debug` output and is never serialized, only read.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct AuthorityClient {
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub client_secret: String,
+    /// Authority the client is registered for, e.g. "lms.example.com"
+    pub authority: String,
+}
+
+impl fmt::Debug for AuthorityClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorityClient")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &REDACTED)
+            .field("authority", &self.authority)
+            .finish()
+    }
+}
+
+/// Claims of a grant token
+#[derive(Serialize)]
+struct GrantClaims<'a> {
+    aud: &'a str,
+    iss: &'a str,
+    sub: &'a str,
+    nbf: i64,
+    exp: i64,
+}
+
+impl AuthorityClient {
+    pub fn new(client_id: &str, client_secret: &str, authority: &str) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            authority: authority.into(),
+        }
+    }
+
+    /// Mints a grant token for `user`: a JWT signed with the client secret,
+    /// to be exchanged for an access token at the `/token` endpoint of the API at `api_url`.
+    ///
+    /// The token's audience is the host of `api_url`, which the API checks against the host it
+    /// is served from, so tokens work with self-hosted servers too.
+    ///
+    /// Fails with [`HypothesisError::AuthError`] if `user` isn't of the client's authority.
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::UserAccountID;
+    /// use hypothesis::auth::AuthorityClient;
+    /// let client = AuthorityClient::new("client_id", "client_secret", "lms.example.com");
+    /// let student = UserAccountID::new("student_1", "lms.example.com");
+    /// let token = client.grant_token(&student, hypothesis::API_URL)?;
+    /// assert_eq!(token.split('.').count(), 3);
+    /// let stranger = UserAccountID::new("student_1", "hypothes.is");
+    /// assert!(client.grant_token(&stranger, hypothesis::API_URL).is_err());
+    /// assert!(format!("{:?}", client).contains("<redacted>"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn grant_token(
+        &self,
+        user: &UserAccountID,
+        api_url: &str,
+    ) -> Result<String, HypothesisError> {
+        if user.authority() != self.authority {
+            return Err(HypothesisError::AuthError(format!(
+                "{} isn't a user of the client's authority {}",
+                user.0, self.authority
+            )));
+        }
+        let api_url = url::Url::parse(api_url)?;
+        let audience = api_url.host_str().ok_or_else(|| {
+            HypothesisError::AuthError(format!("the API URL {} has no host", api_url))
+        })?;
+        let now = OffsetDateTime::now_utc();
+        let claims = GrantClaims {
+            aud: audience,
+            iss: &self.client_id,
+            sub: &user.0,
+            nbf: now.unix_timestamp(),
+            exp: (now + GRANT_TOKEN_LIFETIME).unix_timestamp(),
+        };
+        jsonwebtoken::encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.client_secret.as_bytes()),
+        )
+        .map_err(|e| HypothesisError::AuthError(format!("couldn't sign the grant token: {}", e)))
+    }
+
+    /// Mints a grant token for `user` and exchanges it for an access token
+    async fn access_token(
+        &self,
+        client: &reqwest::Client,
+        api_url: &str,
+        user: &UserAccountID,
+    ) -> Result<OAuthToken, HypothesisError> {
+        let grant_token = self.grant_token(user, api_url)?;
+        request_token(
+            client,
+            api_url,
+            &[
+                ("grant_type", JWT_BEARER_GRANT_TYPE),
+                ("assertion", &grant_token),
+            ],
+            "exchanging the grant token",
+        )
+        .await
+    }
+}
+
+/// How the client authenticates its requests
+#[derive(Clone)]
+pub(crate) enum Credentials {
+    /// No credentials, for read-only access to public annotations
+    Anonymous,
+    DeveloperKey(String),
+    OAuth {
+        token: OAuthToken,
+        /// None if the token doesn't expire or the expiry is unknown
+        expires_at: Option<OffsetDateTime>,
+        /// OAuth client ID, sent along with refresh requests if set
+        client_id: Option<String>,
+        /// Refresh the access token automatically before it expires
+        auto_refresh: bool,
+    },
+    /// Access token obtained with a grant token, replaced by a new one before it expires
+    Grant {
+        client: AuthorityClient,
+        user: UserAccountID,
+        token: OAuthToken,
+        expires_at: Option<OffsetDateTime>,
+    },
+}
+
+/// Leaves out developer keys, and tokens through [`OAuthToken`]'s `Debug`
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Anonymous => f.write_str("Anonymous"),
+            Self::DeveloperKey(_) => f.debug_tuple("DeveloperKey").field(&REDACTED).finish(),
+            Self::OAuth {
+                token,
+                expires_at,
+                client_id,
+                auto_refresh,
+            } => f
+                .debug_struct("OAuth")
+                .field("token", token)
+                .field("expires_at", expires_at)
+                .field("client_id", client_id)
+                .field("auto_refresh", auto_refresh)
+                .finish(),
+            Self::Grant {
+                client,
+                user,
+                token,
+                expires_at,
+            } => f
+                .debug_struct("Grant")
+                .field("client", client)
+                .field("user", user)
+                .field("token", token)
+                .field("expires_at", expires_at)
+                .finish(),
+        }
+    }
+}
+
+impl Credentials {
+    pub(crate) fn oauth(token: OAuthToken, client_id: Option<&str>, auto_refresh: bool) -> Self {
+        Self::OAuth {
+            expires_at: expiry(&token),
+            token,
+            client_id: client_id.map(String::from),
+            auto_refresh,
+        }
+    }
+
+    /// Credentials that get their first access token on [`Credentials::refresh`]
+    pub(crate) fn grant(client: AuthorityClient, user: UserAccountID) -> Self {
+        Self::Grant {
+            client,
+            user,
+            token: OAuthToken {
+                access_token: String::new(),
+                token_type: String::new(),
+                expires_in: None,
+                refresh_token: None,
+            },
+            expires_at: None,
+        }
+    }
+
+    /// The bearer token to send, None for anonymous clients
+    pub(crate) fn bearer(&self) -> Option<&str> {
+        match self {
+            Self::Anonymous => None,
+            Self::DeveloperKey(key) => Some(key),
+            Self::OAuth { token, .. } | Self::Grant { token, .. } => Some(&token.access_token),
+        }
+    }
+
+    /// The OAuth token in use, None for developer keys and anonymous clients
+    pub(crate) fn token(&self) -> Option<&OAuthToken> {
+        match self {
+            Self::Anonymous | Self::DeveloperKey(_) => None,
+            Self::OAuth { token, .. } | Self::Grant { token, .. } => Some(token),
+        }
+    }
+
+    /// True if the access token should be refreshed before the next request
+    pub(crate) fn needs_refresh(&self) -> bool {
+        match self {
+            Self::Anonymous | Self::DeveloperKey(_) => false,
+            Self::OAuth {
+                token,
+                expires_at,
+                auto_refresh,
+                ..
+            } => {
+                *auto_refresh
+                    && token.refresh_token.is_some()
+                    && expires_at.is_some_and(|expires_at| {
+                        expires_at - REFRESH_MARGIN <= OffsetDateTime::now_utc()
+                    })
+            }
+            Self::Grant {
+                token, expires_at, ..
+            } => {
+                // no access token yet, see `Credentials::grant`
+                token.access_token.is_empty()
+                    || expires_at.is_some_and(|expires_at| {
+                        expires_at - REFRESH_MARGIN <= OffsetDateTime::now_utc()
+                    })
+            }
+        }
+    }
+
+    /// Exchanges the refresh token (or a new grant token) for a new access token and stores it
+    pub(crate) async fn refresh(
+        &mut self,
+        client: &reqwest::Client,
+        api_url: &str,
+    ) -> Result<OAuthToken, HypothesisError> {
+        if let Self::Grant {
+            client: authority_client,
+            user,
+            token,
+            expires_at,
+        } = self
+        {
+            let new_token = authority_client.access_token(client, api_url, user).await?;
+            *expires_at = expiry(&new_token);
+            *token = new_token.clone();
+            return Ok(new_token);
+        }
+        let (token, expires_at, client_id) = match self {
+            Self::OAuth {
+                token,
+                expires_at,
+                client_id,
+                ..
+            } => (token, expires_at, client_id),
+            Self::Anonymous => {
+                return Err(HypothesisError::AuthError(
+                    "anonymous clients have no access token to refresh".into(),
+                ))
+            }
+            _ => {
+                return Err(HypothesisError::AuthError(
+                    "developer API keys can't be refreshed".into(),
+                ))
+            }
+        };
+        let refresh_token = token
+            .refresh_token
+            .clone()
+            .ok_or_else(|| HypothesisError::AuthError("no refresh token available".into()))?;
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+        ];
+        if let Some(client_id) = client_id {
+            form.push(("client_id", client_id));
+        }
+        let mut new_token =
+            request_token(client, api_url, &form, "refreshing the access token").await?;
+        // the refresh token stays valid if the server doesn't issue a new one
+        new_token.refresh_token.get_or_insert(refresh_token);
+        *expires_at = expiry(&new_token);
+        *token = new_token.clone();
+        Ok(new_token)
+    }
+}
+
+/// Posts `form` to the `/token` endpoint of the API at `api_url`, `action` describes the
+/// request in errors
+async fn request_token(
+    client: &reqwest::Client,
+    api_url: &str,
+    form: &[(&str, &str)],
+    action: &str,
+) -> Result<OAuthToken, HypothesisError> {
+    let response = client
+        .post(format!("{}/token", api_url))
+        .form(form)
+        .send()
+        .await
+        .map_err(HypothesisError::ReqwestError)?;
+    let status = response.status();
+    let text = response
+        .text()
+        .await
+        .map_err(HypothesisError::ReqwestError)?;
+    if !status.is_success() {
+        return Err(HypothesisError::AuthError(format!(
+            "{} failed with status {}: {}",
+            action, status, text
+        )));
+    }
+    serde_parse::<OAuthToken>(&text)
+}
+
+fn expiry(token: &OAuthToken) -> Option<OffsetDateTime> {
+    token
+        .expires_in
+        .map(|seconds| OffsetDateTime::now_utc() + Duration::seconds(seconds))
+}
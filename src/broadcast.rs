@@ -0,0 +1,141 @@
+//! Post the same reply to many annotations (e.g. a moderator notice on flagged items)
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::annotations::{InputAnnotation, SearchQuery};
+use crate::errors::HypothesisError;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::{AnnotationID, Hypothesis};
+
+/// Options for [`Hypothesis::broadcast_reply`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BroadcastOptions {
+    /// Only report which annotations would be replied to, without posting anything
+    pub dry_run: bool,
+    /// Minimum delay between two posted replies
+    pub delay: Duration,
+}
+
+/// Outcome of [`Hypothesis::broadcast_reply`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BroadcastSummary {
+    /// Annotations that were (or, in a dry run, would be) replied to
+    pub replied_to: Vec<AnnotationID>,
+    /// IDs of the created replies, in the same order (empty in a dry run)
+    pub replies: Vec<AnnotationID>,
+    /// Annotations left out because they already have the reply, e.g. from a cancelled run
+    pub already_replied: Vec<AnnotationID>,
+}
+
+/// Error of [`Hypothesis::broadcast_reply`], with the replies posted before it
+#[derive(Error, Debug)]
+#[error("Broadcast stopped after {} replies: {source}", .summary.replies.len())]
+pub struct BroadcastError {
+    /// What was done before the error
+    pub summary: BroadcastSummary,
+    #[source]
+    pub source: HypothesisError,
+}
+
+/// An error before any reply was posted
+impl From<HypothesisError> for BroadcastError {
+    fn from(source: HypothesisError) -> Self {
+        Self {
+            summary: BroadcastSummary::default(),
+            source,
+        }
+    }
+}
+
+impl From<BroadcastError> for HypothesisError {
+    fn from(error: BroadcastError) -> Self {
+        error.source
+    }
+}
+
+impl Hypothesis {
+    /// Reply with `text` to every annotation matching `query`.
+    ///
+    /// Replies are posted one at a time, at most one per `options.delay`, reporting to `progress`
+    /// after each. Annotations that already have a reply with `text` by the user are left out, so
+    /// running it again after it was cancelled or failed continues with the annotations that are
+    /// left.
+    ///
+    /// On an error part-way, e.g. a cancel or a rejected reply, the [`BroadcastError`] has the
+    /// summary of the replies posted before it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::broadcast::BroadcastOptions;
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let query = SearchQuery::builder().group(&group_id).tag("needs-source").build()?;
+    /// let options = BroadcastOptions { dry_run: true, delay: Duration::from_secs(1) };
+    /// let summary = api.broadcast_reply(&query, "Please add a source for this claim.", &options, ()).await?;
+    /// println!("Would reply to {} annotations", summary.replied_to.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
+    pub async fn broadcast_reply(
+        &self,
+        query: &SearchQuery,
+        text: &str,
+        options: &BroadcastOptions,
+        progress: impl ProgressObserver,
+    ) -> Result<BroadcastSummary, BroadcastError> {
+        let targets = self
+            .search_annotations_return_all(&mut query.clone())
+            .await?;
+        let mut earlier = SearchQuery::builder()
+            .user(&self.user().0)
+            .text(text)
+            .limit(200)
+            .build()?;
+        let earlier = self.search_annotations_return_all(&mut earlier).await?;
+        let replied: HashSet<&AnnotationID> = earlier
+            .iter()
+            .filter(|reply| reply.text == text)
+            .filter_map(|reply| reply.references.last())
+            .collect();
+        let (already_replied, targets): (Vec<_>, Vec<_>) = targets
+            .into_iter()
+            .partition(|target| replied.contains(&target.id));
+        let mut summary = BroadcastSummary {
+            already_replied: already_replied.into_iter().map(|a| a.id).collect(),
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(progress, targets.len());
+        for (i, target) in targets.iter().enumerate() {
+            if !options.dry_run {
+                if let Err(source) = tracker.check_cancelled() {
+                    return Err(BroadcastError { summary, source });
+                }
+                if i > 0 && !options.delay.is_zero() {
+                    tokio::time::sleep(options.delay).await;
+                }
+                let reply = self
+                    .create_annotation(&InputAnnotation::reply_to(target, text))
+                    .await;
+                match tracker.record(reply) {
+                    Ok(reply) => summary.replies.push(reply.id),
+                    Err(source) => return Err(BroadcastError { summary, source }),
+                }
+            }
+            summary.replied_to.push(target.id.to_owned());
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,297 @@
+//! Provisioning of users, groups and group memberships in a single request to the bulk endpoint
+//! (`POST /bulk`), only available to authority clients
+//!
+//! The request body is NDJSON: a `configure` command followed by one command per action, each
+//! a JSON array of the command's name and its body. Users and groups are given anchors
+//! ("$anchor") that memberships refer to, so a student, their course group and the membership
+//! can be sent together before any of them has an ID.
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::auth::AuthorityClient;
+//! use hypothesis::bulk::{BulkAction, BulkGroup, BulkUser};
+//! let client = AuthorityClient::new("client_id", "client_secret", "lms.example.com");
+//! let api = Hypothesis::from_authority_client(&client, "lms_bot")?;
+//! let actions = vec![
+//!     BulkAction::UpsertUser(BulkUser::new("student_1", "student_1", "Student One")),
+//!     BulkAction::UpsertGroup(BulkGroup::new("course", "Reading 101", "course-101")),
+//!     BulkAction::membership("student_1", "course"),
+//! ];
+//! for result in api.bulk(&client, &actions).await? {
+//!     println!("{} {} ({:?})", result.kind, result.id, result.anchor);
+//! }
+//! #     Ok(())
+//! # }
+//! ```
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::{header, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::auth::AuthorityClient;
+use crate::errors::HypothesisError;
+use crate::users::Identity;
+use crate::{Hypothesis, UserAccountID};
+
+/// Media type of bulk requests and responses
+pub const BULK_MEDIA_TYPE: &str = "application/vnd.hypothesis.v1+x-ndjson";
+
+/// A user to create, or update if one with the same username exists
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkUser {
+    /// Name that memberships refer to the user by, within the request
+    pub anchor: String,
+    /// string [ 3 .. 30 ] characters ^[A-Za-z0-9._]+$
+    pub username: String,
+    /// string <= 30 characters
+    pub display_name: String,
+    /// Identities of the user with external identity providers
+    #[serde(default)]
+    pub identities: Vec<Identity>,
+}
+
+impl BulkUser {
+    pub fn new(anchor: &str, username: &str, display_name: &str) -> Self {
+        Self {
+            anchor: anchor.into(),
+            username: username.into(),
+            display_name: display_name.into(),
+            identities: Vec::new(),
+        }
+    }
+}
+
+/// A group to create, or update if one with the same `authority_provided_id` exists
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkGroup {
+    /// Name that memberships refer to the group by, within the request
+    pub anchor: String,
+    pub name: String,
+    /// ID of the group in the authority's own system, e.g. a course ID
+    pub authority_provided_id: String,
+}
+
+impl BulkGroup {
+    pub fn new(anchor: &str, name: &str, authority_provided_id: &str) -> Self {
+        Self {
+            anchor: anchor.into(),
+            name: name.into(),
+            authority_provided_id: authority_provided_id.into(),
+        }
+    }
+}
+
+/// One operation of a bulk request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BulkAction {
+    UpsertUser(BulkUser),
+    UpsertGroup(BulkGroup),
+    /// Adds the user anchored as `member` to the group anchored as `group`
+    CreateMembership {
+        member: String,
+        group: String,
+    },
+}
+
+impl BulkAction {
+    /// Adds the user anchored as `member` to the group anchored as `group`
+    pub fn membership(member: &str, group: &str) -> Self {
+        Self::CreateMembership {
+            member: member.into(),
+            group: group.into(),
+        }
+    }
+
+    /// The command for this action, for users and groups of `authority`.
+    ///
+    /// Upserts find the existing user by authority and username, and the existing group by
+    /// authority and `authority_provided_id`, with the `query` in their `meta`.
+    fn command(&self, authority: &str) -> Value {
+        match self {
+            Self::UpsertUser(user) => json!(["upsert", {"data": {
+                "type": "user",
+                "attributes": {
+                    "authority": authority,
+                    "username": user.username,
+                    "display_name": user.display_name,
+                    "identities": user.identities,
+                },
+                "meta": {
+                    "$anchor": user.anchor,
+                    "query": {"authority": authority, "username": user.username},
+                },
+            }}]),
+            Self::UpsertGroup(group) => json!(["upsert", {"data": {
+                "type": "group",
+                "attributes": {
+                    "authority": authority,
+                    "name": group.name,
+                    "authority_provided_id": group.authority_provided_id,
+                },
+                "meta": {
+                    "$anchor": group.anchor,
+                    "query": {
+                        "authority": authority,
+                        "authority_provided_id": group.authority_provided_id,
+                    },
+                },
+            }}]),
+            Self::CreateMembership { member, group } => json!(["create", {"data": {
+                "type": "group_membership",
+                "relationships": {
+                    "member": {"data": {"type": "user", "id": {"$ref": member}}},
+                    "group": {"data": {"type": "group", "id": {"$ref": group}}},
+                },
+            }}]),
+        }
+    }
+}
+
+/// Body of a bulk request performing `actions` on behalf of `effective_user`, whose authority
+/// the users and groups are created in
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use hypothesis::UserAccountID;
+/// use hypothesis::bulk::{to_ndjson, BulkAction, BulkGroup};
+/// let bot = UserAccountID::new("lms_bot", "lms.example.com");
+/// let body = to_ndjson(&bot, &[BulkAction::UpsertGroup(BulkGroup::new("course", "Reading 101", "c-101"))])?;
+/// let lines: Vec<serde_json::Value> =
+///     body.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+/// assert_eq!(lines[0][0], "configure");
+/// assert_eq!(lines[0][1]["user"]["effective"], "acct:lms_bot@lms.example.com");
+/// assert_eq!(lines[0][1]["instructions"]["total_instructions"], 2);
+/// assert_eq!(lines[1][0], "upsert");
+/// assert_eq!(lines[1][1]["data"]["attributes"]["authority"], "lms.example.com");
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_ndjson(
+    effective_user: &UserAccountID,
+    actions: &[BulkAction],
+) -> Result<String, HypothesisError> {
+    let configure = json!(["configure", {
+        "view": "basic",
+        "user": {"effective": effective_user.0},
+        "instructions": {"total_instructions": actions.len() + 1},
+        "defaults": [
+            ["create", "*", {"on_duplicate": "continue"}],
+            ["upsert", "*", {"merge_query": true}],
+        ],
+    }]);
+    let mut body = String::new();
+    for command in std::iter::once(configure).chain(
+        actions
+            .iter()
+            .map(|action| action.command(effective_user.authority())),
+    ) {
+        body.push_str(&serde_json::to_string(&command)?);
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+/// An object created or updated by a bulk request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BulkResult {
+    pub id: String,
+    /// "user", "group" or "group_membership"
+    pub kind: String,
+    /// Anchor of the user or group in the request
+    pub anchor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResultLine {
+    data: ResultData,
+}
+
+#[derive(Deserialize)]
+struct ResultData {
+    id: Value,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    meta: Option<ResultMeta>,
+}
+
+#[derive(Deserialize)]
+struct ResultMeta {
+    #[serde(rename = "$anchor", default)]
+    anchor: Option<String>,
+}
+
+/// Results of a bulk request from the NDJSON body of its response
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use hypothesis::bulk::parse_results;
+/// let results = parse_results(
+///     "{\"data\": {\"id\": \"acct:student_1@lms.example.com\", \"type\": \"user\", \"meta\": {\"$anchor\": \"student_1\"}}}\n\
+///      {\"data\": {\"id\": 42, \"type\": \"group_membership\"}}\n",
+/// )?;
+/// assert_eq!(results[0].anchor.as_deref(), Some("student_1"));
+/// assert_eq!(results[1].id, "42");
+/// # Ok(())
+/// # }
+/// ```
+pub fn parse_results(ndjson: &str) -> Result<Vec<BulkResult>, HypothesisError> {
+    ndjson
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let ResultLine { data } = serde_json::from_str(line)?;
+            Ok(BulkResult {
+                id: match data.id {
+                    Value::String(id) => id,
+                    id => id.to_string(),
+                },
+                kind: data.kind,
+                anchor: data.meta.and_then(|meta| meta.anchor),
+            })
+        })
+        .collect()
+}
+
+impl Hypothesis {
+    /// Performs `actions` in one request to the bulk endpoint, authenticated with `client`'s
+    /// credentials and on behalf of this client's user, and returns what was created or updated.
+    ///
+    /// The request is processed as a whole: if any action fails, the API rejects all of them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = actions.len())))]
+    pub async fn bulk(
+        &self,
+        client: &AuthorityClient,
+        actions: &[BulkAction],
+    ) -> Result<Vec<BulkResult>, HypothesisError> {
+        let credentials = format!("{}:{}", client.client_id, client.client_secret);
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(credentials)))?,
+        );
+        headers.insert(
+            header::ACCEPT,
+            header::HeaderValue::from_static(BULK_MEDIA_TYPE),
+        );
+        headers.insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static(BULK_MEDIA_TYPE),
+        );
+        let body = to_ndjson(self.user(), actions)?.into_bytes();
+        let response = self
+            .call_with(Method::POST, "bulk", Some(body), crate::NONE, &headers)
+            .await?;
+        if let Some(error) = response.status_error() {
+            return Err(error);
+        }
+        parse_results(&response.text)
+    }
+}
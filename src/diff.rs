@@ -0,0 +1,156 @@
+//! Compare two collections of annotations, e.g. two groups that mirror each other, or a local
+//! [`Store`](../store/struct.Store.html) checkpoint and the annotations on the server
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{Annotation, SearchQuery};
+use crate::errors::HypothesisError;
+use crate::store::{field_changes, AnnotationChange};
+use crate::{GroupID, Hypothesis};
+
+/// Fields compared between copies of an annotation, which differ in ID, group, dates etc.
+const CONTENT_FIELDS: &[&str] = &["text", "tags", "target", "hidden"];
+
+/// What makes an annotation in one collection the same as one in the other
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Identity {
+    /// Same ID, for two versions of the same collection.
+    /// Changes in any field are reported.
+    Id,
+    /// Same uri and quoted text (or text, for page notes), for copies in another group or account.
+    /// Only changes in text, tags, target and visibility are reported.
+    Anchor,
+}
+
+impl Identity {
+    pub(crate) fn key(self, annotation: &Annotation) -> String {
+        match self {
+            Self::Id => annotation.id.0.to_owned(),
+            Self::Anchor => format!(
+                "{}\n{}",
+                annotation.uri,
+                crate::toc::quote(annotation).unwrap_or(&annotation.text)
+            ),
+        }
+    }
+}
+
+/// Annotations only in the second collection, only in the first, or in both but different
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Diff {
+    pub added: Vec<Annotation>,
+    pub removed: Vec<Annotation>,
+    pub changed: Vec<AnnotationChange>,
+}
+
+impl Diff {
+    /// True if both collections have the same annotations
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares collections `a` and `b`, matching annotations by `identity`.
+/// If several annotations in a collection have the same identity, the first one counts.
+///
+/// # Example
+/// ```
+/// use hypothesis::diff::{diff, Identity};
+/// # fn annotation(id: &str, group: &str, uri: &str, text: &str) -> hypothesis::annotations::Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": uri, "text": text,
+/// #         "tags": [], "group": group, "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+/// #     })).unwrap()
+/// # }
+/// let private = vec![
+///     annotation("a1", "private", "https://example.com/1", "reviewed"),
+///     annotation("a2", "private", "https://example.com/2", "new"),
+/// ];
+/// let public = vec![
+///     annotation("b1", "public", "https://example.com/1", "reviewed"),
+///     annotation("b3", "public", "https://example.com/3", "retracted"),
+/// ];
+/// let diff = diff(&private, &public, Identity::Anchor);
+/// assert_eq!(&*diff.added[0].id, "b3");
+/// assert_eq!(&*diff.removed[0].id, "a2");
+/// assert!(diff.changed.is_empty());
+/// ```
+pub fn diff(a: &[Annotation], b: &[Annotation], identity: Identity) -> Diff {
+    let (in_a, in_b) = (by_key(a, identity), by_key(b, identity));
+    let mut diff = Diff::default();
+    let mut seen = HashSet::new();
+    for annotation in b {
+        let key = identity.key(annotation);
+        if !seen.insert(key.clone()) {
+            continue;
+        }
+        match in_a.get(&key) {
+            None => diff.added.push(annotation.clone()),
+            Some(before) => {
+                let mut fields = field_changes(before, annotation);
+                if identity == Identity::Anchor {
+                    fields.retain(|change| CONTENT_FIELDS.contains(&change.field.as_str()));
+                }
+                if !fields.is_empty() {
+                    diff.changed.push(AnnotationChange {
+                        before: (*before).clone(),
+                        after: annotation.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    diff.removed = a
+        .iter()
+        .filter(|annotation| {
+            let key = identity.key(annotation);
+            !in_b.contains_key(&key) && seen.insert(key)
+        })
+        .cloned()
+        .collect();
+    diff
+}
+
+/// The first annotation with each identity
+fn by_key(annotations: &[Annotation], identity: Identity) -> HashMap<String, &Annotation> {
+    let mut by_key = HashMap::new();
+    for annotation in annotations {
+        by_key.entry(identity.key(annotation)).or_insert(annotation);
+    }
+    by_key
+}
+
+impl Hypothesis {
+    /// Compares the annotations in groups `a` and `b` by [`Identity::Anchor`], e.g. to audit a
+    /// public group that mirrors a private one
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{GroupID, Hypothesis};
+    /// let api = Hypothesis::from_env()?;
+    /// let diff = api.diff_groups(&GroupID::from("private"), &GroupID::from("public")).await?;
+    /// println!("{} not mirrored yet", diff.removed.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn diff_groups(&self, a: &GroupID, b: &GroupID) -> Result<Diff, HypothesisError> {
+        let mut annotations = Vec::new();
+        for group in [a, b] {
+            let mut query = SearchQuery::builder()
+                .group(group.clone())
+                .limit(200)
+                .build()?;
+            annotations.push(self.search_annotations_return_all(&mut query).await?);
+        }
+        Ok(diff(&annotations[0], &annotations[1], Identity::Anchor))
+    }
+}
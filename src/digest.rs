@@ -0,0 +1,272 @@
+//! "This week in the group" digests: the annotations made in a time window, by document, with
+//! who contributed how many, as Markdown or HTML for a newsletter or static site
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::annotations::{
+    group_by_document, Annotation, DocumentGroup, Order, PageCursor, SearchQuery, Sort,
+};
+use crate::errors::HypothesisError;
+use crate::facets::facet_counts;
+use crate::toc::quote;
+use crate::{Hypothesis, UserAccountID};
+
+/// Someone who annotated in the time window of a [`Digest`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Contributor {
+    pub user: UserAccountID,
+    /// Display name, or the username if there is none
+    pub name: String,
+    /// Annotations made in the time window
+    pub annotations: usize,
+}
+
+/// Annotations made in a time window
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Digest {
+    #[serde(with = "time::serde::rfc3339")]
+    pub since: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub until: OffsetDateTime,
+    /// Documents in order of their first annotation, see
+    /// [`group_by_document`](../annotations/fn.group_by_document.html)
+    pub documents: Vec<DocumentGroup>,
+    /// Most annotations first
+    pub contributors: Vec<Contributor>,
+}
+
+/// Display name of `user` from any of `annotations`, or else the username
+fn name(user: &UserAccountID, annotations: &[Annotation]) -> String {
+    annotations
+        .iter()
+        .filter(|annotation| annotation.user == *user)
+        .find_map(|annotation| annotation.user_info.as_ref()?.display_name.clone())
+        .unwrap_or_else(|| user.username().to_owned())
+}
+
+/// `text` on one line
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Digest {
+    /// Digest of the annotations among `annotations` created from `since` until before `until`
+    ///
+    /// # Example
+    /// ```
+    /// use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+    /// use hypothesis::annotations::Annotation;
+    /// use hypothesis::digest::Digest;
+    /// # fn annotation(user: &str, created: &str, text: &str) -> Annotation {
+    /// #     serde_json::from_value(serde_json::json!({
+    /// #         "id": created, "created": created, "updated": created,
+    /// #         "user": format!("acct:{}@hypothes.is", user), "uri": "https://example.com/post",
+    /// #         "text": text, "tags": [], "group": "__world__", "hidden": false, "flagged": false,
+    /// #         "links": {"incontext": "https://hyp.is/a/example.com/post"},
+    /// #         "document": {"title": ["A post"]},
+    /// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+    /// #         "target": [{"source": "https://example.com/post", "selector": [
+    /// #             {"type": "TextQuoteSelector", "exact": "The main claim", "prefix": "", "suffix": ""}]}],
+    /// #     })).unwrap()
+    /// # }
+    /// let annotations = vec![
+    ///     annotation("alice", "2023-01-02T10:00:00Z", "Is this right?"),
+    ///     annotation("bob", "2022-12-20T10:00:00Z", "Too old for the digest"),
+    /// ];
+    /// let since = OffsetDateTime::parse("2023-01-01T00:00:00Z", &Rfc3339)?;
+    /// let until = OffsetDateTime::parse("2023-01-08T00:00:00Z", &Rfc3339)?;
+    /// let digest = Digest::new(&annotations, since, until);
+    /// assert_eq!(digest.total(), 1);
+    /// let markdown = digest.to_markdown();
+    /// let lines: Vec<_> = markdown.lines().collect();
+    /// assert_eq!(lines[0], "# Annotations from 2023-01-01 to 2023-01-08");
+    /// assert_eq!(lines[2], "1 annotation on 1 document by 1 person.");
+    /// assert_eq!(lines[6], "- alice: 1");
+    /// assert_eq!(lines[8], "## [A post](https://example.com/post)");
+    /// assert_eq!(
+    ///     lines[10],
+    ///     "- **alice**: Is this right? ([in context](https://hyp.is/a/example.com/post))"
+    /// );
+    /// assert_eq!(lines[11], "  > The main claim");
+    /// # Ok::<(), time::error::Parse>(())
+    /// ```
+    pub fn new(annotations: &[Annotation], since: OffsetDateTime, until: OffsetDateTime) -> Self {
+        let annotations: Vec<Annotation> = annotations
+            .iter()
+            .filter(|annotation| since <= annotation.created && annotation.created < until)
+            .cloned()
+            .collect();
+        let contributors = facet_counts(&annotations)
+            .users
+            .into_iter()
+            .map(|facet| Contributor {
+                name: name(&facet.value, &annotations),
+                user: facet.value,
+                annotations: facet.count,
+            })
+            .collect();
+        Self {
+            since,
+            until,
+            documents: group_by_document(&annotations),
+            contributors,
+        }
+    }
+
+    /// Number of annotations in the digest
+    pub fn total(&self) -> usize {
+        self.documents.iter().map(|d| d.annotations.len()).sum()
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "Annotations from {} to {}",
+            self.since.date(),
+            self.until.date()
+        )
+    }
+
+    fn summary(&self) -> String {
+        let plural = |count: usize, one: &str, many: &str| {
+            format!("{} {}", count, if count == 1 { one } else { many })
+        };
+        format!(
+            "{} on {} by {}.",
+            plural(self.total(), "annotation", "annotations"),
+            plural(self.documents.len(), "document", "documents"),
+            plural(self.contributors.len(), "person", "people")
+        )
+    }
+
+    /// The author's name of `annotation`
+    fn author<'a>(&'a self, annotation: &'a Annotation) -> &'a str {
+        self.contributors
+            .iter()
+            .find(|contributor| contributor.user == annotation.user)
+            .map_or_else(|| annotation.user.username(), |c| c.name.as_str())
+    }
+
+    /// The digest as a Markdown document
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# {}\n\n{}\n", self.title(), self.summary());
+        if !self.contributors.is_empty() {
+            markdown.push_str("\n## Contributors\n\n");
+            for contributor in &self.contributors {
+                markdown.push_str(&format!(
+                    "- {}: {}\n",
+                    contributor.name, contributor.annotations
+                ));
+            }
+        }
+        for document in &self.documents {
+            markdown.push_str(&format!(
+                "\n## [{}]({})\n\n",
+                one_line(&document.title),
+                document.uri
+            ));
+            for annotation in &document.annotations {
+                markdown.push_str(&format!("- **{}**:", self.author(annotation)));
+                let text = one_line(&annotation.text);
+                if !text.is_empty() {
+                    markdown.push_str(&format!(" {}", text));
+                }
+                markdown.push_str(&format!(
+                    " ([in context]({}))\n",
+                    annotation.incontext_link()
+                ));
+                if let Some(quote) = quote(annotation) {
+                    markdown.push_str(&format!("  > {}\n", one_line(quote)));
+                }
+            }
+        }
+        markdown
+    }
+
+    /// The digest as an HTML fragment, to embed in a page or email
+    pub fn to_html(&self) -> String {
+        let mut html = format!(
+            "<h1>{}</h1>\n<p>{}</p>\n",
+            escape_html(&self.title()),
+            escape_html(&self.summary())
+        );
+        if !self.contributors.is_empty() {
+            html.push_str("<h2>Contributors</h2>\n<ul>\n");
+            for contributor in &self.contributors {
+                html.push_str(&format!(
+                    "<li>{}: {}</li>\n",
+                    escape_html(&contributor.name),
+                    contributor.annotations
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+        for document in &self.documents {
+            html.push_str(&format!(
+                "<h2><a href=\"{}\">{}</a></h2>\n<ul>\n",
+                escape_html(&document.uri),
+                escape_html(&one_line(&document.title))
+            ));
+            for annotation in &document.annotations {
+                html.push_str(&format!(
+                    "<li><strong>{}</strong>: {} (<a href=\"{}\">in context</a>)",
+                    escape_html(self.author(annotation)),
+                    escape_html(&one_line(&annotation.text)),
+                    escape_html(&annotation.incontext_link())
+                ));
+                if let Some(quote) = quote(annotation) {
+                    html.push_str(&format!(
+                        "<blockquote>{}</blockquote>",
+                        escape_html(&one_line(quote))
+                    ));
+                }
+                html.push_str("</li>\n");
+            }
+            html.push_str("</ul>\n");
+        }
+        html
+    }
+}
+
+impl Hypothesis {
+    /// Digest of the annotations matching `scope` that were created from `since` until before
+    /// `until`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use time::{Duration, OffsetDateTime};
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let api = Hypothesis::from_env()?;
+    /// let scope = SearchQuery::builder().group("readingGroupId").build()?;
+    /// let now = OffsetDateTime::now_utc();
+    /// let digest = api.digest(&scope, now - Duration::days(7), now).await?;
+    /// std::fs::write("digest.md", digest.to_markdown())?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(scope = %scope.summary())))]
+    pub async fn digest(
+        &self,
+        scope: &SearchQuery,
+        since: OffsetDateTime,
+        until: OffsetDateTime,
+    ) -> Result<Digest, HypothesisError> {
+        // annotations created in the window were updated in it or later
+        let mut query = scope.clone();
+        query.sort = Sort::Updated;
+        query.order = Order::Asc;
+        PageCursor::start_at(&mut query, since)?;
+        let annotations = self.search_annotations_return_all(&mut query).await?;
+        Ok(Digest::new(&annotations, since, until))
+    }
+}
@@ -0,0 +1,152 @@
+//! Optional enrichment stage for exported / synced annotations
+//!
+//! An [`Enricher`] is a pluggable async transformer, e.g. a call to a translation or
+//! summarization service. Its output is attached to the annotation under the enricher's name,
+//! so that exporters can include it alongside the original record.
+//! No specific provider is built in.
+//!
+//! Enrichers run during [`Hypothesis::export_resumable_enriched`] and
+//! [`Hypothesis::sync_store_enriched`] on each page of annotations, or on any list of annotations
+//! with [`enrich_annotations`].
+//!
+//! [`Hypothesis::export_resumable_enriched`]: ../struct.Hypothesis.html#method.export_resumable_enriched
+//! [`Hypothesis::sync_store_enriched`]: ../struct.Hypothesis.html#method.sync_store_enriched
+
+use std::collections::HashMap;
+
+use futures::future::{BoxFuture, TryFutureExt};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Annotation;
+use crate::errors::HypothesisError;
+
+/// An async transformer run on every annotation during export/sync
+///
+/// # Example
+/// ```
+/// use futures::future::BoxFuture;
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::enrich::Enricher;
+/// use hypothesis::errors::HypothesisError;
+///
+/// /// Counts the words in each annotation's text
+/// struct WordCount;
+///
+/// impl Enricher for WordCount {
+///     fn name(&self) -> &str {
+///         "word_count"
+///     }
+///
+///     fn enrich<'a>(
+///         &'a self,
+///         annotation: &'a Annotation,
+///     ) -> BoxFuture<'a, Result<Option<serde_json::Value>, HypothesisError>> {
+///         Box::pin(async move { Ok(Some(annotation.text.split_whitespace().count().into())) })
+///     }
+/// }
+/// ```
+pub trait Enricher: Send + Sync {
+    /// Key under which the result is stored in [`EnrichedAnnotation::enrichments`]
+    fn name(&self) -> &str;
+
+    /// Computes the enrichment for a single annotation.
+    /// Returning `Ok(None)` leaves the annotation without an entry for this enricher.
+    fn enrich<'a>(
+        &'a self,
+        annotation: &'a Annotation,
+    ) -> BoxFuture<'a, Result<Option<serde_json::Value>, HypothesisError>>;
+}
+
+/// An annotation along with the output of each enricher run on it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnrichedAnnotation {
+    #[serde(flatten)]
+    pub annotation: Annotation,
+    /// Enricher name -> enricher output
+    #[serde(default)]
+    pub enrichments: HashMap<String, serde_json::Value>,
+}
+
+impl From<Annotation> for EnrichedAnnotation {
+    fn from(annotation: Annotation) -> Self {
+        Self {
+            annotation,
+            enrichments: HashMap::new(),
+        }
+    }
+}
+
+/// Runs each enricher (in order) on one annotation
+pub async fn enrich_annotation(
+    annotation: Annotation,
+    enrichers: &[&dyn Enricher],
+) -> Result<EnrichedAnnotation, HypothesisError> {
+    let mut enriched = EnrichedAnnotation::from(annotation);
+    for enricher in enrichers {
+        if let Some(value) = enricher.enrich(&enriched.annotation).await? {
+            enriched
+                .enrichments
+                .insert(enricher.name().to_owned(), value);
+        }
+    }
+    Ok(enriched)
+}
+
+/// Runs the enrichers on many annotations, `limit` annotations at a time, stopping at the first
+/// error. The results are in the order of `annotations`.
+///
+/// # Example
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use futures::future::BoxFuture;
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::enrich::{enrich_annotations, Enricher};
+/// use hypothesis::errors::HypothesisError;
+/// use hypothesis::testing::fixtures;
+///
+/// struct Length;
+///
+/// impl Enricher for Length {
+///     fn name(&self) -> &str {
+///         "length"
+///     }
+///
+///     fn enrich<'a>(
+///         &'a self,
+///         annotation: &'a Annotation,
+///     ) -> BoxFuture<'a, Result<Option<serde_json::Value>, HypothesisError>> {
+///         Box::pin(async move { Ok(Some(annotation.text.len().into())) })
+///     }
+/// }
+///
+/// let enriched = enrich_annotations(fixtures::annotations(3), &[&Length], 2).await?;
+/// assert_eq!(enriched[2].annotation.id, "annotation0002".into());
+/// assert_eq!(enriched[2].enrichments["length"], 19);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn enrich_annotations(
+    annotations: Vec<Annotation>,
+    enrichers: &[&dyn Enricher],
+    limit: usize,
+) -> Result<Vec<EnrichedAnnotation>, HypothesisError> {
+    if enrichers.is_empty() {
+        return Ok(annotations
+            .into_iter()
+            .map(EnrichedAnnotation::from)
+            .collect());
+    }
+    let mut enriched: Vec<(usize, EnrichedAnnotation)> =
+        stream::iter(annotations.into_iter().enumerate())
+            .map(|(i, annotation)| enrich_annotation(annotation, enrichers).map_ok(move |a| (i, a)))
+            .buffer_unordered(limit.max(1))
+            .try_collect()
+            .await?;
+    enriched.sort_unstable_by_key(|(i, _)| *i);
+    Ok(enriched
+        .into_iter()
+        .map(|(_, annotation)| annotation)
+        .collect())
+}
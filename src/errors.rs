@@ -1,18 +1,49 @@
 //! API and CLI specific errors
+use std::collections::HashMap;
 use std::fmt;
 
 use reqwest::header::InvalidHeaderValue;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::policy::PolicyViolation;
+use crate::progress::Progress;
+
 #[derive(Error, Debug)]
 pub enum HypothesisError {
-    #[error("Make sure input fields are valid:\n{source}\n{raw_text}")]
+    #[error("{}Make sure input fields are valid:\n{source}\n{raw_text}", prefix(.context))]
     APIError {
         #[source]
         source: APIError,
         serde_error: Option<serde_json::Error>,
         raw_text: String,
+        /// `X-Request-Id` of the failed request, for reference in support tickets
+        request_id: Option<String>,
+        /// The call that failed, None if the error wasn't from an API call
+        context: Option<Box<RequestContext>>,
+    },
+    #[error("{}Not found (or not visible to you):\n{source}", prefix(.context))]
+    NotFound {
+        #[source]
+        source: APIError,
+        request_id: Option<String>,
+        context: Option<Box<RequestContext>>,
+    },
+    #[error("{}Forbidden:\n{source}", prefix(.context))]
+    Forbidden {
+        #[source]
+        source: APIError,
+        request_id: Option<String>,
+        context: Option<Box<RequestContext>>,
+    },
+    #[error("{}HTTP status {status}:\n{source}\n{raw_text}", prefix(.context))]
+    StatusError {
+        status: u16,
+        #[source]
+        source: APIError,
+        raw_text: String,
+        request_id: Option<String>,
+        context: Option<Box<RequestContext>>,
     },
     #[error("Invalid header value: {0}")]
     HeaderError(#[from] InvalidHeaderValue),
@@ -32,21 +63,417 @@ pub enum HypothesisError {
     URLError(#[from] url::ParseError),
     #[error("Builder error: {0}")]
     BuilderError(String),
+    #[error("Invalid annotation: {0}")]
+    ValidationError(String),
+    #[error("Invalid search query: {0}")]
+    QueryError(String),
+    #[error("Invalid output format: {0}")]
+    FormatError(String),
+    #[error("Neither a group ID nor an invite link: {0}")]
+    InviteError(String),
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    #[error("CSV error: {0}")]
+    CSVError(#[from] csv::Error),
+    #[error("Authorization error: {0}")]
+    AuthError(String),
+    #[error("URL policy doesn't allow annotating {uri}: {violation}")]
+    PolicyError {
+        uri: String,
+        violation: PolicyViolation,
+    },
+    #[error("More than {0} events were waiting to be consumed")]
+    BufferOverflow(usize),
+    #[error("No response within {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Cancelled after {} of {} items", .0.done, .0.total)]
+    Cancelled(Progress),
+}
+
+/// The API call an error came from
+///
+/// # Example
+/// ```
+/// use hypothesis::errors::RequestContext;
+/// let context = RequestContext {
+///     method: "PATCH".into(),
+///     url: "https://api.hypothes.is/api/annotations/abc123".into(),
+///     status: 422,
+/// };
+/// assert_eq!(context.to_string(), "PATCH https://api.hypothes.is/api/annotations/abc123 (422)");
+/// assert_eq!(context.endpoint(), "/annotations/:id");
+/// assert_eq!(context.ids(), vec!["abc123"]);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RequestContext {
+    /// HTTP method, e.g. "POST"
+    pub method: String,
+    pub url: String,
+    /// HTTP status code of the response
+    pub status: u16,
+}
+
+impl RequestContext {
+    fn path(&self) -> String {
+        url::Url::parse(&self.url).map_or_else(|_| self.url.clone(), |url| url.path().to_owned())
+    }
+
+    /// The path below `/api` with IDs replaced by ":id", e.g. "/annotations/:id/flag"
+    pub fn endpoint(&self) -> String {
+        crate::hooks::endpoint(&self.path())
+    }
+
+    /// The annotation, group or user IDs in the URL, in order
+    pub fn ids(&self) -> Vec<String> {
+        crate::hooks::path_ids(&self.path())
+    }
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({})", self.method, self.url, self.status)
+    }
+}
+
+/// Start of the message of an error with `context`
+fn prefix(context: &Option<Box<RequestContext>>) -> String {
+    context
+        .as_ref()
+        .map(|context| format!("{}: ", context))
+        .unwrap_or_default()
+}
+
+/// Broad cause of a [`HypothesisError`], for scripts to tell failures apart
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Missing or invalid credentials or settings, e.g. HYPOTHESIS_KEY isn't set
+    Config,
+    /// Credentials were rejected, or aren't allowed to do this
+    Auth,
+    NotFound,
+    /// The API couldn't be reached or didn't answer in time
+    Network,
+    /// The API answered with an error or something unexpected
+    Api,
+    /// Invalid input, e.g. an annotation or search query
+    Input,
+    /// Reading or writing local files
+    Io,
+    /// Stopped on request, see [`progress`](../progress/index.html#cancellation)
+    Cancelled,
+}
+
+impl ErrorKind {
+    /// Exit code for the kind of failure, following BSD `sysexits.h`
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Input => 65,
+            Self::NotFound => 66,
+            Self::Network => 69,
+            Self::Io => 74,
+            Self::Api => 76,
+            Self::Auth => 77,
+            Self::Config => 78,
+            // as if interrupted by SIGINT
+            Self::Cancelled => 130,
+        }
+    }
+}
+
+impl HypothesisError {
+    /// Broad cause of the error
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::errors::{ErrorKind, HypothesisError};
+    /// let error = HypothesisError::NotFound {
+    ///     source: Default::default(),
+    ///     request_id: None,
+    ///     context: None,
+    /// };
+    /// assert_eq!(error.kind(), ErrorKind::NotFound);
+    /// assert_eq!(error.exit_code(), 66);
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::EnvironmentError { .. } | Self::HeaderError(_) => ErrorKind::Config,
+            Self::AuthError(_) | Self::Forbidden { .. } => ErrorKind::Auth,
+            Self::StatusError { status: 401, .. } => ErrorKind::Auth,
+            Self::NotFound { .. } => ErrorKind::NotFound,
+            Self::ReqwestError(e) if e.status().is_none() && !e.is_decode() => ErrorKind::Network,
+            Self::Timeout(_) => ErrorKind::Network,
+            Self::APIError { .. }
+            | Self::StatusError { .. }
+            | Self::ReqwestError(_)
+            | Self::SerdeError(_)
+            | Self::BufferOverflow(_) => ErrorKind::Api,
+            Self::TimeError(_)
+            | Self::URLError(_)
+            | Self::BuilderError(_)
+            | Self::ValidationError(_)
+            | Self::QueryError(_)
+            | Self::FormatError(_)
+            | Self::InviteError(_)
+            | Self::CSVError(_)
+            | Self::PolicyError { .. } => ErrorKind::Input,
+            Self::IOError(_) => ErrorKind::Io,
+            Self::Cancelled(_) => ErrorKind::Cancelled,
+        }
+    }
+
+    /// Exit code of a program failing with this error, see [`ErrorKind::exit_code`]
+    pub fn exit_code(&self) -> i32 {
+        self.kind().exit_code()
+    }
+
+    /// HTTP status code of the failed API call, if a response was received
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            Self::StatusError { status, .. } => Some(*status),
+            Self::NotFound { .. } => Some(404),
+            Self::Forbidden { .. } => Some(403),
+            Self::APIError { context, .. } => context.as_ref().map(|context| context.status),
+            Self::ReqwestError(e) => e.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// True if the same call may succeed when tried again later: the API was unreachable or
+    /// didn't answer in time, rate limited the call, or failed with 502, 503 or 504.
+    ///
+    /// Calls that create annotations may have taken effect even so, retrying them can make
+    /// duplicates.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::errors::HypothesisError;
+    /// let error = |status| HypothesisError::StatusError {
+    ///     status,
+    ///     source: Default::default(),
+    ///     raw_text: String::new(),
+    ///     request_id: None,
+    ///     context: None,
+    /// };
+    /// assert!(error(503).is_retryable());
+    /// assert!(error(429).is_retryable() && error(429).is_rate_limited());
+    /// assert!(!error(400).is_retryable());
+    /// assert_eq!(error(400).status_code(), Some(400));
+    /// assert!(HypothesisError::Timeout(std::time::Duration::from_secs(5)).is_retryable());
+    /// ```
+    pub fn is_retryable(&self) -> bool {
+        match self.status_code() {
+            Some(status) => matches!(status, 408 | 429 | 502 | 503 | 504),
+            None => self.kind() == ErrorKind::Network,
+        }
+    }
+
+    /// True if the API rejected the call with 429 Too Many Requests
+    pub fn is_rate_limited(&self) -> bool {
+        self.status_code() == Some(429)
+    }
+
+    /// True if what the call was about doesn't exist, or isn't visible to the user
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound || self.status_code() == Some(404)
+    }
+
+    /// True if the credentials are missing, were rejected, or aren't allowed to do this
+    pub fn is_auth(&self) -> bool {
+        self.kind() == ErrorKind::Auth
+    }
+
+    /// `X-Request-Id` of the failed API call, if there was one
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Self::APIError { request_id, .. }
+            | Self::NotFound { request_id, .. }
+            | Self::Forbidden { request_id, .. }
+            | Self::StatusError { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The API call that failed, if the error came from one
+    pub fn context(&self) -> Option<&RequestContext> {
+        match self {
+            Self::APIError { context, .. }
+            | Self::NotFound { context, .. }
+            | Self::Forbidden { context, .. }
+            | Self::StatusError { context, .. } => context.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The error payload of a failed API call
+    pub fn api_error(&self) -> Option<&APIError> {
+        match self {
+            Self::APIError { source, .. }
+            | Self::NotFound { source, .. }
+            | Self::Forbidden { source, .. }
+            | Self::StatusError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The input fields that the API rejected, see [`APIError::field_errors`]
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        self.api_error()
+            .map(APIError::field_errors)
+            .unwrap_or_default()
+    }
+
+    /// Machine-readable form of the error, e.g. to print instead of the message with `--json`
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::errors::HypothesisError;
+    /// let error = HypothesisError::QueryError("unterminated quote".into());
+    /// assert_eq!(error.to_json(), serde_json::json!({
+    ///     "kind": "input",
+    ///     "message": "Invalid search query: unterminated quote",
+    ///     "request_id": null,
+    ///     "request": null,
+    /// }));
+    /// ```
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kind": self.kind(),
+            "message": self.to_string(),
+            "request_id": self.request_id(),
+            "request": self.context(),
+        })
+    }
 }
 
 /// Errors returned from the Hypothesis API
+///
+/// # Example
+/// ```
+/// use hypothesis::errors::{APIError, FieldError};
+/// let error: APIError = serde_json::from_str(r#"{
+///     "status": "failure",
+///     "reason": "group: Group 'abc' does not exist\ntext: 'text' is too long",
+///     "description": "Couldn't create the annotation"
+/// }"#).unwrap();
+/// assert_eq!(error.field_errors(), vec![
+///     FieldError { field: "group".into(), message: "Group 'abc' does not exist".into() },
+///     FieldError { field: "text".into(), message: "'text' is too long".into() },
+/// ]);
+/// assert_eq!(error.details.description.as_deref(), Some("Couldn't create the annotation"));
+///
+/// let error: APIError = serde_json::from_str(r#"{
+///     "errors": [{"detail": "is not a valid username", "source": {"pointer": "/data/attributes/username"}}]
+/// }"#).unwrap();
+/// assert_eq!(error.field_errors()[0].field, "data.attributes.username");
+/// ```
 #[derive(Error, Serialize, Deserialize, Debug, Default, Clone)]
 pub struct APIError {
     /// API returned status
+    #[serde(default)]
     pub status: String,
     /// Cause of failure
+    #[serde(default)]
     pub reason: String,
+    /// The rest of the error payload, boxed to keep errors small
+    #[serde(flatten)]
+    pub details: Box<ErrorDetails>,
+}
+
+/// Parts of an API error payload beyond its status and reason
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ErrorDetails {
+    /// Longer explanation, if given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Detailed errors, if given: a list of error objects or a map from fields to messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<serde_json::Value>,
+    /// Any other fields
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+/// An input field that the API rejected, and why
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Field name, with nested fields separated by dots
+    pub field: String,
+    pub message: String,
+}
+
+impl APIError {
+    /// The rejected fields, from `errors` if given, else from lines of `reason` of the form
+    /// "field: message"
+    pub fn field_errors(&self) -> Vec<FieldError> {
+        let from_errors = match &self.details.errors {
+            Some(serde_json::Value::Array(errors)) => errors
+                .iter()
+                .filter_map(|error| {
+                    let field = error
+                        .pointer("/source/pointer")
+                        .or_else(|| error.get("field"))
+                        .and_then(serde_json::Value::as_str)?;
+                    let message = ["detail", "message", "title"]
+                        .iter()
+                        .find_map(|key| error.get(key)?.as_str())
+                        .unwrap_or_default();
+                    Some(FieldError {
+                        field: field.trim_start_matches('/').replace('/', "."),
+                        message: message.to_owned(),
+                    })
+                })
+                .collect(),
+            Some(serde_json::Value::Object(errors)) => errors
+                .iter()
+                .flat_map(|(field, messages)| {
+                    let messages = match messages {
+                        serde_json::Value::Array(messages) => messages.clone(),
+                        message => vec![message.clone()],
+                    };
+                    messages.into_iter().map(move |message| FieldError {
+                        field: field.clone(),
+                        message: message
+                            .as_str()
+                            .map_or_else(|| message.to_string(), String::from),
+                    })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        if !from_errors.is_empty() {
+            return from_errors;
+        }
+        self.reason
+            .lines()
+            .filter_map(|line| {
+                let (field, message) = line.split_once(": ")?;
+                let is_field = !field.is_empty()
+                    && field
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_.[]".contains(c));
+                is_field.then(|| FieldError {
+                    field: field.to_owned(),
+                    message: message.trim().to_owned(),
+                })
+            })
+            .collect()
+    }
 }
 
 impl fmt::Display for APIError {
     #[inline]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Status: {}\nReason: {}", self.status, self.reason)
+        write!(f, "Status: {}\nReason: {}", self.status, self.reason)?;
+        if let Some(description) = &self.details.description {
+            write!(f, "\nDescription: {}", description)?;
+        }
+        for error in self.field_errors() {
+            if !self.reason.contains(&error.message) {
+                write!(f, "\n{}: {}", error.field, error.message)?;
+            }
+        }
+        Ok(())
     }
 }
 
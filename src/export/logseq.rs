@@ -0,0 +1,252 @@
+//! Annotations as outline pages for [Logseq](https://logseq.com) (or Roam), one page per document
+//!
+//! Pages start with `title::` and `source::` properties. Each highlight or page note is a
+//! top-level block, with the annotation's text and tags (`#tag`) in a child block and replies
+//! nested below that. Top-level blocks carry the annotation's ID as their block `id::`, so they
+//! can be referenced as `((id))` from other pages, and a `hypothesis::` link to the annotation
+//! in context.
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::export::logseq;
+//! let api = Hypothesis::from_env()?;
+//! let mut query = SearchQuery::builder().user(&api.user().0).build()?;
+//! let annotations = api.search_annotations_return_all(&mut query).await?;
+//! for page in logseq::pages(&annotations) {
+//!     std::fs::write(format!("graph/pages/{}", page.file_name()), &page.content)?;
+//! }
+//! #     Ok(())
+//! # }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{group_by_document, Annotation};
+use crate::threads::{build_threads, Thread};
+use crate::toc::quote;
+use crate::AnnotationID;
+
+/// A page of the outline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Page {
+    /// Title of the document
+    pub title: String,
+    /// Markdown outline
+    pub content: String,
+}
+
+impl Page {
+    /// Name of the page's file in a graph's `pages` directory, with characters that can't be
+    /// in file names replaced as Logseq does
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::export::logseq::Page;
+    /// let page = Page { title: "Rust/WebAssembly: a guide".into(), content: String::new() };
+    /// assert_eq!(page.file_name(), "Rust___WebAssembly%3A a guide.md");
+    /// ```
+    pub fn file_name(&self) -> String {
+        let mut name = String::new();
+        for c in self.title.chars() {
+            match c {
+                '/' => name.push_str("___"),
+                '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' | '#' | '%' => {
+                    name.push_str(&format!("%{:02X}", c as u32))
+                }
+                c if c.is_control() => {}
+                c => name.push(c),
+            }
+        }
+        name.push_str(".md");
+        name
+    }
+}
+
+/// The UUID that an annotation ID encodes (as 22 characters of URL-safe base64), which Logseq
+/// accepts as a block ID
+fn block_id(id: &AnnotationID) -> Option<String> {
+    if id.len() != 22 {
+        return None;
+    }
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    let mut bytes = Vec::with_capacity(16);
+    for c in id.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// `#tag`, or `#[[tag]]` for tags with spaces
+fn tag(tag: &str) -> String {
+    if tag.contains(char::is_whitespace) {
+        format!("#[[{}]]", tag)
+    } else {
+        format!("#{}", tag)
+    }
+}
+
+/// Writes a block with the non-blank `lines` at `depth`
+fn block(outline: &mut String, depth: usize, lines: &[String]) {
+    let indent = "  ".repeat(depth);
+    for (i, line) in lines.iter().enumerate() {
+        outline.push_str(&indent);
+        outline.push_str(if i == 0 { "- " } else { "  " });
+        outline.push_str(line);
+        outline.push('\n');
+    }
+}
+
+/// The text and tags of `annotation` as block lines, prefixed with `speaker` if given
+fn text_lines(annotation: &Annotation, speaker: Option<&str>) -> Vec<String> {
+    let mut lines: Vec<String> = annotation
+        .text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect();
+    if !annotation.tags.is_empty() {
+        let tags: Vec<_> = annotation.tags.iter().map(|t| tag(t)).collect();
+        match lines.last_mut() {
+            Some(last) => {
+                last.push(' ');
+                last.push_str(&tags.join(" "));
+            }
+            None => lines.push(tags.join(" ")),
+        }
+    }
+    if let Some(speaker) = speaker {
+        match lines.first_mut() {
+            Some(first) => *first = format!("**{}**: {}", speaker, first),
+            None => lines.push(format!("**{}**:", speaker)),
+        }
+    }
+    lines
+}
+
+fn write_replies(outline: &mut String, depth: usize, replies: &[Thread]) {
+    for reply in replies {
+        let speaker = reply
+            .annotation
+            .user_info
+            .as_ref()
+            .and_then(|info| info.display_name.clone())
+            .unwrap_or_else(|| reply.annotation.user.username().to_owned());
+        block(
+            outline,
+            depth,
+            &text_lines(&reply.annotation, Some(&speaker)),
+        );
+        write_replies(outline, depth + 1, &reply.replies);
+    }
+}
+
+/// One page per document that `annotations` were made on, with threads in document order
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::export::logseq::pages;
+/// # fn annotation(id: &str, user: &str, text: &str, tags: &[&str], references: &[&str]) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": format!("acct:{}@hypothes.is", user), "uri": "https://example.com/post",
+/// #         "text": text, "tags": tags, "group": "__world__", "hidden": false, "flagged": false,
+/// #         "links": {}, "document": {"title": ["A post"]}, "references": references,
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "target": [{"source": "https://example.com/post", "selector": [
+/// #             {"type": "TextQuoteSelector", "exact": "The main claim", "prefix": "", "suffix": ""}]}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("NkyAIJ4gEeqHTQ_OYqzdbg", "alice", "Is this right?", &["rust", "to do"], &[]),
+///     annotation("b", "bob", "Yes", &[], &["NkyAIJ4gEeqHTQ_OYqzdbg"]),
+/// ];
+/// let pages = pages(&annotations);
+/// assert_eq!(pages[0].title, "A post");
+/// assert_eq!(pages[0].content, "\
+/// title:: A post
+/// source:: https://example.com/post
+///
+/// - > The main claim
+///   id:: 364c8020-9e20-11ea-874d-0fce62acdd6e
+///   hypothesis:: https://hyp.is/NkyAIJ4gEeqHTQ_OYqzdbg/example.com/post
+///   - Is this right? #rust #[[to do]]
+///     - **bob**: Yes
+/// ");
+/// ```
+pub fn pages(annotations: &[Annotation]) -> Vec<Page> {
+    group_by_document(annotations)
+        .into_iter()
+        .map(|document| {
+            let mut content = format!("title:: {}\nsource:: {}\n\n", document.title, document.uri);
+            let order: Vec<AnnotationID> =
+                document.annotations.iter().map(|a| a.id.clone()).collect();
+            let mut threads = build_threads(document.annotations);
+            // keep roots in document order rather than by creation time
+            threads.sort_by_key(|thread| order.iter().position(|id| *id == thread.annotation.id));
+            for thread in &threads {
+                let annotation = &thread.annotation;
+                let highlight = quote(annotation).map(|quote| {
+                    format!(
+                        "> {}",
+                        quote.split_whitespace().collect::<Vec<_>>().join(" ")
+                    )
+                });
+                let mut text = text_lines(annotation, None);
+                let mut lines = Vec::new();
+                match &highlight {
+                    Some(highlight) => lines.push(highlight.clone()),
+                    None if !text.is_empty() => lines.push(text.remove(0)),
+                    None => continue,
+                }
+                if let Some(id) = block_id(&annotation.id) {
+                    lines.push(format!("id:: {}", id));
+                }
+                lines.push(format!("hypothesis:: {}", annotation.incontext_link()));
+                // page notes keep their text in the top-level block
+                if highlight.is_none() {
+                    lines.append(&mut text);
+                }
+                block(&mut content, 0, &lines);
+                let mut depth = 1;
+                if !text.is_empty() {
+                    block(&mut content, 1, &text);
+                    depth = 2;
+                }
+                write_replies(&mut content, depth, &thread.replies);
+            }
+            Page {
+                title: document.title,
+                content,
+            }
+        })
+        .collect()
+}
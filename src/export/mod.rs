@@ -0,0 +1,9 @@
+//! Highlights and notes in the import formats of other tools
+//!
+//! - [`logseq`]: outline pages for Logseq or Roam, one per document
+//! - [`readwise`]: Readwise's CSV upload and highlights API
+//! - [`zotero`]: BibTeX entries with notes, for Zotero and other reference managers
+
+pub mod logseq;
+pub mod readwise;
+pub mod zotero;
@@ -0,0 +1,186 @@
+//! Highlights for [Readwise](https://readwise.io), as CSV for the upload page or as the body of
+//! a request to its highlights API (`POST https://readwise.io/api/v2/highlights/`)
+//!
+//! Only annotations that highlight text are exported, replies and page notes are left out.
+//! The annotation's text becomes the highlight's note, with its tags appended in Readwise's
+//! inline tag syntax (`.tag`), and documents are titled as by
+//! [`group_by_document`](../../annotations/fn.group_by_document.html).
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::export::readwise;
+//! let api = Hypothesis::from_env()?;
+//! let mut query = SearchQuery::builder().user(&api.user().0).build()?;
+//! let annotations = api.search_annotations_return_all(&mut query).await?;
+//! readwise::write_csv(&annotations, std::fs::File::create("readwise.csv")?)?;
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+
+use crate::annotations::{group_by_document, Annotation, Selector};
+use crate::errors::HypothesisError;
+use crate::toc::quote;
+
+/// A highlight as accepted by the Readwise highlights API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Highlight {
+    /// The highlighted text
+    pub text: String,
+    /// Title of the document
+    pub title: String,
+    pub source_url: String,
+    /// Always "hypothesis"
+    pub source_type: String,
+    /// Always "articles"
+    pub category: String,
+    /// The annotation's text and tags
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub note: String,
+    /// The PDF page, or else the position of the highlight in the document's text
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub location: Option<u64>,
+    /// "page" or "order"
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub location_type: Option<String>,
+    /// RFC 3339 creation time of the annotation
+    pub highlighted_at: String,
+    /// Link to the highlight in context
+    pub highlight_url: String,
+}
+
+/// The CSV columns of Readwise's upload format
+#[derive(Serialize)]
+struct Row<'a> {
+    #[serde(rename = "Highlight")]
+    highlight: &'a str,
+    #[serde(rename = "Title")]
+    title: &'a str,
+    #[serde(rename = "Author")]
+    author: &'a str,
+    #[serde(rename = "URL")]
+    url: &'a str,
+    #[serde(rename = "Note")]
+    note: &'a str,
+    #[serde(rename = "Location")]
+    location: Option<u64>,
+    #[serde(rename = "Date")]
+    date: &'a str,
+}
+
+/// The text of `annotation` followed by its tags as Readwise inline tags
+fn note(annotation: &Annotation) -> String {
+    let tags: Vec<String> = annotation
+        .tags
+        .iter()
+        .map(|tag| format!(".{}", tag.split_whitespace().collect::<Vec<_>>().join("-")))
+        .collect();
+    [annotation.text.trim(), &tags.join(" ")]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 1-based PDF page of the highlight, if it is on one and the page has a number
+fn page(annotation: &Annotation) -> Option<u64> {
+    annotation
+        .target
+        .iter()
+        .flat_map(|target| &target.selector)
+        .find_map(|selector| match selector {
+            Selector::PageSelector(page) => page.page_number().parse().ok(),
+            _ => None,
+        })
+}
+
+/// The highlights among `annotations`, by document and in document order
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::export::readwise::highlights;
+/// # fn annotation(id: &str, text: &str, tags: &[&str], selectors: serde_json::Value) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com/post", "text": text,
+/// #         "tags": tags, "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "document": {"title": ["A post"]},
+/// #         "target": [{"source": "https://example.com/post", "selector": selectors}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("a", "Worth rereading", &["rust", "to do"], serde_json::json!([
+///         {"type": "TextPositionSelector", "start": 120, "end": 134},
+///         {"type": "TextQuoteSelector", "exact": "The main claim", "prefix": "", "suffix": ""},
+///     ])),
+///     annotation("page-note", "No quote", &[], serde_json::json!([])),
+/// ];
+/// let highlights = highlights(&annotations);
+/// assert_eq!(highlights.len(), 1);
+/// assert_eq!(highlights[0].text, "The main claim");
+/// assert_eq!(highlights[0].title, "A post");
+/// assert_eq!(highlights[0].note, "Worth rereading .rust .to-do");
+/// assert_eq!(highlights[0].location, Some(120));
+/// assert_eq!(highlights[0].highlight_url, "https://hyp.is/a/example.com/post");
+/// ```
+pub fn highlights(annotations: &[Annotation]) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+    for document in group_by_document(annotations) {
+        for annotation in &document.annotations {
+            let Some(text) = quote(annotation).filter(|_| annotation.references.is_empty()) else {
+                continue;
+            };
+            let (location, location_type) = match (page(annotation), annotation.position()) {
+                (Some(page), _) => (Some(page), Some("page")),
+                (None, Some(position)) => (Some(position), Some("order")),
+                (None, None) => (None, None),
+            };
+            highlights.push(Highlight {
+                text: text.to_owned(),
+                title: document.title.clone(),
+                source_url: annotation.uri.clone(),
+                source_type: "hypothesis".into(),
+                category: "articles".into(),
+                note: note(annotation),
+                location,
+                location_type: location_type.map(String::from),
+                highlighted_at: annotation.created.format(&Rfc3339).unwrap_or_default(),
+                highlight_url: annotation.incontext_link(),
+            });
+        }
+    }
+    highlights
+}
+
+/// Body of a request to the Readwise highlights API creating the highlights among `annotations`
+pub fn to_json(annotations: &[Annotation]) -> serde_json::Value {
+    serde_json::json!({ "highlights": highlights(annotations) })
+}
+
+/// Writes the highlights among `annotations` in Readwise's CSV upload format
+pub fn write_csv(annotations: &[Annotation], writer: impl Write) -> Result<(), HypothesisError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for highlight in highlights(annotations) {
+        writer.serialize(Row {
+            highlight: &highlight.text,
+            title: &highlight.title,
+            author: "",
+            url: &highlight.source_url,
+            note: &highlight.note,
+            location: highlight.location,
+            date: &highlight.highlighted_at,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Annotations as BibTeX entries with notes, to import into Zotero (or other reference managers)
+//! with the annotations attached to the documents they were made on
+//!
+//! Annotations are grouped by document as by
+//! [`group_by_document`](../../annotations/fn.group_by_document.html), and documents with the
+//! same DOI (e.g. a publisher's page and its "doi:" URI) are merged. Each document becomes an
+//! entry whose `annote` field, which Zotero imports as a child note, holds the quotes, texts
+//! and tags of its annotations.
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::export::zotero;
+//! let api = Hypothesis::from_env()?;
+//! let mut query = SearchQuery::builder().user(&api.user().0).tag("literature").build()?;
+//! let annotations = api.search_annotations_return_all(&mut query).await?;
+//! std::fs::write("hypothesis.bib", zotero::to_bibtex(&annotations))?;
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{group_by_document, sort_by_position, Annotation};
+use crate::toc::quote;
+
+/// A document and the annotations on it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// BibTeX key, e.g. "rust2021" for a document titled "Rust ..." annotated in 2021
+    pub key: String,
+    pub title: String,
+    /// Web address of the document if one was annotated, else the URI of its first annotation
+    pub url: String,
+    /// Bare DOI, like "10.1000/xyz123"
+    pub doi: Option<String>,
+    /// In document order
+    pub annotations: Vec<Annotation>,
+}
+
+/// DOI of the document `annotation` was made on, from its "doi:" URI or document metadata
+fn doi(annotation: &Annotation) -> Option<String> {
+    annotation
+        .uri
+        .strip_prefix("doi:")
+        .or_else(|| annotation.document.as_ref()?.doi())
+        .map(str::to_owned)
+}
+
+fn is_web(uri: &str) -> bool {
+    uri.starts_with("https://") || uri.starts_with("http://")
+}
+
+/// The first word of `title` and the year of the first annotation, made unique among `keys`
+/// with a letter suffix
+fn key(title: &str, annotations: &[Annotation], keys: &mut HashSet<String>) -> String {
+    let word: String = title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|word| !word.is_empty())
+        .unwrap_or("annotations")
+        .to_ascii_lowercase();
+    let year = annotations
+        .iter()
+        .map(|annotation| annotation.created.year())
+        .min()
+        .unwrap_or_default();
+    let base = format!("{}{}", word, year);
+    let key = std::iter::once(base.clone())
+        .chain(('b'..='z').map(|suffix| format!("{}{}", base, suffix)))
+        .find(|key| !keys.contains(key))
+        .unwrap_or(base);
+    keys.insert(key.clone());
+    key
+}
+
+/// The documents that `annotations` were made on, in order of first appearance
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::export::zotero::references;
+/// # fn annotation(id: &str, uri: &str, document: serde_json::Value) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": uri, "text": "", "document": document,
+/// #         "tags": [], "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "target": [{"source": uri}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("a", "https://journal.example.com/article/1", serde_json::json!({
+///         "title": ["Rust in science"], "highwire": {"doi": ["10.1000/xyz123"]},
+///     })),
+///     annotation("b", "doi:10.1000/xyz123", serde_json::json!({})),
+///     annotation("c", "https://example.com/rust-blog", serde_json::json!({"title": ["Rust blog"]})),
+/// ];
+/// let references = references(&annotations);
+/// assert_eq!(references.len(), 2);
+/// assert_eq!(references[0].key, "rust2021");
+/// assert_eq!(references[0].doi.as_deref(), Some("10.1000/xyz123"));
+/// assert_eq!(references[0].url, "https://journal.example.com/article/1");
+/// assert_eq!(references[0].annotations.len(), 2);
+/// assert_eq!(references[1].key, "rust2021b");
+/// ```
+pub fn references(annotations: &[Annotation]) -> Vec<Reference> {
+    let mut references: Vec<Reference> = Vec::new();
+    for document in group_by_document(annotations) {
+        let doi = document.annotations.iter().find_map(doi);
+        let url = document
+            .annotations
+            .iter()
+            .map(|annotation| &annotation.uri)
+            .find(|uri| is_web(uri))
+            .unwrap_or(&document.uri)
+            .clone();
+        let same_doi = doi.as_ref().and_then(|doi| {
+            references
+                .iter_mut()
+                .find(|reference| reference.doi.as_ref() == Some(doi))
+        });
+        match same_doi {
+            Some(reference) => {
+                if !is_web(&reference.url) {
+                    reference.url = url;
+                }
+                // a title from metadata beats one made from the URI
+                if reference.title == reference.annotations[0].uri {
+                    reference.title = document.title;
+                }
+                reference.annotations.extend(document.annotations);
+                sort_by_position(&mut reference.annotations);
+            }
+            None => references.push(Reference {
+                key: String::new(),
+                title: document.title,
+                url,
+                doi,
+                annotations: document.annotations,
+            }),
+        }
+    }
+    let mut keys = HashSet::new();
+    for reference in &mut references {
+        reference.key = key(&reference.title, &reference.annotations, &mut keys);
+    }
+    references
+}
+
+/// Plain text note with the quote, text and tags of each annotation of `reference`
+pub fn note(reference: &Reference) -> String {
+    reference
+        .annotations
+        .iter()
+        .map(|annotation| {
+            let mut parts = Vec::new();
+            if let Some(quote) = quote(annotation) {
+                let quote: Vec<_> = quote.lines().map(|line| format!("> {}", line)).collect();
+                parts.push(quote.join("\n"));
+            }
+            if !annotation.text.trim().is_empty() {
+                parts.push(annotation.text.trim().to_owned());
+            }
+            if !annotation.tags.is_empty() {
+                parts.push(format!("Tags: {}", annotation.tags.join(", ")));
+            }
+            parts.join("\n\n")
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Escapes characters with a special meaning in BibTeX values
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '%' | '&' | '#' | '$' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A BibTeX file with an entry for each document that `annotations` were made on
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::export::zotero::to_bibtex;
+/// # let annotation: Annotation = serde_json::from_value(serde_json::json!({
+/// #     "id": "a", "created": "2021-03-04T00:00:00Z", "updated": "2021-03-04T00:00:00Z",
+/// #     "user": "acct:alice@hypothes.is", "uri": "https://example.com/post",
+/// #     "text": "Costs 5% more", "tags": ["pricing"], "group": "__world__",
+/// #     "hidden": false, "flagged": false, "links": {}, "document": {"title": ["A post"]},
+/// #     "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #     "target": [{"source": "https://example.com/post", "selector": [
+/// #         {"type": "TextQuoteSelector", "exact": "The price", "prefix": "", "suffix": ""}]}],
+/// # })).unwrap();
+/// assert_eq!(to_bibtex(&[annotation]), "\
+/// @online{a2021,
+///   title = {A post},
+///   url = {https://example.com/post},
+///   urldate = {2021-03-04},
+///   annote = {> The price
+///
+/// Costs 5\\% more
+///
+/// Tags: pricing},
+/// }
+/// ");
+/// ```
+pub fn to_bibtex(annotations: &[Annotation]) -> String {
+    let mut bibtex = String::new();
+    for (i, reference) in references(annotations).iter().enumerate() {
+        if i > 0 {
+            bibtex.push('\n');
+        }
+        let entry_type = if reference.doi.is_some() {
+            "misc"
+        } else {
+            "online"
+        };
+        bibtex.push_str(&format!("@{}{{{},\n", entry_type, reference.key));
+        bibtex.push_str(&format!("  title = {{{}}},\n", escape(&reference.title)));
+        bibtex.push_str(&format!("  url = {{{}}},\n", escape(&reference.url)));
+        if let Some(doi) = &reference.doi {
+            bibtex.push_str(&format!("  doi = {{{}}},\n", escape(doi)));
+        }
+        if let Some(first) = reference.annotations.iter().map(|a| a.created).min() {
+            bibtex.push_str(&format!("  urldate = {{{}}},\n", first.date()));
+        }
+        let note = note(reference);
+        if !note.is_empty() {
+            bibtex.push_str(&format!("  annote = {{{}}},\n", escape(&note)));
+        }
+        bibtex.push_str("}\n");
+    }
+    bibtex
+}
@@ -0,0 +1,83 @@
+//! Counts by user, tag and group over a set of annotations, like the facets in the sidebar of
+//! the Hypothesis web app's search page
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Annotation;
+use crate::{GroupID, UserAccountID};
+
+/// Number of annotations with a given user, tag or group
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Facet<T> {
+    pub value: T,
+    pub count: usize,
+}
+
+/// Facets of a set of annotations, each list sorted most frequent first
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Facets {
+    pub users: Vec<Facet<UserAccountID>>,
+    pub tags: Vec<Facet<String>>,
+    pub groups: Vec<Facet<GroupID>>,
+}
+
+/// Count the users, tags and groups of `annotations`.
+/// Ties are sorted alphabetically.
+///
+/// # Example
+/// ```
+/// use hypothesis::UserAccountID;
+/// use hypothesis::facets::{facet_counts, Facet};
+/// # fn annotation(user: &str, tags: &[&str]) -> hypothesis::annotations::Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": user, "uri": "https://example.com", "text": "", "tags": tags,
+/// #         "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("acct:alice@hypothes.is", &["rust", "async"]),
+///     annotation("acct:bob@hypothes.is", &["rust"]),
+///     annotation("acct:bob@hypothes.is", &[]),
+/// ];
+/// let facets = facet_counts(&annotations);
+/// assert_eq!(facets.users[0], Facet { value: UserAccountID::new("bob", "hypothes.is"), count: 2 });
+/// assert_eq!(facets.tags[0], Facet { value: "rust".to_owned(), count: 2 });
+/// assert_eq!(facets.tags[1], Facet { value: "async".to_owned(), count: 1 });
+/// assert_eq!(facets.groups, vec![Facet { value: "__world__".into(), count: 3 }]);
+/// ```
+pub fn facet_counts(annotations: &[Annotation]) -> Facets {
+    Facets {
+        users: count(annotations.iter().map(|a| &a.user), |user| &user.0),
+        tags: count(annotations.iter().flat_map(|a| &a.tags), |tag| tag),
+        groups: count(annotations.iter().map(|a| &a.group), |group| group),
+    }
+}
+
+/// Facets of `values`, most frequent first, then by `name`
+pub(crate) fn count<'a, T: Hash + Eq + Clone + 'a>(
+    values: impl Iterator<Item = &'a T>,
+    name: impl Fn(&T) -> &str,
+) -> Vec<Facet<T>> {
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    let mut facets: Vec<_> = counts
+        .into_iter()
+        .map(|(value, count)| Facet {
+            value: value.clone(),
+            count,
+        })
+        .collect();
+    facets.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| name(&a.value).cmp(name(&b.value)))
+    });
+    facets
+}
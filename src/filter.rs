@@ -0,0 +1,230 @@
+//! Composable predicates over annotations, for filtering search results on the client where the
+//! API's filters aren't enough (regular expressions, subdomains, time ranges, ...)
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use futures::TryStreamExt;
+//! use regex::Regex;
+//! use time::{Duration, OffsetDateTime};
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::filter::Filter;
+//! let api = Hypothesis::from_env()?;
+//! let filter = Filter::tag_matches(Regex::new("^week-[0-9]+$")?)
+//!     .and(Filter::uri_host("example.com"))
+//!     .and(Filter::created_after(OffsetDateTime::now_utc() - Duration::days(30)));
+//! let query = SearchQuery::builder().user(&api.user().0).limit(200).build()?;
+//! let annotations: Vec<_> = filter.filter_stream(api.stream_search(&query)).try_collect().await?;
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::ops::Not;
+use std::sync::Arc;
+
+use futures::{future, Stream, StreamExt};
+use regex::Regex;
+use time::OffsetDateTime;
+use url::Url;
+
+use crate::annotations::Annotation;
+use crate::toc::quote;
+use crate::uri::equivalent;
+use crate::{GroupID, UserAccountID};
+
+/// A predicate over annotations, cheap to clone
+#[derive(Clone)]
+pub struct Filter(Arc<dyn Fn(&Annotation) -> bool + Send + Sync>);
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Filter")
+    }
+}
+
+impl Filter {
+    /// A filter with a custom `predicate`
+    pub fn new(predicate: impl Fn(&Annotation) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(predicate))
+    }
+
+    /// A filter that lets every annotation through
+    pub fn all() -> Self {
+        Self::new(|_| true)
+    }
+
+    /// True if `annotation` passes the filter
+    pub fn matches(&self, annotation: &Annotation) -> bool {
+        (self.0)(annotation)
+    }
+
+    /// Annotations passing both this filter and `other`
+    pub fn and(self, other: Filter) -> Self {
+        Self::new(move |annotation| self.matches(annotation) && other.matches(annotation))
+    }
+
+    /// Annotations passing this filter or `other`
+    pub fn or(self, other: Filter) -> Self {
+        Self::new(move |annotation| self.matches(annotation) || other.matches(annotation))
+    }
+
+    /// Annotations with `tag`
+    pub fn tag(tag: &str) -> Self {
+        let tag = tag.to_owned();
+        Self::new(move |annotation| annotation.tags.contains(&tag))
+    }
+
+    /// Annotations with a tag matching `pattern`
+    pub fn tag_matches(pattern: Regex) -> Self {
+        Self::new(move |annotation| annotation.tags.iter().any(|tag| pattern.is_match(tag)))
+    }
+
+    /// Annotations whose text matches `pattern`
+    pub fn text_matches(pattern: Regex) -> Self {
+        Self::new(move |annotation| pattern.is_match(&annotation.text))
+    }
+
+    /// Annotations whose quote matches `pattern`
+    pub fn quote_matches(pattern: Regex) -> Self {
+        Self::new(move |annotation| quote(annotation).is_some_and(|quote| pattern.is_match(quote)))
+    }
+
+    /// Annotations whose quote, text or a tag matches `pattern`
+    pub fn any_matches(pattern: Regex) -> Self {
+        Self::new(move |annotation| {
+            quote(annotation)
+                .into_iter()
+                .chain([annotation.text.as_str()])
+                .chain(annotation.tags.iter().map(String::as_str))
+                .any(|field| pattern.is_match(field))
+        })
+    }
+
+    /// Annotations by `user`
+    pub fn user(user: UserAccountID) -> Self {
+        Self::new(move |annotation| annotation.user == user)
+    }
+
+    /// Annotations in `group`
+    pub fn group(group: impl Into<GroupID>) -> Self {
+        let group = group.into();
+        Self::new(move |annotation| annotation.group == group)
+    }
+
+    /// Annotations on web pages of `host` or its subdomains, ignoring case
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::filter::Filter;
+    /// # fn annotation(uri: &str) -> hypothesis::annotations::Annotation {
+    /// #     serde_json::from_value(serde_json::json!({
+    /// #         "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #         "user": "acct:alice@hypothes.is", "uri": uri, "text": "", "tags": [],
+    /// #         "group": "__world__", "hidden": false, "flagged": false, "links": {}, "target": [],
+    /// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+    /// #     })).unwrap()
+    /// # }
+    /// let filter = Filter::uri_host("example.com");
+    /// assert!(filter.matches(&annotation("https://blog.Example.com/post")));
+    /// assert!(!filter.matches(&annotation("https://notexample.com/post")));
+    /// assert!(!filter.matches(&annotation("urn:x-pdf:abc123")));
+    /// ```
+    pub fn uri_host(host: &str) -> Self {
+        let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+        Self::new(move |annotation| {
+            let Ok(url) = Url::parse(annotation.uri.trim()) else {
+                return false;
+            };
+            url.host_str().is_some_and(|annotated| {
+                annotated == host
+                    || annotated
+                        .strip_suffix(host.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            })
+        })
+    }
+
+    /// Annotations on `uri`, compared as by [`equivalent`](../uri/fn.equivalent.html)
+    pub fn uri(uri: &str) -> Self {
+        let uri = uri.to_owned();
+        Self::new(move |annotation| equivalent(&uri, &annotation.uri))
+    }
+
+    /// Annotations created at or after `time`
+    pub fn created_after(time: OffsetDateTime) -> Self {
+        Self::new(move |annotation| annotation.created >= time)
+    }
+
+    /// Annotations created before `time`
+    pub fn created_before(time: OffsetDateTime) -> Self {
+        Self::new(move |annotation| annotation.created < time)
+    }
+
+    /// Annotations updated at or after `time`
+    pub fn updated_after(time: OffsetDateTime) -> Self {
+        Self::new(move |annotation| annotation.updated >= time)
+    }
+
+    /// Replies to other annotations
+    pub fn is_reply() -> Self {
+        Self::new(|annotation| !annotation.references.is_empty())
+    }
+
+    /// The `annotations` that pass the filter
+    ///
+    /// # Example
+    /// ```
+    /// use regex::Regex;
+    /// use hypothesis::filter::Filter;
+    /// # fn annotation(id: &str, text: &str, tags: &[&str]) -> hypothesis::annotations::Annotation {
+    /// #     serde_json::from_value(serde_json::json!({
+    /// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": text,
+    /// #         "tags": tags, "group": "__world__", "hidden": false, "flagged": false, "links": {},
+    /// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// #     })).unwrap()
+    /// # }
+    /// let annotations = vec![
+    ///     annotation("a", "TODO: check", &["week-1"]),
+    ///     annotation("b", "Done", &["week-2"]),
+    ///     annotation("c", "TODO: cite", &["misc"]),
+    /// ];
+    /// let filter = Filter::tag_matches(Regex::new("^week-").unwrap())
+    ///     .and(!Filter::text_matches(Regex::new("^Done").unwrap()))
+    ///     .or(Filter::tag("misc"));
+    /// let ids: Vec<_> = filter.apply(annotations).into_iter().map(|a| a.id).collect();
+    /// assert_eq!(ids, vec!["a".into(), "c".into()]);
+    /// ```
+    pub fn apply(&self, annotations: Vec<Annotation>) -> Vec<Annotation> {
+        annotations
+            .into_iter()
+            .filter(|annotation| self.matches(annotation))
+            .collect()
+    }
+
+    /// The annotations of `stream` that pass the filter, with errors passed through
+    pub fn filter_stream<'a, E: 'a>(
+        &self,
+        stream: impl Stream<Item = Result<Annotation, E>> + 'a,
+    ) -> impl Stream<Item = Result<Annotation, E>> + 'a {
+        let filter = self.clone();
+        stream.filter(move |item| {
+            future::ready(match item {
+                Ok(annotation) => filter.matches(annotation),
+                Err(_) => true,
+            })
+        })
+    }
+}
+
+impl Not for Filter {
+    type Output = Filter;
+
+    /// Annotations not passing the filter
+    fn not(self) -> Self::Output {
+        Self::new(move |annotation| !self.matches(annotation))
+    }
+}
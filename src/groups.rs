@@ -1,13 +1,16 @@
 //! Objects related to the "groups" endpoint
 
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "cli")]
 use structopt::StructOpt;
 
-use crate::is_default;
+use crate::policy::wildcard_matches;
+use crate::{is_default, GroupID};
 
 /// Which field to expand
-#[derive(Serialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Expand {
     /// Expand `organization` field to `Org`
@@ -17,8 +20,18 @@ pub enum Expand {
 }
 
 /// Filter groups by authority and target document
+///
+/// # Example
+/// ```
+/// use hypothesis::groups::{Expand, GroupFilters};
+/// let filters: GroupFilters = serde_json::from_str(r#"{"expand": ["scopes"]}"#).unwrap();
+/// assert_eq!(filters.expand, vec![Expand::Scopes]);
+/// let json = serde_json::to_string(&filters).unwrap();
+/// assert_eq!(serde_json::from_str::<GroupFilters>(&json).unwrap(), filters);
+/// ```
 #[cfg_attr(feature = "cli", derive(StructOpt))]
-#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
 pub struct GroupFilters {
     /// Filter returned groups to this authority.
     /// For authenticated requests, the user's associated authority will supersede any provided value.
@@ -38,21 +51,79 @@ pub struct GroupFilters {
     pub expand: Vec<Expand>,
 }
 
-/// URL to the group's main (activity) page
+/// Links of a group
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Links {
     /// URL to the group's main (activity) page
     #[serde(default)]
     pub html: Option<String>,
+    /// Invite link that others can follow to join the group, if you may share it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<String>,
+    /// Any other links, by name
+    #[serde(flatten)]
+    pub other: HashMap<String, String>,
+}
+
+/// The ID of the group that `id_or_invite` is, or that it invites to.
+///
+/// Invite links are group pages (`https://hypothes.is/groups/<id>/<slug>`), which is what the
+/// Hypothesis web app shows under "Invite new members".
+///
+/// # Example
+/// ```
+/// use hypothesis::groups::group_id_from_invite;
+/// assert_eq!(
+///     group_id_from_invite("https://hypothes.is/groups/Jx3nbqV9/reading-group").unwrap(),
+///     "Jx3nbqV9".into()
+/// );
+/// assert_eq!(group_id_from_invite("Jx3nbqV9").unwrap(), "Jx3nbqV9".into());
+/// assert!(group_id_from_invite("https://hypothes.is/users/alice").is_none());
+/// ```
+pub fn group_id_from_invite(id_or_invite: &str) -> Option<GroupID> {
+    let id_or_invite = id_or_invite.trim();
+    if !id_or_invite.contains('/') {
+        return Some(id_or_invite)
+            .filter(|id| !id.is_empty())
+            .map(GroupID::from);
+    }
+    let url = url::Url::parse(id_or_invite).ok()?;
+    let mut segments = url.path_segments()?;
+    match (segments.next(), segments.next()) {
+        (Some("groups"), Some(id)) if !id.is_empty() => Some(id.into()),
+        _ => None,
+    }
 }
 
 /// See [the Hypothesis API docs](https://h.readthedocs.io/en/latest/api-reference/v1/#tag/groups/paths/~1groups/get) for more information.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Scope {
+    /// Whether annotations can only be made on documents matching `uri_patterns`
     pub enforced: bool,
+    /// URL patterns, with `*` as wildcard
     pub uri_patterns: Vec<String>,
 }
 
+impl Scope {
+    /// True if annotations on `uri` are allowed: the scope isn't enforced or `uri` matches one
+    /// of its patterns
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::groups::Scope;
+    /// let scope = Scope { enforced: true, uri_patterns: vec!["https://example.com/course/*".into()] };
+    /// assert!(scope.allows("https://example.com/course/week-1"));
+    /// assert!(!scope.allows("https://example.com/blog"));
+    /// ```
+    pub fn allows(&self, uri: &str) -> bool {
+        !self.enforced
+            || self
+                .uri_patterns
+                .iter()
+                .any(|pattern| wildcard_matches(pattern, uri.trim()))
+    }
+}
+
 /// Group type
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -95,7 +166,7 @@ pub struct Org {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Group {
     /// Group ID
-    pub id: String,
+    pub id: GroupID,
     /// Authority-unique identifier that may be set for groups that are owned by a third-party authority.
     /// This field is currently present but unused for first-party-authority groups.
     pub groupid: Option<String>,
@@ -116,6 +187,49 @@ pub struct Group {
     pub group_type: Type,
 }
 
+impl Group {
+    /// The group's organization, if it was expanded (see [`Expand::Organization`]) and is
+    /// visible to you
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::groups::Group;
+    /// let group: Group = serde_json::from_value(serde_json::json!({
+    ///     "id": "Jx3nbqV9", "groupid": null, "name": "Reading group", "links": {},
+    ///     "organization": {"id": "__default__", "default": true, "logo": null, "name": "Hypothesis"},
+    ///     "scopes": {"enforced": true, "uri_patterns": ["https://example.com/*"]},
+    ///     "scoped": true, "type": "restricted",
+    /// })).unwrap();
+    /// assert_eq!(group.org().unwrap().name, "Hypothesis");
+    /// assert_eq!(group.organization_id(), Some("__default__"));
+    /// assert!(group.scopes.unwrap().enforced);
+    /// ```
+    pub fn org(&self) -> Option<&Org> {
+        match &self.organization {
+            Organization::Organization(org) => org.as_ref(),
+            Organization::String(_) => None,
+        }
+    }
+
+    /// True if annotations on `uri` may be made in this group, judging by its scopes.
+    /// Without scopes (e.g. if they weren't expanded, see [`Expand::Scopes`]) this is only
+    /// false if the group is scoped.
+    pub fn allows_uri(&self, uri: &str) -> bool {
+        match &self.scopes {
+            Some(scope) => scope.allows(uri),
+            None => !self.scoped,
+        }
+    }
+
+    /// ID of the group's organization, whether it was expanded or not
+    pub fn organization_id(&self) -> Option<&str> {
+        match &self.organization {
+            Organization::String(id) => Some(id),
+            Organization::Organization(org) => org.as_ref().map(|org| org.id.as_str()),
+        }
+    }
+}
+
 /// Information about another user
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Member {
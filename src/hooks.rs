@@ -0,0 +1,96 @@
+//! Observe every API call the client makes, e.g. to log slow requests, and the search results
+//! that had to be skipped because they couldn't be read
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use hypothesis::Hypothesis;
+//! let mut api = Hypothesis::new("username", "developer_key")?;
+//! api.set_request_hook(|info| {
+//!     eprintln!("{} {} -> {:?} in {:?}", info.method, info.url, info.status, info.latency)
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode, Url};
+
+use crate::ApiVersion;
+
+/// What the request hook is told about each API call
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestInfo {
+    pub method: Method,
+    pub url: Url,
+    /// None if no response was received (e.g. connection errors)
+    pub status: Option<StatusCode>,
+    /// Time until the response headers were received
+    pub latency: Duration,
+    /// API version the response is in, from its `Hypothesis-Media-Type` header
+    pub api_version: Option<ApiVersion>,
+}
+
+/// Path segments kept as-is by [`RequestInfo::endpoint`], all others are IDs
+const ENDPOINT_SEGMENTS: &[&str] = &[
+    "annotations",
+    "flag",
+    "groups",
+    "hide",
+    "me",
+    "members",
+    "profile",
+    "search",
+    "token",
+    "users",
+];
+
+impl RequestInfo {
+    /// The path below `/api` with IDs replaced by ":id", e.g. "/annotations/:id/flag"
+    pub fn endpoint(&self) -> String {
+        endpoint(self.url.path())
+    }
+}
+
+/// `path` below `/api` with IDs replaced by ":id"
+pub(crate) fn endpoint(path: &str) -> String {
+    path.trim_start_matches("/api")
+        .split('/')
+        .map(|segment| {
+            if segment.is_empty() || ENDPOINT_SEGMENTS.contains(&segment) {
+                segment
+            } else {
+                ":id"
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The IDs in `path` below `/api`, i.e. the segments that [`endpoint`] replaces
+pub(crate) fn path_ids(path: &str) -> Vec<String> {
+    path.trim_start_matches("/api")
+        .split('/')
+        .filter(|segment| !segment.is_empty() && !ENDPOINT_SEGMENTS.contains(segment))
+        .map(String::from)
+        .collect()
+}
+
+/// Called after every API call, see [`Hypothesis::set_request_hook`](../struct.Hypothesis.html#method.set_request_hook)
+pub type RequestHook = Arc<dyn Fn(&RequestInfo) + Send + Sync>;
+
+/// A row of search results that couldn't be read as an annotation and was left out
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedRow {
+    /// The row's `id`, if it has one
+    pub id: Option<String>,
+    /// Why the row couldn't be read
+    pub error: String,
+    /// The row as the API returned it
+    pub row: serde_json::Value,
+}
+
+/// Called for every skipped search result, see [`Hypothesis::set_skip_hook`](../struct.Hypothesis.html#method.set_skip_hook)
+pub type SkipHook = Arc<dyn Fn(&SkippedRow) + Send + Sync>;
@@ -0,0 +1,72 @@
+//! Objects related to the API root ("index") endpoint
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ApiVersion;
+
+/// An endpoint of the API
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Link {
+    /// e.g. "GET"
+    pub method: String,
+    /// URL template, e.g. "https://api.hypothes.is/api/annotations/:id"
+    pub url: String,
+    #[serde(default)]
+    pub desc: String,
+}
+
+/// A link, or a group of named links (e.g. "create", "read", ... for "annotation")
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Links {
+    Link(Link),
+    Group(BTreeMap<String, Links>),
+}
+
+/// The service's description of itself, useful to check what a (self-hosted) server supports
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiIndex {
+    #[serde(default)]
+    pub message: String,
+    pub links: BTreeMap<String, Links>,
+    /// From the `Hypothesis-Media-Type` header of the response
+    #[serde(skip)]
+    pub api_version: Option<ApiVersion>,
+}
+
+impl ApiIndex {
+    /// The link at a dot-separated `path` like "annotation.create", None if the server doesn't have it
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::index::ApiIndex;
+    /// let index: ApiIndex = serde_json::from_str(r#"{
+    ///     "message": "Annotator Store API",
+    ///     "links": {
+    ///         "annotation": {
+    ///             "create": {"method": "POST", "url": "https://api.hypothes.is/api/annotations", "desc": "Create an annotation"}
+    ///         },
+    ///         "search": {"method": "GET", "url": "https://api.hypothes.is/api/search", "desc": "Search for annotations"}
+    ///     }
+    /// }"#).unwrap();
+    /// assert_eq!(index.link("annotation.create").unwrap().method, "POST");
+    /// assert!(index.link("search").is_some());
+    /// assert!(index.link("annotation.hide").is_none());
+    /// ```
+    pub fn link(&self, path: &str) -> Option<&Link> {
+        let mut segments = path.split('.');
+        let mut links = self.links.get(segments.next()?)?;
+        for segment in segments {
+            match links {
+                Links::Group(group) => links = group.get(segment)?,
+                Links::Link(_) => return None,
+            }
+        }
+        match links {
+            Links::Link(link) => Some(link),
+            Links::Group(_) => None,
+        }
+    }
+}
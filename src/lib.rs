@@ -12,6 +12,9 @@
 //! * annotations (create / update / delete / search / fetch / flag),
 //! * groups (create / update / list / fetch / leave / members)
 //! * profile (user information / groups)
+//! * users (create / update / fetch, for third-party authorities)
+//!
+//! as well as tag management helpers (rename / merge / bulk add / bulk remove).
 //!
 //! ## Installation and Usage
 //! ### Authorization
@@ -35,10 +38,11 @@
 //! Add to your Cargo.toml:
 //! ```toml
 //! [dependencies]
-//! hypothesis = {version = "0.4.0", default-features = false}
+//! hypothesis = {version = "0.4.0", default-features = false, features = ["native-tls"]}
 //! # For a tokio runtime:
 //! tokio = { version = "0.2", features = ["macros"] }
 //! ```
+//! Replace `native-tls` with `rustls` to use [rustls](https://github.com/rustls/rustls) for TLS instead.
 //!
 //! #### Examples
 //! ```rust no_run
@@ -75,9 +79,7 @@
 //! Make sure you have a .env file (added to .gitignore) in the repo root with HYPOTHESIS_NAME, HYPOTHESIS_KEY, and TEST_GROUP_ID
 //!
 //! ### Caveats / Todo:
-//! - Only supports APIKey authorization and hypothes.is authority (i.e. single users).
 //! - `Target.selector.RangeSelector` doesn't seem to follow [W3C standards](https://www.w3.org/TR/annotation-model/#range-selector). It's just a hashmap for now.
-//! - `Annotation` hypermedia links are stored as a hashmap, b/c I don't know all the possible values.
 //! - Need to figure out how `Document` works to properly document it (hah).
 //! - Can't delete a group after making it, can leave it though (maybe it's the same thing?)
 //! - No idea what `UserProfile.preferences` and `UserProfile.features` mean.
@@ -85,54 +87,387 @@
 #[macro_use]
 extern crate derive_builder;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Deref;
 use std::str::FromStr;
 use std::string::ParseError;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{env, fmt};
 
 use futures::future::try_join_all;
-use reqwest::{header, Url};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use reqwest::{header, Method, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use time::format_description::well_known::Rfc3339;
+use tokio::sync::Mutex;
 
-use crate::annotations::{Annotation, InputAnnotation, SearchQuery};
+use crate::annotations::{
+    find_duplicates, AdaptivePaging, Annotation, DedupeStrategy, DeletionResult, InputAnnotation,
+    ModerationAction, ModerationResult, PageCursor, SearchPage, SearchQuery,
+};
+use crate::auth::{AuthorityClient, Credentials, OAuthToken};
 use crate::errors::HypothesisError;
 use crate::groups::{Expand, Group, GroupFilters, Member};
+use crate::hooks::{RequestHook, RequestInfo, SkipHook, SkippedRow};
+use crate::index::ApiIndex;
+use crate::policy::UrlPolicy;
 use crate::profile::UserProfile;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::quota::{QuotaTracker, QuotaUsage};
+use crate::retry::{RetryPolicy, Throttle};
+use crate::users::{NewUser, User};
 
+pub mod analytics;
 pub mod annotations;
+pub mod auth;
+pub mod broadcast;
+pub mod bulk;
 #[cfg(feature = "cli")]
 pub mod cli;
+pub mod diff;
+pub mod digest;
+pub mod enrich;
 pub mod errors;
+pub mod export;
+pub mod facets;
+pub mod filter;
 pub mod groups;
+pub mod hooks;
+pub mod index;
+pub mod links;
+pub mod mapping;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mirror;
+pub mod output;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod policy;
 pub mod profile;
+pub mod progress;
+pub mod quota;
+pub mod realtime;
+pub mod replace;
+pub mod resume;
+pub mod retention;
+pub mod retry;
+pub mod roster;
+pub mod store;
+pub mod tags;
+pub mod template;
+pub mod testing;
+pub mod threads;
+pub mod toc;
+pub mod uri;
+pub mod users;
+pub mod w3c;
 
 /// Hypothesis API URL
 pub const API_URL: &str = "https://api.hypothes.is/api";
 
+/// Authority of hypothes.is user accounts
+pub const DEFAULT_AUTHORITY: &str = "hypothes.is";
+
+/// Result of a conditional request such as [`Hypothesis::fetch_annotation_conditional`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conditional<T> {
+    /// The resource changed (or no validator was sent), along with its new validators
+    Modified {
+        value: T,
+        /// From the `ETag` header, to send with the next request
+        etag: Option<String>,
+        /// From the `Last-Modified` header
+        last_modified: Option<String>,
+    },
+    /// The resource still matches the validator that was sent
+    NotModified,
+}
+
+/// Version of the Hypothesis API, requested with the `Accept` header of each call
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    /// e.g. "application/vnd.hypothesis.v1+json"
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Self::V1 => "application/vnd.hypothesis.v1+json",
+            Self::V2 => "application/vnd.hypothesis.v2+json",
+        }
+    }
+
+    /// Parses a media type like the one in the `Hypothesis-Media-Type` header of responses
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::ApiVersion;
+    /// assert_eq!(ApiVersion::from_media_type("application/vnd.hypothesis.v2+json"), Some(ApiVersion::V2));
+    /// assert_eq!(ApiVersion::from_media_type("application/json"), None);
+    /// ```
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        [Self::V1, Self::V2]
+            .into_iter()
+            .find(|version| version.media_type() == media_type.trim())
+    }
+}
+
 /// checks if a variable is the default value of its type
 fn is_default<T: Default + PartialEq>(t: &T) -> bool {
     t == &T::default()
 }
 
 pub fn serde_parse<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T, errors::HypothesisError> {
-    serde_json::from_str::<T>(text).map_err(|e| errors::HypothesisError::APIError {
+    serde_json::from_str::<T>(text).map_err(|e| parse_error(text, e))
+}
+
+/// Error for a response body `text` that couldn't be deserialized
+fn parse_error(text: &str, error: serde_json::Error) -> HypothesisError {
+    HypothesisError::APIError {
         source: serde_json::from_str::<errors::APIError>(text).unwrap_or_default(),
-        serde_error: Some(e),
+        serde_error: Some(error),
         raw_text: text.to_owned(),
-    })
+        request_id: None,
+        context: None,
+    }
+}
+
+/// No body or query for [`Hypothesis::call`]
+const NONE: Option<&()> = None;
+
+/// Largest `offset` the search API accepts
+pub const MAX_OFFSET: usize = 9800;
+
+/// Progress of [`Hypothesis::stream_search_prefetch`]
+struct PrefetchState<'a> {
+    /// `search_after` is the cursor of the current window
+    query: SearchQuery,
+    /// Pages by offset from the cursor, fetched ahead
+    window: Option<BoxStream<'a, Result<SearchPage, HypothesisError>>>,
+    /// Rest of the page being consumed
+    page: VecDeque<Annotation>,
+    /// Where the next window starts
+    cursor: PageCursor,
+    done: bool,
+}
+
+/// Flattens the fields of `query` into URL parameters, repeating the parameter for each item of
+/// a list (e.g. `expand=organization&expand=scopes`)
+fn query_params(
+    query: &(impl Serialize + ?Sized),
+) -> Result<Vec<(String, String)>, HypothesisError> {
+    let query: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&serde_json::to_string(query).map_err(HypothesisError::SerdeError)?)
+            .map_err(HypothesisError::SerdeError)?;
+    let param = |value: &serde_json::Value| match value {
+        serde_json::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    Ok(query
+        .into_iter()
+        .flat_map(|(k, v)| match v {
+            serde_json::Value::Array(values) => values
+                .iter()
+                .map(|value| (k.clone(), param(value)))
+                .collect(),
+            v => vec![(k, param(&v))],
+        })
+        .collect())
+}
+
+/// The HTTP client for API calls, trusting `root_certificates` in addition to the system's and
+/// asking for compressed responses if `compression` is set (and the "gzip" or "brotli" feature
+/// is enabled)
+fn build_client(
+    root_certificates: &[reqwest::Certificate],
+    compression: bool,
+) -> Result<reqwest::Client, HypothesisError> {
+    let builder = root_certificates
+        .iter()
+        .fold(reqwest::Client::builder(), |builder, certificate| {
+            builder.add_root_certificate(certificate.clone())
+        });
+    #[cfg(feature = "gzip")]
+    let builder = builder.gzip(compression);
+    #[cfg(feature = "brotli")]
+    let builder = builder.brotli(compression);
+    #[cfg(not(any(feature = "gzip", feature = "brotli")))]
+    let _ = compression;
+    builder.build().map_err(HypothesisError::ReqwestError)
+}
+
+/// Body of an API response, along with the ID the server gave the request
+struct ApiResponse {
+    method: Method,
+    url: url::Url,
+    status: StatusCode,
+    text: String,
+    /// From the `X-Request-Id` header
+    request_id: Option<String>,
+    /// From the `Hypothesis-Media-Type` header
+    api_version: Option<ApiVersion>,
+    /// From the `ETag` header
+    etag: Option<String>,
+    /// From the `Last-Modified` header
+    last_modified: Option<String>,
+    /// From the `Retry-After` header, in seconds
+    retry_after: Option<Duration>,
+}
+
+impl ApiResponse {
+    /// Deserializes the body of a successful response, attaching the request ID to errors
+    fn parse<T: DeserializeOwned>(&self) -> Result<T, HypothesisError> {
+        self.parse_with(|text| serde_json::from_str(text))
+    }
+
+    /// Like [`parse`](Self::parse), with a custom parser of the body
+    fn parse_with<T>(
+        &self,
+        parse: impl FnOnce(&str) -> Result<T, serde_json::Error>,
+    ) -> Result<T, HypothesisError> {
+        if let Some(error) = self.status_error() {
+            return Err(error);
+        }
+        // e.g. 204 No Content
+        let text = if self.text.trim().is_empty() {
+            "null"
+        } else {
+            &self.text
+        };
+        parse(text).map_err(|e| self.with_context(parse_error(text, e)))
+    }
+
+    /// For endpoints without a result: fails if the status isn't 2xx
+    fn check(self) -> Result<(), HypothesisError> {
+        match self.status_error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Typed error for 4xx / 5xx responses, None on success
+    fn status_error(&self) -> Option<HypothesisError> {
+        if self.status.is_success() {
+            return None;
+        }
+        // HTML error pages etc. don't parse, the raw text is kept in that case
+        let source = serde_json::from_str::<errors::APIError>(&self.text).unwrap_or_default();
+        let request_id = self.request_id.clone();
+        let context = Some(self.context());
+        Some(match self.status {
+            StatusCode::NOT_FOUND => HypothesisError::NotFound {
+                source,
+                request_id,
+                context,
+            },
+            StatusCode::FORBIDDEN => HypothesisError::Forbidden {
+                source,
+                request_id,
+                context,
+            },
+            status => HypothesisError::StatusError {
+                status: status.as_u16(),
+                source,
+                raw_text: self.text.clone(),
+                request_id,
+                context,
+            },
+        })
+    }
+
+    /// The call this is the response to
+    fn context(&self) -> Box<errors::RequestContext> {
+        Box::new(errors::RequestContext {
+            method: self.method.to_string(),
+            url: self.url.to_string(),
+            status: self.status.as_u16(),
+        })
+    }
+
+    /// Attaches the request ID and the call to a parse error
+    fn with_context(&self, mut error: HypothesisError) -> HypothesisError {
+        if let HypothesisError::APIError {
+            request_id,
+            context,
+            ..
+        } = &mut error
+        {
+            request_id.clone_from(&self.request_id);
+            *context = Some(self.context());
+        }
+        error
+    }
 }
 
 /// Hypothesis API client
+///
+/// Clones are cheap and share the connection pool, the credentials (so an OAuth token refreshed
+/// by one is used by all), the [quota](Hypothesis::quota_usage) count and the settings made so
+/// far. Settings changed later (hooks, URL policy, retry policy, ...) only apply to the client
+/// they are changed on and to clones made after. Clone one client for each task instead of making
+/// a new client per task, which would open new connections for each.
+///
+/// # Example
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hypothesis::Hypothesis;
+/// let api = Hypothesis::from_env()?;
+/// let tasks: Vec<_> = ["group1", "group2"]
+///     .into_iter()
+///     .map(|group| {
+///         let api = api.clone();
+///         tokio::spawn(async move { api.fetch_group(&group.into(), Vec::new()).await })
+///     })
+///     .collect();
+/// for task in tasks {
+///     println!("{}", task.await??.name);
+/// }
+/// println!("{} calls", api.quota_usage().total.calls);
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
 pub struct Hypothesis {
-    /// Authenticated user
-    pub username: String,
-    /// "acct:{username}@hypothes.is"
-    pub user: UserAccountID,
-    /// authorized reqwest async client
+    /// Settings, copied on write by the setters
+    config: Arc<Config>,
+    /// Developer key or OAuth token sent with each request
+    credentials: Arc<Mutex<Credentials>>,
+    /// Counts the API calls made
+    quota: Arc<std::sync::Mutex<QuotaTracker>>,
+    /// Spaces calls `retry_policy.min_interval` apart
+    throttle: Arc<Throttle>,
+    /// reqwest async client
     client: reqwest::Client,
 }
 
+/// Settings of a [`Hypothesis`] client, shared by its clones until one of them changes a setting
+#[derive(Clone)]
+struct Config {
+    username: String,
+    user: UserAccountID,
+    authority: String,
+    url_policy: UrlPolicy,
+    api_version: ApiVersion,
+    api_url: String,
+    /// Called after every API call
+    request_hook: Option<RequestHook>,
+    /// Called for every search result that couldn't be read
+    skip_hook: Option<SkipHook>,
+    /// Trusted in addition to the system's root certificates
+    root_certificates: Vec<reqwest::Certificate>,
+    /// Whether compressed responses are asked for, see [`Hypothesis::set_compression`]
+    compression: bool,
+    /// Retries of failed calls, see [`Hypothesis::set_retry_policy`]
+    retry_policy: RetryPolicy,
+}
+
 impl Hypothesis {
     /// Make a new Hypothesis client with your username and developer key
     /// (see [here](https://h.readthedocs.io/en/latest/api/authorization/) on how to get one)
@@ -148,33 +483,519 @@ impl Hypothesis {
     /// # }
     /// ```
     pub fn new(username: &str, developer_key: &str) -> Result<Self, HypothesisError> {
-        let user = UserAccountID::from_str(username).expect("This should never error");
+        Self::with_authority(username, developer_key, DEFAULT_AUTHORITY)
+    }
+
+    /// Make a new Hypothesis client without credentials, for read-only access to public
+    /// annotations and groups (e.g. to search `__world__`)
+    ///
+    /// `api.user()` is empty, so calls that need a user (creating annotations, fetching private
+    /// groups, etc.) fail, and [`Hypothesis::verify_auth`] returns an error.
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let api = Hypothesis::anonymous()?;
+    /// assert!(api.is_anonymous());
+    /// let query = SearchQuery::builder().uri("https://example.com").build()?;
+    /// let annotations = api.search_annotations(&query).await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn anonymous() -> Result<Self, HypothesisError> {
+        let mut api = Self::with_credentials(UserAccountID(String::new()), Credentials::Anonymous)?;
+        api.config_mut().authority = DEFAULT_AUTHORITY.into();
+        Ok(api)
+    }
+
+    /// True if the client was made with [`Hypothesis::anonymous`]
+    pub fn is_anonymous(&self) -> bool {
+        self.config.user.0.is_empty()
+    }
+
+    /// Authenticated user
+    pub fn username(&self) -> &str {
+        &self.config.username
+    }
+
+    /// "acct:{username}@{authority}", empty for [anonymous](Hypothesis::anonymous) clients
+    pub fn user(&self) -> &UserAccountID {
+        &self.config.user
+    }
+
+    /// Authority the user belongs to, "hypothes.is" unless set with [`Hypothesis::with_authority`]
+    pub fn authority(&self) -> &str {
+        &self.config.authority
+    }
+
+    /// URLs that this client may create annotations on, see [`UrlPolicy`](policy/struct.UrlPolicy.html)
+    pub fn url_policy(&self) -> &UrlPolicy {
+        &self.config.url_policy
+    }
+
+    /// Check the URI of every annotation created or updated from now on against `policy`
+    pub fn set_url_policy(&mut self, policy: UrlPolicy) {
+        self.config_mut().url_policy = policy;
+    }
+
+    /// API version asked for in every call
+    pub fn api_version(&self) -> ApiVersion {
+        self.config.api_version
+    }
+
+    /// Ask for `version` of the API in every call from now on
+    pub fn set_api_version(&mut self, version: ApiVersion) {
+        self.config_mut().api_version = version;
+    }
+
+    /// Base URL of the API, [`API_URL`] unless the client talks to a self-hosted server
+    pub fn api_url(&self) -> &str {
+        &self.config.api_url
+    }
+
+    /// Send calls from now on to the API at `url`, e.g. a self-hosted server
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::{Hypothesis, API_URL};
+    /// let mut api = Hypothesis::new("username", "developer_key")?;
+    /// let before = api.clone();
+    /// api.set_api_url("https://annotations.example.com/api/");
+    /// assert_eq!(api.api_url(), "https://annotations.example.com/api");
+    /// assert_eq!(before.api_url(), API_URL);
+    /// assert_eq!(api.clone().api_url(), api.api_url());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_api_url(&mut self, url: &str) {
+        self.config_mut().api_url = url.trim_end_matches('/').to_owned();
+    }
+
+    /// The settings, copied first if clones share them
+    fn config_mut(&mut self) -> &mut Config {
+        Arc::make_mut(&mut self.config)
+    }
+
+    /// Make a new Hypothesis client for a user of a third-party authority
+    /// (e.g. a publisher or LMS integration running its own authority)
+    ///
+    /// `api.user()` and thus search filters and the ownership of created annotations
+    /// resolve to "acct:{username}@{authority}".
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::with_authority("student_1", "developer_key", "lms.example.com")?;
+    /// assert_eq!(api.user().0, "acct:student_1@lms.example.com");
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_authority(
+        username: &str,
+        developer_key: &str,
+        authority: &str,
+    ) -> Result<Self, HypothesisError> {
+        Self::with_credentials(
+            UserAccountID::new(username, authority),
+            Credentials::DeveloperKey(developer_key.into()),
+        )
+    }
+
+    /// Make a new Hypothesis client acting on behalf of `user` with an OAuth access token
+    /// (e.g. obtained through an OAuth authorization flow by an app registered with Hypothesis).
+    ///
+    /// If `auto_refresh` is set and the token came with a refresh token, the access token is
+    /// refreshed automatically (using `client_id` if given) shortly before it expires.
+    /// Use [`Hypothesis::access_token`] to persist the current token.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::auth::OAuthToken;
+    /// let token = OAuthToken {
+    ///     access_token: "5768-mSgpSXOUMb8A4FsPYQT_xhV0l1iKJcDfQZ1n10z9vFf".into(),
+    ///     token_type: "Bearer".into(),
+    ///     expires_in: Some(3600),
+    ///     refresh_token: Some("4657-dkJGNdVn8dmaSuaT5Wa9nzQ6F2GCIiTGBd1RixsTVEA".into()),
+    /// };
+    /// let api = Hypothesis::from_oauth_token(&"acct:student_1@lms.example.com".parse()?, token, None, true)?;
+    /// assert_eq!(api.username(), "student_1");
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn from_oauth_token(
+        user: &UserAccountID,
+        token: OAuthToken,
+        client_id: Option<&str>,
+        auto_refresh: bool,
+    ) -> Result<Self, HypothesisError> {
+        Self::with_credentials(
+            user.clone(),
+            Credentials::oauth(token, client_id, auto_refresh),
+        )
+    }
+
+    /// Make a new Hypothesis client acting on behalf of `username`, a user of the authority
+    /// that `client` is registered for.
+    ///
+    /// No request is made yet: the first API call mints a grant token for the user and
+    /// exchanges it for an access token at the client's [`api_url`](Self::api_url), so a
+    /// self-hosted server can be set with [`set_api_url`](Self::set_api_url) first. The access
+    /// token is replaced by a new one, minted the same way, shortly before it expires.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::auth::AuthorityClient;
+    /// let client = AuthorityClient::new("client_id", "client_secret", "lms.example.com");
+    /// let mut api = Hypothesis::from_authority_client(&client, "student_1")?;
+    /// api.set_api_url("https://hypothesis.lms.example.com/api");
+    /// assert_eq!(api.user().0, "acct:student_1@lms.example.com");
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn from_authority_client(
+        client: &AuthorityClient,
+        username: &str,
+    ) -> Result<Self, HypothesisError> {
+        let user = UserAccountID::new(username, &client.authority);
+        Self::with_credentials(user.clone(), Credentials::grant(client.clone(), user))
+    }
+
+    fn with_credentials(
+        user: UserAccountID,
+        credentials: Credentials,
+    ) -> Result<Self, HypothesisError> {
+        // fail early on keys that can't be sent as a header
+        if let Some(bearer) = credentials.bearer() {
+            header::HeaderValue::from_str(&format!("Bearer {}", bearer))
+                .map_err(HypothesisError::HeaderError)?;
+        }
+        Ok(Self {
+            config: Arc::new(Config {
+                username: user.username().into(),
+                authority: user.authority().into(),
+                user,
+                url_policy: UrlPolicy::default(),
+                api_version: ApiVersion::default(),
+                api_url: API_URL.to_owned(),
+                request_hook: None,
+                skip_hook: None,
+                root_certificates: Vec::new(),
+                compression: true,
+                retry_policy: RetryPolicy::default(),
+            }),
+            credentials: Arc::new(Mutex::new(credentials)),
+            quota: Default::default(),
+            throttle: Default::default(),
+            client: build_client(&[], true)?,
+        })
+    }
+
+    /// Trust the root certificate in `pem` (PEM encoded) in addition to the system's,
+    /// e.g. the CA of a corporate TLS proxy in front of the API.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let mut api = Hypothesis::from_env()?;
+    /// api.add_root_certificate(&std::fs::read("corporate-ca.pem")?)?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_root_certificate(&mut self, pem: &[u8]) -> Result<(), HypothesisError> {
+        let certificate =
+            reqwest::Certificate::from_pem(pem).map_err(HypothesisError::ReqwestError)?;
+        let config = self.config_mut();
+        config.root_certificates.push(certificate);
+        self.client = build_client(&self.config.root_certificates, self.config.compression)?;
+        Ok(())
+    }
+
+    /// Ask for gzip or brotli compressed responses, and decode them, with the "gzip" and
+    /// "brotli" features. Compression is on by default when either feature is enabled; without
+    /// them this does nothing.
+    ///
+    /// Search responses are JSON that compresses to a fraction of its size, which makes syncing
+    /// large groups over slow links much faster; on fast links turning it off saves the CPU
+    /// time spent decoding. Compare the latencies reported to the
+    /// [request hook](Hypothesis::set_request_hook) with and without it.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::Hypothesis;
+    /// let mut api = Hypothesis::new("username", "developer_key")?;
+    /// api.set_compression(false)?;
+    /// assert!(!api.compression());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_compression(&mut self, enabled: bool) -> Result<(), HypothesisError> {
+        self.config_mut().compression = enabled;
+        self.client = build_client(&self.config.root_certificates, enabled)?;
+        Ok(())
+    }
+
+    /// Whether compressed responses are asked for, see [`Hypothesis::set_compression`]
+    pub fn compression(&self) -> bool {
+        self.config.compression && cfg!(any(feature = "gzip", feature = "brotli"))
+    }
+
+    /// Retry failed calls and space calls according to `policy` (default:
+    /// [`RetryPolicy::default`](retry/struct.RetryPolicy.html)). See the [`retry`](retry/index.html)
+    /// module.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.config_mut().retry_policy = policy;
+    }
+
+    /// Starts an authorized request, refreshing the OAuth access token first if needed
+    async fn request(
+        &self,
+        method: Method,
+        url: impl reqwest::IntoUrl,
+    ) -> Result<reqwest::RequestBuilder, HypothesisError> {
+        let mut credentials = self.credentials.lock().await;
+        if credentials.needs_refresh() {
+            credentials
+                .refresh(&self.client, &self.config.api_url)
+                .await?;
+        }
+        let request = self
+            .client
+            .request(method, url)
+            .header(header::ACCEPT, self.config.api_version.media_type());
+        Ok(match credentials.bearer() {
+            Some(bearer) => request.bearer_auth(bearer),
+            None => request,
+        })
+    }
+
+    /// Sends `method` to `{api_url}/{path}`, with `body` as JSON and the fields of `query` as
+    /// URL parameters. Pass [`NONE`] for either to leave it out.
+    ///
+    /// Waits for the [throttle](retry/index.html) and retries according to the retry policy.
+    async fn call(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&(impl Serialize + ?Sized)>,
+        query: Option<&(impl Serialize + ?Sized)>,
+    ) -> Result<ApiResponse, HypothesisError> {
         let mut headers = header::HeaderMap::new();
-        headers.insert(
-            header::AUTHORIZATION,
-            header::HeaderValue::from_str(&format!("Bearer {}", developer_key))
-                .map_err(HypothesisError::HeaderError)?,
-        );
-        headers.insert(
-            header::ACCEPT,
-            header::HeaderValue::from_str("application/vnd.hypothesis.v1+json")
-                .map_err(HypothesisError::HeaderError)?,
+        let body = body.map(serde_json::to_vec).transpose()?;
+        if body.is_some() {
+            headers.insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("application/json"),
+            );
+        }
+        self.call_with(method, path, body, query, &headers).await
+    }
+
+    /// Like [`call`](Self::call), with `body` sent as is and `headers` added to the request,
+    /// replacing any it would otherwise have of the same name
+    async fn call_with(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+        query: Option<&(impl Serialize + ?Sized)>,
+        headers: &header::HeaderMap,
+    ) -> Result<ApiResponse, HypothesisError> {
+        let url = format!("{}/{}", self.config.api_url, path);
+        let params = query.map(query_params).transpose()?;
+        let mut retries = 0;
+        loop {
+            let mut request = self.request(method.clone(), &url).await?;
+            if let Some(body) = &body {
+                request = request.body(body.clone());
+            }
+            if let Some(params) = &params {
+                request = request.query(params);
+            }
+            request = request.headers(headers.clone());
+            let policy = &self.config.retry_policy;
+            self.throttle.wait(policy.min_interval).await;
+            let response = self.send(request).await;
+            let (retry, retry_after) = match &response {
+                Ok(response) => (
+                    response
+                        .status_error()
+                        .is_some_and(|error| policy.retries(&method, &error, retries)),
+                    response.retry_after,
+                ),
+                Err(error) => (policy.retries(&method, error, retries), None),
+            };
+            if !retry {
+                return response;
+            }
+            let backoff = policy.backoff(retries, retry_after);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(%method, %url, retries, ?backoff, "retrying API call");
+            tokio::time::sleep(backoff).await;
+            retries += 1;
+        }
+    }
+
+    /// Sends a request started with `request`, reporting it to the request hook
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<ApiResponse, HypothesisError> {
+        let request = request.build().map_err(HypothesisError::ReqwestError)?;
+        let (method, url) = (request.method().clone(), request.url().clone());
+        let start = Instant::now();
+        let response = self.client.execute(request).await;
+        let info = RequestInfo {
+            method,
+            url,
+            status: response.as_ref().ok().map(reqwest::Response::status),
+            latency: start.elapsed(),
+            api_version: response.as_ref().ok().and_then(|response| {
+                response
+                    .headers()
+                    .get("hypothesis-media-type")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(ApiVersion::from_media_type)
+            }),
+        };
+        self.quota
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(&info);
+        let response = response.map_err(HypothesisError::ReqwestError)?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let request_id = header("x-request-id");
+        let etag = header(header::ETAG.as_str());
+        let last_modified = header(header::LAST_MODIFIED.as_str());
+        let retry_after = header(header::RETRY_AFTER.as_str())
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .map(Duration::from_secs);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            method = %info.method,
+            url = %info.url,
+            status = ?info.status,
+            latency = ?info.latency,
+            request_id = ?request_id,
+            "API call"
         );
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
+        if let Some(hook) = &self.config.request_hook {
+            hook(&info);
+        }
+        let status = response.status();
+        let text = response
+            .text()
+            .await
             .map_err(HypothesisError::ReqwestError)?;
-        Ok(Self {
-            username: username.into(),
-            user,
-            client,
+        Ok(ApiResponse {
+            method: info.method,
+            url: info.url,
+            status,
+            text,
+            request_id,
+            api_version: info.api_version,
+            etag,
+            last_modified,
+            retry_after,
         })
     }
 
-    /// Make a new Hypothesis client from environment variables.
-    /// Username from `$HYPOTHESIS_NAME`,
-    /// Developer key from `$HYPOTHESIS_KEY`
-    /// (see [here](https://h.readthedocs.io/en/latest/api/authorization/) on how to get one)
+    /// Call `hook` with the method, URL, status and latency of every API call made from now on.
+    /// See the [`hooks`](hooks/index.html) module.
+    ///
+    /// Clones made before keep the hook they had, clones made after share this one.
+    pub fn set_request_hook(&mut self, hook: impl Fn(&RequestInfo) + Send + Sync + 'static) {
+        self.config_mut().request_hook = Some(Arc::new(hook));
+    }
+
+    /// Call `hook` with every search result that is left out because it isn't a valid annotation
+    /// (see [`read_rows`](annotations/fn.read_rows.html)), instead of failing the whole search.
+    ///
+    /// Skipped rows are also logged as warnings with the `tracing` feature.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::Hypothesis;
+    /// let mut api = Hypothesis::new("username", "developer_key")?;
+    /// api.set_skip_hook(|skipped| eprintln!("skipped {:?}: {}", skipped.id, skipped.error));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_skip_hook(&mut self, hook: impl Fn(&SkippedRow) + Send + Sync + 'static) {
+        self.config_mut().skip_hook = Some(Arc::new(hook));
+    }
+
+    /// API calls made by this client and its clones, in total and within the quota window.
+    /// See the [`quota`](quota/index.html) module.
+    pub fn quota_usage(&self) -> QuotaUsage {
+        self.quota.lock().unwrap_or_else(|e| e.into_inner()).usage()
+    }
+
+    /// Count calls within the last `window` in [`Hypothesis::quota_usage`] (default: one hour).
+    ///
+    /// Unlike the other settings, this changes the window for all clones of this client, made
+    /// before or after, as they share the count of calls it applies to.
+    pub fn set_quota_window(&self, window: Duration) {
+        self.quota
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_window(window);
+    }
+
+    /// Exchange the OAuth refresh token for a new access token.
+    /// Clients made with [`Hypothesis::from_authority_client`] exchange a new grant token instead.
+    ///
+    /// The new token is used for all further requests and returned so it can be persisted.
+    /// Fails with [`HypothesisError::AuthError`](errors/enum.HypothesisError.html) for developer-key clients
+    /// or if no refresh token is available.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn refresh_access_token(&self) -> Result<OAuthToken, HypothesisError> {
+        self.credentials
+            .lock()
+            .await
+            .refresh(&self.client, &self.config.api_url)
+            .await
+    }
+
+    /// The OAuth token currently in use, None for developer-key clients
+    pub async fn access_token(&self) -> Option<OAuthToken> {
+        self.credentials.lock().await.token().cloned()
+    }
+
+    /// Checks `uri` against the client's URL policy
+    fn check_url_policy(&self, uri: &str) -> Result<(), HypothesisError> {
+        self.config
+            .url_policy
+            .check(uri)
+            .map_err(|violation| HypothesisError::PolicyError {
+                uri: uri.to_owned(),
+                violation,
+            })
+    }
+
+    /// Make a new Hypothesis client from environment variables:
+    /// - the username from `$HYPOTHESIS_NAME`,
+    /// - the developer key from `$HYPOTHESIS_KEY`
+    ///   (see [here](https://h.readthedocs.io/en/latest/api/authorization/) on how to get one),
+    /// - optionally the authority of the user from `$HYPOTHESIS_AUTHORITY` (default "hypothes.is"),
+    /// - and optionally the base URL of a self-hosted API from `$HYPOTHESIS_API_URL`
+    ///   (default [`API_URL`]).
+    ///
+    /// Fails with an [`EnvironmentError`](errors/enum.HypothesisError.html#variant.EnvironmentError)
+    /// suggesting the variable to set if one is missing. To read them from a `.env` file, load it
+    /// first, e.g. with the `dotenv` crate.
     /// # Example
     /// ```
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -190,18 +1011,34 @@ impl Hypothesis {
     /// # }
     /// ```
     pub fn from_env() -> Result<Self, HypothesisError> {
-        let username =
-            env::var("HYPOTHESIS_NAME").map_err(|e| HypothesisError::EnvironmentError {
+        let var = |name: &str, suggestion: &str| {
+            env::var(name).map_err(|e| HypothesisError::EnvironmentError {
                 source: e,
-                suggestion: "Set the environment variable HYPOTHESIS_NAME to your username".into(),
-            })?;
-        let developer_key =
-            env::var("HYPOTHESIS_KEY").map_err(|e| HypothesisError::EnvironmentError {
-                source: e,
-                suggestion: "Set the environment variable HYPOTHESIS_KEY to your personal API key"
-                    .into(),
-            })?;
-        Self::new(&username, &developer_key)
+                suggestion: format!("Set the environment variable {} to {}", name, suggestion),
+            })
+        };
+        // optional variables may be unset, but not set to something that isn't unicode
+        let optional = |name: &str, suggestion: &str| match var(name, suggestion) {
+            Err(HypothesisError::EnvironmentError {
+                source: env::VarError::NotPresent,
+                ..
+            }) => Ok(None),
+            result => result.map(Some),
+        };
+        let username = var("HYPOTHESIS_NAME", "your username")?;
+        let developer_key = var("HYPOTHESIS_KEY", "your personal API key")?;
+        let authority = optional("HYPOTHESIS_AUTHORITY", "the authority of your account")?;
+        let api_url = optional("HYPOTHESIS_API_URL", "the base URL of the API")?;
+        let mut api = Self::with_authority(
+            &username,
+            &developer_key,
+            authority.as_deref().unwrap_or(DEFAULT_AUTHORITY),
+        )?;
+        if let Some(api_url) = api_url {
+            url::Url::parse(&api_url)?;
+            api.set_api_url(&api_url);
+        }
+        Ok(api)
     }
 
     /// Create a new annotation
@@ -232,21 +1069,16 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(uri = %annotation.uri)))]
     pub async fn create_annotation(
         &self,
         annotation: &InputAnnotation,
     ) -> Result<Annotation, HypothesisError> {
-        let text = self
-            .client
-            .post(&format!("{}/annotations", API_URL))
-            .json(annotation)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Annotation>(&text)
+        self.check_url_policy(&annotation.uri)?;
+        let response = self
+            .call(Method::POST, "annotations", Some(annotation), NONE)
+            .await?;
+        response.parse::<Annotation>()
     }
 
     /// Create many new annotations
@@ -286,6 +1118,7 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = annotations.len())))]
     pub async fn create_annotations(
         &self,
         annotations: &[InputAnnotation],
@@ -326,24 +1159,25 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %annotation.id)))]
     pub async fn update_annotation(
         &self,
         annotation: &Annotation,
     ) -> Result<Annotation, HypothesisError> {
-        let text = self
-            .client
-            .patch(&format!("{}/annotations/{}", API_URL, annotation.id))
-            .json(&annotation)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Annotation>(&text)
+        self.check_url_policy(&annotation.uri)?;
+        let response = self
+            .call(
+                Method::PATCH,
+                &format!("annotations/{}", annotation.id),
+                Some(&annotation),
+                NONE,
+            )
+            .await?;
+        response.parse::<Annotation>()
     }
 
     /// Update many annotations at once
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = annotations.len())))]
     pub async fn update_annotations(
         &self,
         annotations: &[Annotation],
@@ -355,12 +1189,58 @@ impl Hypothesis {
         async { try_join_all(futures).await }.await
     }
 
+    /// Update only the fields of an annotation that are set in `changes`
+    ///
+    /// Fields left as None (or empty, for `uri` and `group`) keep their current value, while
+    /// e.g. `text: Some(String::new())` clears the text.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{AnnotationID, Hypothesis};
+    /// use hypothesis::annotations::InputAnnotation;
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let id = AnnotationID::from("an_annotation_id");
+    /// // remove all tags, leave the text alone
+    /// let changes = InputAnnotation::builder().tags(vec![]).build()?;
+    /// let updated_annotation = api.patch_annotation(&id, &changes).await?;
+    /// assert!(updated_annotation.tags.is_empty());
+    /// #    Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn patch_annotation(
+        &self,
+        id: &AnnotationID,
+        changes: &InputAnnotation,
+    ) -> Result<Annotation, HypothesisError> {
+        if !changes.uri.is_empty() {
+            self.check_url_policy(&changes.uri)?;
+        }
+        let response = self
+            .call(
+                Method::PATCH,
+                &format!("annotations/{}", id),
+                Some(changes),
+                NONE,
+            )
+            .await?;
+        response.parse::<Annotation>()
+    }
+
     /// Search for annotations with optional filters
     ///
     /// Returns a list of annotations matching the search query.
     /// See  [`SearchQuery`](annotations/struct.SearchQuery.html) for more filtering options
     ///
     /// This returns a max of 50 annotations at once, use `search_annotations_return_all` if you expect more
+    ///
+    /// Results that aren't valid annotations are left out and reported to the
+    /// [skip hook](#method.set_skip_hook), see [`Hypothesis::search_page`].
     /// # Example
     /// ```
     /// # #[tokio::main]
@@ -372,62 +1252,256 @@ impl Hypothesis {
     /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
     /// let api = Hypothesis::new(&username, &developer_key)?;
     /// /// Search for your own annotations:
-    /// let search_query = SearchQuery::builder().user(&api.user.0).build()?;
+    /// let search_query = SearchQuery::builder().user(&api.user().0).build()?;
     /// let search_results = api.search_annotations(&search_query).await?;
     /// #     assert!(!search_results.is_empty());
     /// #     Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
     pub async fn search_annotations(
         &self,
         query: &SearchQuery,
     ) -> Result<Vec<Annotation>, HypothesisError> {
-        let query: HashMap<String, serde_json::Value> = serde_json::from_str(
-            &serde_json::to_string(&query).map_err(HypothesisError::SerdeError)?,
-        )
-        .map_err(HypothesisError::SerdeError)?;
-        let url = Url::parse_with_params(
-            &format!("{}/search", API_URL),
-            &query
-                .into_iter()
-                .map(|(k, v)| (k, v.to_string().replace('"', "")))
-                .collect::<Vec<_>>(),
-        )
-        .map_err(HypothesisError::URLError)?;
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        #[derive(Deserialize, Debug, Clone, PartialEq)]
-        struct SearchResult {
-            rows: Vec<Annotation>,
-            total: usize,
+        Ok(self.search_page(query).await?.annotations)
+    }
+
+    /// Like [`Hypothesis::search_annotations`], but returns the whole page of results, with the
+    /// total and the rows that aren't valid annotations. The skipped rows are also reported to the
+    /// [skip hook](#method.set_skip_hook).
+    ///
+    /// Pass the page to a [`PageCursor`](annotations/struct.PageCursor.html) to page past rows
+    /// that were skipped.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
+    pub async fn search_page(&self, query: &SearchQuery) -> Result<SearchPage, HypothesisError> {
+        let response = self.call(Method::GET, "search", NONE, Some(query)).await?;
+        let page = response.parse_with(SearchPage::parse)?;
+        for row in &page.skipped {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(id = ?row.id, error = %row.error, "skipped search result");
+            if let Some(hook) = &self.config.skip_hook {
+                hook(row);
+            }
         }
-        Ok(serde_parse::<SearchResult>(&text)?.rows)
+        Ok(page)
     }
 
     /// Retrieve all annotations matching query
     /// See  [`SearchQuery`](annotations/struct.SearchQuery.html) for filtering options
+    ///
+    /// Pages are followed with a [`PageCursor`](annotations/struct.PageCursor.html), so
+    /// annotations updated at the same time as the end of a page are neither skipped nor repeated.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
     pub async fn search_annotations_return_all(
         &self,
         query: &mut SearchQuery,
     ) -> Result<Vec<Annotation>, HypothesisError> {
         let mut annotations = Vec::new();
+        let mut cursor = PageCursor::default();
         loop {
-            let next = self.search_annotations(query).await?;
-            if next.is_empty() {
-                break;
+            let page = self.search_page(query).await?;
+            match cursor.advance(query, page)? {
+                Some(next) => annotations.extend(next),
+                None => break,
             }
-            query.search_after = next[next.len() - 1]
-                .updated
-                .format(&Rfc3339)
-                .map_err(time::Error::Format)?;
-            annotations.extend_from_slice(&next);
+        }
+        Ok(annotations)
+    }
+
+    /// All annotations matching query as a stream, fetching the next page only once the previous one
+    /// has been consumed, so results don't need to be held in memory and reading can stop early.
+    ///
+    /// The stream ends after the first error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use futures::{pin_mut, StreamExt};
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let api = Hypothesis::from_env()?;
+    /// let query = SearchQuery::builder().user(&api.user().0).limit(200).build()?;
+    /// let annotations = api.stream_search(&query).take(500);
+    /// pin_mut!(annotations);
+    /// while let Some(annotation) = annotations.next().await {
+    ///     println!("{}", serde_json::to_string(&annotation?)?);
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn stream_search(
+        &self,
+        query: &SearchQuery,
+    ) -> impl Stream<Item = Result<Annotation, HypothesisError>> + '_ {
+        let state = (query.clone(), PageCursor::default(), VecDeque::new(), false);
+        futures::stream::unfold(
+            state,
+            move |(mut query, mut cursor, mut page, done)| async move {
+                loop {
+                    if let Some(annotation) = page.pop_front() {
+                        return Some((Ok(annotation), (query, cursor, page, done)));
+                    }
+                    if done {
+                        return None;
+                    }
+                    let next = match self.search_page(&query).await {
+                        Ok(next) => cursor.advance(&mut query, next),
+                        Err(e) => Err(e),
+                    };
+                    match next {
+                        // may be empty while paging through annotations with the same time
+                        Ok(Some(next)) => page.extend(next),
+                        Ok(None) => return None,
+                        Err(e) => return Some((Err(e), (query, cursor, page, true))),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Like [`Hypothesis::stream_search`], but keeps up to `pages` pages of results in flight
+    /// while the consumer works through the current one, for large exports that would otherwise
+    /// wait on the network for every page.
+    ///
+    /// Pages are fetched concurrently by offset from a shared `search_after` cursor, which moves
+    /// on once the API's maximum offset ([`MAX_OFFSET`]) is reached, so `query.offset` is ignored.
+    /// Results are still yielded in order, and the stream ends after the first error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use futures::TryStreamExt;
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let api = Hypothesis::from_env()?;
+    /// let query = SearchQuery::builder().group("__world__").limit(200).build()?;
+    /// let annotations: Vec<_> = api.stream_search_prefetch(&query, 4).try_collect().await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn stream_search_prefetch(
+        &self,
+        query: &SearchQuery,
+        pages: usize,
+    ) -> impl Stream<Item = Result<Annotation, HypothesisError>> + '_ {
+        let mut query = query.clone();
+        query.limit = query.limit.max(1);
+        query.offset = 0;
+        let limit = usize::from(query.limit);
+        let window_pages = MAX_OFFSET / limit + 1;
+        let state = PrefetchState {
+            query,
+            window: None,
+            page: VecDeque::new(),
+            cursor: PageCursor::default(),
+            done: false,
+        };
+        futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(annotation) = state.page.pop_front() {
+                    return Some((Ok(annotation), state));
+                }
+                if state.done {
+                    return None;
+                }
+                let window = state.window.get_or_insert_with(|| {
+                    let query = state.query.clone();
+                    futures::stream::iter(0..window_pages)
+                        .map(move |i| {
+                            let mut query = query.clone();
+                            query.offset = i * limit;
+                            async move { self.search_page(&query).await }
+                        })
+                        .buffered(pages.max(1))
+                        .boxed()
+                });
+                match window.next().await {
+                    Some(Ok(next)) => {
+                        // a short page is the last one
+                        state.done = next.rows() < limit;
+                        let next = state.cursor.unseen(next.annotations);
+                        state.cursor.note(&next, &state.query);
+                        state.page.extend(next);
+                    }
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                    // window used up: continue after its last result
+                    None => {
+                        state.window = None;
+                        match state.cursor.search_after(&state.query) {
+                            // a whole window of annotations with the same time can't be passed
+                            Ok(Some(search_after)) if search_after != state.query.search_after => {
+                                state.query.search_after = search_after
+                            }
+                            Ok(_) => return None,
+                            Err(e) => {
+                                state.done = true;
+                                return Some((Err(e), state));
+                            }
+                        }
+                    }
+                }
+                if state.done {
+                    // don't wait for (or make) requests past the end of the results
+                    state.window = None;
+                }
+            }
+        })
+    }
+
+    /// Retrieve all annotations matching query like [`Hypothesis::search_annotations_return_all`],
+    /// tuning the page size (starting at `query.limit`) to the connection.
+    ///
+    /// Pages that time out are retried with half the page size, see
+    /// [`AdaptivePaging`](annotations/struct.AdaptivePaging.html). Other errors are returned.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::{AdaptivePaging, SearchQuery};
+    /// let api = Hypothesis::from_env()?;
+    /// let mut query = SearchQuery::builder().user(&api.user().0).limit(50).build()?;
+    /// let annotations = api.search_annotations_adaptive(&mut query, &AdaptivePaging::default()).await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
+    pub async fn search_annotations_adaptive(
+        &self,
+        query: &mut SearchQuery,
+        paging: &AdaptivePaging,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let mut annotations = Vec::new();
+        let mut cursor = PageCursor::default();
+        query.limit = paging.next_limit(query.limit, Some(paging.target_latency));
+        loop {
+            let start = Instant::now();
+            let next = match tokio::time::timeout(paging.timeout, self.search_page(query)).await {
+                Ok(Ok(next)) => Some(next),
+                Ok(Err(HypothesisError::ReqwestError(e))) if e.is_timeout() => None,
+                Ok(Err(e)) => return Err(e),
+                Err(_) => None,
+            };
+            let limit = paging.next_limit(query.limit, next.as_ref().map(|_| start.elapsed()));
+            match next {
+                Some(next) => match cursor.advance(query, next)? {
+                    Some(next) => annotations.extend(next),
+                    None => break,
+                },
+                // timed out even at the smallest page size
+                None if limit >= query.limit => {
+                    return Err(HypothesisError::Timeout(paging.timeout))
+                }
+                // timed out: the same page is retried, smaller
+                None => {}
+            }
+            query.limit = limit;
         }
         Ok(annotations)
     }
@@ -456,23 +1530,70 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
-    pub async fn fetch_annotation(&self, id: &str) -> Result<Annotation, HypothesisError> {
-        let text = self
-            .client
-            .get(&format!("{}/annotations/{}", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Annotation>(&text)
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn fetch_annotation(&self, id: &AnnotationID) -> Result<Annotation, HypothesisError> {
+        let response = self
+            .call(Method::GET, &format!("annotations/{}", id), NONE, NONE)
+            .await?;
+        response.parse::<Annotation>()
+    }
+
+    /// Fetch annotation by ID unless it still has the `etag` of an earlier fetch
+    /// (sent as `If-None-Match`), to poll an annotation without downloading it again unchanged.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{AnnotationID, Conditional, Hypothesis};
+    /// let api = Hypothesis::from_env()?;
+    /// let id = AnnotationID::from("Qe7fpc5ZEeuEPbdDmNzbAw");
+    /// let etag = match api.fetch_annotation_conditional(&id, None).await? {
+    ///     Conditional::Modified { etag, .. } => etag,
+    ///     Conditional::NotModified => unreachable!(),
+    /// };
+    /// if let Conditional::Modified { value, .. } =
+    ///     api.fetch_annotation_conditional(&id, etag.as_deref()).await?
+    /// {
+    ///     println!("changed: {}", value.text);
+    /// }
+    /// #    Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id, etag = ?etag)))]
+    pub async fn fetch_annotation_conditional(
+        &self,
+        id: &AnnotationID,
+        etag: Option<&str>,
+    ) -> Result<Conditional<Annotation>, HypothesisError> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(etag) = etag {
+            headers.insert(header::IF_NONE_MATCH, header::HeaderValue::from_str(etag)?);
+        }
+        let response = self
+            .call_with(
+                Method::GET,
+                &format!("annotations/{}", id),
+                None,
+                NONE,
+                &headers,
+            )
+            .await?;
+        if response.status == StatusCode::NOT_MODIFIED {
+            return Ok(Conditional::NotModified);
+        }
+        Ok(Conditional::Modified {
+            value: response.parse::<Annotation>()?,
+            etag: response.etag.clone(),
+            last_modified: response.last_modified.clone(),
+        })
     }
 
     /// Fetch multiple annotations by ID
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = ids.len())))]
     pub async fn fetch_annotations(
         &self,
-        ids: &[String],
+        ids: &[AnnotationID],
     ) -> Result<Vec<Annotation>, HypothesisError> {
         let futures: Vec<_> = ids.iter().map(|id| self.fetch_annotation(id)).collect();
         try_join_all(futures).await
@@ -496,113 +1617,212 @@ impl Hypothesis {
     /// #                       .uri("http://example.com")
     /// #                       .group(group_id).build()?).await?;
     /// #    let annotation_id = annotation.id.to_owned();    
-    /// let deleted = api.delete_annotation(&annotation_id).await?;
-    /// assert!(deleted);
+    /// let result = api.delete_annotation(&annotation_id).await?;
+    /// assert!(result.deleted);
     /// assert!(api.fetch_annotation(&annotation_id).await.is_err());
     /// #    Ok(())
     /// # }
     /// ```
-    pub async fn delete_annotation(&self, id: &str) -> Result<bool, HypothesisError> {
-        let text = self
-            .client
-            .delete(&format!("{}/annotations/{}", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        #[derive(Deserialize, Debug, Clone, PartialEq)]
-        struct DeletionResult {
-            id: String,
-            deleted: bool,
-        }
-        Ok(serde_parse::<DeletionResult>(&text)?.deleted)
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn delete_annotation(
+        &self,
+        id: &AnnotationID,
+    ) -> Result<DeletionResult, HypothesisError> {
+        let response = self
+            .call(Method::DELETE, &format!("annotations/{}", id), NONE, NONE)
+            .await?;
+        let mut result = response.parse::<DeletionResult>()?;
+        result.status = response.status.as_u16();
+        Ok(result)
     }
 
     /// Delete multiple annotations by ID
-    pub async fn delete_annotations(&self, ids: &[String]) -> Result<Vec<bool>, HypothesisError> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = ids.len())))]
+    pub async fn delete_annotations(
+        &self,
+        ids: &[AnnotationID],
+    ) -> Result<Vec<DeletionResult>, HypothesisError> {
         let futures: Vec<_> = ids.iter().map(|id| self.delete_annotation(id)).collect();
         try_join_all(futures).await
     }
 
+    /// Remove duplicates (see [`find_duplicates`](annotations/fn.find_duplicates.html)) among
+    /// the annotations matching `query`, keeping the oldest of each.
+    ///
+    /// Returns the IDs of the deleted annotations.
+    /// `progress` is told about every group of duplicates removed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::{DedupeStrategy, SearchQuery};
+    /// let api = Hypothesis::from_env()?;
+    /// let query = SearchQuery::builder().user(&api.user().0).limit(200).build()?;
+    /// let deleted = api.dedupe(&query, DedupeStrategy::Merge, ()).await?;
+    /// println!("removed {} duplicates", deleted.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary(), strategy = ?strategy)))]
+    pub async fn dedupe(
+        &self,
+        query: &SearchQuery,
+        strategy: DedupeStrategy,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<AnnotationID>, HypothesisError> {
+        let annotations = self
+            .search_annotations_return_all(&mut query.clone())
+            .await?;
+        let groups = find_duplicates(&annotations);
+        let mut tracker = Tracker::new(progress, groups.len());
+        let mut deleted = Vec::new();
+        for group in groups {
+            tracker.check_cancelled()?;
+            let (keep, duplicates) = group.split_first().expect("duplicate groups aren't empty");
+            deleted.extend(tracker.record(self.dedupe_group(keep, duplicates, strategy).await)?);
+        }
+        Ok(deleted)
+    }
+
+    /// Removes `duplicates` of `keep` as in [`Hypothesis::dedupe`], returning their IDs
+    async fn dedupe_group(
+        &self,
+        keep: &Annotation,
+        duplicates: &[&Annotation],
+        strategy: DedupeStrategy,
+    ) -> Result<Vec<AnnotationID>, HypothesisError> {
+        if strategy == DedupeStrategy::Merge {
+            let mut tags = keep.tags.clone();
+            for tag in duplicates.iter().flat_map(|a| &a.tags) {
+                if !tags.contains(tag) {
+                    tags.push(tag.to_owned());
+                }
+            }
+            if tags != keep.tags {
+                let patch = InputAnnotation::builder().tags(tags).build()?;
+                self.patch_annotation(&keep.id, &patch).await?;
+            }
+        }
+        let ids: Vec<_> = duplicates.iter().map(|a| a.id.clone()).collect();
+        self.delete_annotations(&ids).await?;
+        Ok(ids)
+    }
+
     /// Flag an annotation
     ///
     /// Flag an annotation for review (moderation). The moderator of the group containing the
     /// annotation will be notified of the flag and can decide whether or not to hide the
     /// annotation. Note that flags persist and cannot be removed once they are set.
-    pub async fn flag_annotation(&self, id: &str) -> Result<(), HypothesisError> {
-        let text = self
-            .client
-            .put(&format!("{}/annotations/{}/flag", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        let error = serde_json::from_str::<errors::APIError>(&text);
-        if let Ok(error) = error {
-            Err(HypothesisError::APIError {
-                source: error,
-                raw_text: text,
-                serde_error: None,
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn flag_annotation(
+        &self,
+        id: &AnnotationID,
+    ) -> Result<ModerationResult, HypothesisError> {
+        let response = self
+            .call(Method::PUT, &format!("annotations/{}/flag", id), NONE, NONE)
+            .await?;
+        let status = response.status.as_u16();
+        response.check()?;
+        Ok(ModerationResult {
+            id: id.to_owned(),
+            action: ModerationAction::Flag,
+            status,
+        })
+    }
+
+    /// All flagged annotations of a group, most flagged first.
+    ///
+    /// Flag counts (`Annotation::moderation`) are only available to moderators of the group,
+    /// otherwise annotations are only ordered by when they were last updated.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{GroupID, Hypothesis};
+    /// let api = Hypothesis::from_env()?;
+    /// for annotation in api.list_flagged_annotations(&GroupID::from("groupId")).await? {
+    ///     let flags = annotation.moderation.map_or(0, |m| m.flag_count);
+    ///     println!("{} flagged {} times: {}", annotation.id, flags, annotation.text);
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %group)))]
+    pub async fn list_flagged_annotations(
+        &self,
+        group: &GroupID,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let mut query = SearchQuery::builder()
+            .group(group.clone())
+            .limit(200)
+            .build()?;
+        let mut flagged: Vec<_> = self
+            .search_annotations_return_all(&mut query)
+            .await?
+            .into_iter()
+            .filter(|annotation| {
+                annotation.flagged
+                    || annotation
+                        .moderation
+                        .as_ref()
+                        .is_some_and(|moderation| moderation.flag_count > 0)
             })
-        } else {
-            Ok(())
-        }
+            .collect();
+        // stable, so equally flagged annotations stay most recently updated first
+        flagged.sort_by_key(|annotation| {
+            std::cmp::Reverse(annotation.moderation.as_ref().map_or(0, |m| m.flag_count))
+        });
+        Ok(flagged)
     }
 
     /// Hide an annotation
     ///
     /// Hide an annotation. The authenticated user needs to have the moderate permission for the
     /// group that contains the annotation — this permission is granted to the user who created the group.
-    pub async fn hide_annotation(&self, id: &str) -> Result<(), HypothesisError> {
-        let text = self
-            .client
-            .put(&format!("{}/annotations/{}/hide", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        let error = serde_json::from_str::<errors::APIError>(&text);
-        if let Ok(error) = error {
-            Err(HypothesisError::APIError {
-                source: error,
-                raw_text: text,
-                serde_error: None,
-            })
-        } else {
-            Ok(())
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn hide_annotation(
+        &self,
+        id: &AnnotationID,
+    ) -> Result<ModerationResult, HypothesisError> {
+        let response = self
+            .call(Method::PUT, &format!("annotations/{}/hide", id), NONE, NONE)
+            .await?;
+        let status = response.status.as_u16();
+        response.check()?;
+        Ok(ModerationResult {
+            id: id.to_owned(),
+            action: ModerationAction::Hide,
+            status,
+        })
     }
 
     /// Show an annotation
     ///
     /// Show/"un-hide" an annotation. The authenticated user needs to have the moderate permission
     /// for the group that contains the annotation—this permission is granted to the user who created the group.
-    pub async fn show_annotation(&self, id: &str) -> Result<(), HypothesisError> {
-        let text = self
-            .client
-            .delete(&format!("{}/annotations/{}/hide", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        let error = serde_json::from_str::<errors::APIError>(&text);
-        if let Ok(error) = error {
-            Err(HypothesisError::APIError {
-                source: error,
-                raw_text: text,
-                serde_error: None,
-            })
-        } else {
-            Ok(())
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn show_annotation(
+        &self,
+        id: &AnnotationID,
+    ) -> Result<ModerationResult, HypothesisError> {
+        let response = self
+            .call(
+                Method::DELETE,
+                &format!("annotations/{}/hide", id),
+                NONE,
+                NONE,
+            )
+            .await?;
+        let status = response.status.as_u16();
+        response.check()?;
+        Ok(ModerationResult {
+            id: id.to_owned(),
+            action: ModerationAction::Show,
+            status,
+        })
     }
 
     /// Retrieve a list of applicable Groups, filtered by authority and target document (`document_uri`).
@@ -625,29 +1845,10 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn get_groups(&self, query: &GroupFilters) -> Result<Vec<Group>, HypothesisError> {
-        let query: HashMap<String, serde_json::Value> = serde_json::from_str(
-            &serde_json::to_string(&query).map_err(HypothesisError::SerdeError)?,
-        )
-        .map_err(HypothesisError::SerdeError)?;
-        let url = Url::parse_with_params(
-            &format!("{}/groups", API_URL),
-            &query
-                .into_iter()
-                .map(|(k, v)| (k, v.to_string().replace('"', "")))
-                .collect::<Vec<_>>(),
-        )
-        .map_err(HypothesisError::URLError)?;
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse(&text)
+        let response = self.call(Method::GET, "groups", NONE, Some(query)).await?;
+        response.parse()
     }
 
     /// Create a new, private group for the currently-authenticated user.
@@ -666,6 +1867,7 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %name)))]
     pub async fn create_group(
         &self,
         name: &str,
@@ -676,20 +1878,14 @@ impl Hypothesis {
         if let Some(description) = description {
             params.insert("description", description);
         }
-        let text = self
-            .client
-            .post(&format!("{}/groups", API_URL))
-            .json(&params)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse(&text)
+        let response = self
+            .call(Method::POST, "groups", Some(&params), NONE)
+            .await?;
+        response.parse()
     }
 
     /// Create multiple groups
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = names.len())))]
     pub async fn create_groups(
         &self,
         names: &[String],
@@ -709,12 +1905,12 @@ impl Hypothesis {
     /// ```
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// use hypothesis::Hypothesis;
+    /// use hypothesis::{GroupID, Hypothesis};
     /// use hypothesis::groups::Expand;
     /// #     dotenv::dotenv()?;
     /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
     /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
-    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// #     let group_id = GroupID::from(dotenv::var("TEST_GROUP_ID")?);
     ///
     /// let api = Hypothesis::new(&username, &developer_key)?;
     /// /// Expands organization into a struct
@@ -722,47 +1918,55 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }    
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
     pub async fn fetch_group(
         &self,
-        id: &str,
+        id: &GroupID,
         expand: Vec<Expand>,
     ) -> Result<Group, HypothesisError> {
-        let params: HashMap<&str, Vec<String>> = if !expand.is_empty() {
-            vec![(
-                "expand",
-                expand
-                    .into_iter()
-                    .map(|e| serde_json::to_string(&e))
-                    .collect::<Result<_, _>>()
-                    .map_err(HypothesisError::SerdeError)?,
-            )]
-            .into_iter()
-            .collect()
-        } else {
-            HashMap::new()
-        };
-        let text = self
-            .client
-            .get(&format!("{}/groups/{}", API_URL, id))
-            .json(&params)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Group>(&text)
+        let params: HashMap<&str, Vec<Expand>> = HashMap::from([("expand", expand)]);
+        let response = self
+            .call(Method::GET, &format!("groups/{}", id), NONE, Some(&params))
+            .await?;
+        response.parse::<Group>()
+    }
+
+    /// Whether annotations on `uri` can be made in `group`, according to the group's scopes
+    /// (see [`Group::allows_uri`](groups/struct.Group.html#method.allows_uri)), e.g. to skip
+    /// out-of-scope documents before a bulk import.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::from_env()?;
+    /// if !api.check_group_scope(&"Jx3nbqV9".into(), "https://example.com/post").await? {
+    ///     eprintln!("https://example.com/post is out of the group's scope");
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(group = %group, uri = %uri)))]
+    pub async fn check_group_scope(
+        &self,
+        group: &GroupID,
+        uri: &str,
+    ) -> Result<bool, HypothesisError> {
+        let group = self.fetch_group(group, vec![Expand::Scopes]).await?;
+        Ok(group.allows_uri(uri))
     }
 
     /// Fetch multiple groups by ID
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = ids.len())))]
     pub async fn fetch_groups(
         &self,
-        ids: &[String],
+        ids: &[GroupID],
         expands: Vec<Vec<Expand>>,
     ) -> Result<Vec<Group>, HypothesisError> {
         let futures: Vec<_> = ids
             .iter()
-            .zip(expands.into_iter())
+            .zip(expands)
             .map(|(id, expand)| self.fetch_group(id, expand))
             .collect();
         async { try_join_all(futures).await }.await
@@ -774,11 +1978,11 @@ impl Hypothesis {
     /// ```no_run
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// use hypothesis::Hypothesis;
+    /// use hypothesis::{GroupID, Hypothesis};
     /// #     dotenv::dotenv()?;
     /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
     /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
-    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// #     let group_id = GroupID::from(dotenv::var("TEST_GROUP_ID")?);
     ///
     /// let api = Hypothesis::new(&username, &developer_key)?;
     /// let group = api.update_group(&group_id, Some("new_group_name"), None).await?;
@@ -787,9 +1991,10 @@ impl Hypothesis {
     /// #    Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
     pub async fn update_group(
         &self,
-        id: &str,
+        id: &GroupID,
         name: Option<&str>,
         description: Option<&str>,
     ) -> Result<Group, HypothesisError> {
@@ -800,23 +2005,22 @@ impl Hypothesis {
         if let Some(description) = description {
             params.insert("description", description);
         }
-        let text = self
-            .client
-            .patch(&format!("{}/groups/{}", API_URL, id))
-            .json(&params)
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Group>(&text)
+        let response = self
+            .call(
+                Method::PATCH,
+                &format!("groups/{}", id),
+                Some(&params),
+                NONE,
+            )
+            .await?;
+        response.parse::<Group>()
     }
 
     /// Update multiple groups
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = ids.len())))]
     pub async fn update_groups(
         &self,
-        ids: &[String],
+        ids: &[GroupID],
         names: &[Option<String>],
         descriptions: &[Option<String>],
     ) -> Result<Vec<Group>, HypothesisError> {
@@ -839,51 +2043,138 @@ impl Hypothesis {
     /// ```
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// use hypothesis::Hypothesis;
+    /// use hypothesis::{GroupID, Hypothesis};
     /// #     dotenv::dotenv()?;
     /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
     /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
-    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// #     let group_id = GroupID::from(dotenv::var("TEST_GROUP_ID")?);
     ///
     /// let api = Hypothesis::new(&username, &developer_key)?;
     /// let members = api.get_group_members(&group_id).await?;
     /// #    Ok(())
     /// # }
     /// ```
-    pub async fn get_group_members(&self, id: &str) -> Result<Vec<Member>, HypothesisError> {
-        let text = self
-            .client
-            .get(&format!("{}/groups/{}/members", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Vec<Member>>(&text)
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn get_group_members(&self, id: &GroupID) -> Result<Vec<Member>, HypothesisError> {
+        let response = self
+            .call(Method::GET, &format!("groups/{}/members", id), NONE, NONE)
+            .await?;
+        response.parse::<Vec<Member>>()
     }
 
     /// Remove yourself from a group.
-    pub async fn leave_group(&self, id: &str) -> Result<(), HypothesisError> {
-        let text = self
-            .client
-            .delete(&format!("{}/groups/{}/members/me", API_URL, id))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        let error = serde_json::from_str::<errors::APIError>(&text);
-        if let Ok(error) = error {
-            Err(HypothesisError::APIError {
-                source: error,
-                raw_text: text,
-                serde_error: None,
-            })
-        } else {
-            Ok(())
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn leave_group(&self, id: &GroupID) -> Result<(), HypothesisError> {
+        let response = self
+            .call(
+                Method::DELETE,
+                &format!("groups/{}/members/me", id),
+                NONE,
+                NONE,
+            )
+            .await?;
+        response.check()
+    }
+
+    /// Join a group, given its ID or an invite link to it (see
+    /// [`group_id_from_invite`](groups/fn.group_id_from_invite.html)), and return its ID.
+    ///
+    /// Only open and restricted groups can be joined without an invitation by the group's
+    /// creator; other groups answer with an error.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::from_env()?;
+    /// let group = api.join_group("https://hypothes.is/groups/Jx3nbqV9/reading-group").await?;
+    /// assert_eq!(&*group, "Jx3nbqV9");
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(id_or_invite = %id_or_invite))
+    )]
+    pub async fn join_group(&self, id_or_invite: &str) -> Result<GroupID, HypothesisError> {
+        let id = groups::group_id_from_invite(id_or_invite)
+            .ok_or_else(|| HypothesisError::InviteError(id_or_invite.to_owned()))?;
+        let response = self
+            .call(
+                Method::POST,
+                &format!("groups/{}/members/me", id),
+                NONE,
+                NONE,
+            )
+            .await?;
+        response.check()?;
+        Ok(id)
+    }
+
+    /// Add a user to a group.
+    ///
+    /// Only available to authority clients, for users of their own authority.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id, userid = %userid)))]
+    pub async fn add_group_member(
+        &self,
+        id: &GroupID,
+        userid: &UserAccountID,
+    ) -> Result<(), HypothesisError> {
+        let response = self
+            .call(
+                Method::POST,
+                &format!("groups/{}/members/{}", id, userid),
+                NONE,
+                NONE,
+            )
+            .await?;
+        response.check()
+    }
+
+    /// Remove a user from a group.
+    ///
+    /// Only available to authority clients, for users of their own authority.
+    /// Use [`Hypothesis::leave_group`] to remove yourself.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id, userid = %userid)))]
+    pub async fn remove_group_member(
+        &self,
+        id: &GroupID,
+        userid: &UserAccountID,
+    ) -> Result<(), HypothesisError> {
+        let response = self
+            .call(
+                Method::DELETE,
+                &format!("groups/{}/members/{}", id, userid),
+                NONE,
+                NONE,
+            )
+            .await?;
+        response.check()
+    }
+
+    /// Fetch the API index: the service's links (endpoints) and the API version it answered with.
+    /// Useful to check what a server supports.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::from_env()?;
+    /// let index = api.fetch_api_index().await?;
+    /// if index.link("bulk.action").is_none() {
+    ///     println!("no bulk API on this server");
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn fetch_api_index(&self) -> Result<ApiIndex, HypothesisError> {
+        let response = self.call(Method::GET, "", NONE, NONE).await?;
+        let mut index = response.parse::<ApiIndex>()?;
+        index.api_version = response.api_version;
+        Ok(index)
     }
 
     /// Fetch profile information for the currently-authenticated user.
@@ -899,22 +2190,61 @@ impl Hypothesis {
     /// let api = Hypothesis::new(&username, &developer_key)?;
     /// let profile = api.fetch_user_profile().await?;
     /// assert!(profile.userid.is_some());
-    /// assert_eq!(profile.userid.unwrap(), api.user);
+    /// assert_eq!(&profile.userid.unwrap(), api.user());
     /// #     Ok(())
     /// # }
     /// ```
-
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn fetch_user_profile(&self) -> Result<UserProfile, HypothesisError> {
-        let text = self
-            .client
-            .get(&format!("{}/profile", API_URL))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<UserProfile>(&text)
+        let response = self.call(Method::GET, "profile", NONE, NONE).await?;
+        response.parse::<UserProfile>()
+    }
+
+    /// Check that the API accepts the client's credentials, with a single cheap call to
+    /// `/profile`, before doing real work. Returns the user the credentials belong to.
+    ///
+    /// Fails with [`HypothesisError::AuthError`](errors/enum.HypothesisError.html) if the
+    /// credentials are rejected or belong to another user than `api.user()`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::new("username", "developer_key")?;
+    /// match api.verify_auth().await {
+    ///     Ok(user) => println!("Logged in as {}", user),
+    ///     Err(e) => eprintln!("Check your API key: {}", e),
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn verify_auth(&self) -> Result<UserAccountID, HypothesisError> {
+        let profile = match self.fetch_user_profile().await {
+            Err(HypothesisError::StatusError {
+                status: 401,
+                source,
+                ..
+            }) => {
+                return Err(HypothesisError::AuthError(format!(
+                    "the API rejected the credentials: {}",
+                    source.reason
+                )))
+            }
+            result => result?,
+        };
+        match profile.userid {
+            None => Err(HypothesisError::AuthError(
+                "the API didn't recognize the credentials".into(),
+            )),
+            Some(userid) if userid != *self.user() => Err(HypothesisError::AuthError(format!(
+                "the credentials belong to {}, not {}",
+                userid,
+                self.user()
+            ))),
+            Some(userid) => Ok(userid),
+        }
     }
 
     /// Fetch the groups for which the currently-authenticated user is a member.
@@ -931,34 +2261,124 @@ impl Hypothesis {
     /// #     Ok(())
     /// # }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn fetch_user_groups(&self) -> Result<Vec<Group>, HypothesisError> {
-        let text = self
-            .client
-            .get(&format!("{}/profile/groups", API_URL))
-            .send()
-            .await
-            .map_err(HypothesisError::ReqwestError)?
-            .text()
-            .await
-            .map_err(HypothesisError::ReqwestError)?;
-        serde_parse::<Vec<Group>>(&text)
+        let response = self.call(Method::GET, "profile/groups", NONE, NONE).await?;
+        response.parse::<Vec<Group>>()
+    }
+
+    /// Create a new user of the client's authority.
+    ///
+    /// Only available to authority clients (i.e. with client credentials for a third-party authority).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::users::NewUser;
+    /// # let api = Hypothesis::with_authority("lms_bot", "client_key", "lms.example.com")?;
+    /// let user = api.create_user(&NewUser::builder()
+    ///                 .authority("lms.example.com")
+    ///                 .username("student_1")
+    ///                 .email("student_1@example.com")
+    ///                 .build()?).await?;
+    /// assert_eq!(user.userid.0, "acct:student_1@lms.example.com");
+    /// #    Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(username = %user.username)))]
+    pub async fn create_user(&self, user: &NewUser) -> Result<User, HypothesisError> {
+        let response = self.call(Method::POST, "users", Some(user), NONE).await?;
+        response.parse::<User>()
+    }
+
+    /// Update a user's email address and/or display name.
+    ///
+    /// Only available to authority clients, for users of their own authority.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(userid = %userid)))]
+    pub async fn update_user(
+        &self,
+        userid: &UserAccountID,
+        email: Option<&str>,
+        display_name: Option<&str>,
+    ) -> Result<User, HypothesisError> {
+        let mut params = HashMap::new();
+        if let Some(email) = email {
+            params.insert("email", email);
+        }
+        if let Some(display_name) = display_name {
+            params.insert("display_name", display_name);
+        }
+        let response = self
+            .call(
+                Method::PATCH,
+                &format!("users/{}", userid),
+                Some(&params),
+                NONE,
+            )
+            .await?;
+        response.parse::<User>()
+    }
+
+    /// Fetch a user of the client's authority.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(userid = %userid)))]
+    pub async fn fetch_user(&self, userid: &UserAccountID) -> Result<User, HypothesisError> {
+        let response = self
+            .call(Method::GET, &format!("users/{}", userid), NONE, NONE)
+            .await?;
+        response.parse::<User>()
     }
 }
 
-/// Stores user account ID in the form "acct:{username}@hypothes.is"
+/// Stores user account ID in the form "acct:{username}@{authority}"
 ///
-/// Create from username:
+/// Create from username (for the "hypothes.is" authority):
 /// ```
 /// # use hypothesis::UserAccountID;
 /// let user_id = "my_username".parse::<UserAccountID>().unwrap();
+/// assert_eq!(user_id.0, "acct:my_username@hypothes.is");
+/// ```
+/// or from username and authority:
 /// ```
-#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+/// # use hypothesis::UserAccountID;
+/// let user_id = UserAccountID::new("my_username", "lms.example.com");
+/// assert_eq!(user_id.username(), "my_username");
+/// assert_eq!(user_id.authority(), "lms.example.com");
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct UserAccountID(pub String);
 
+impl UserAccountID {
+    pub fn new(username: &str, authority: &str) -> Self {
+        Self(format!("acct:{}@{}", username, authority))
+    }
+
+    /// The part between "acct:" and "@"
+    pub fn username(&self) -> &str {
+        let account = self.0.strip_prefix("acct:").unwrap_or(&self.0);
+        account
+            .rsplit_once('@')
+            .map_or(account, |(username, _)| username)
+    }
+
+    /// The part after "@"
+    pub fn authority(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map_or("", |(_, authority)| authority)
+    }
+}
+
 impl FromStr for UserAccountID {
     type Err = ParseError;
+    /// Parses either a full "acct:{username}@{authority}" ID or a "hypothes.is" username
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(format!("acct:{}@hypothes.is", s)))
+        if s.starts_with("acct:") {
+            Ok(Self(s.to_owned()))
+        } else {
+            Ok(Self::new(s, DEFAULT_AUTHORITY))
+        }
     }
 }
 
@@ -975,3 +2395,86 @@ impl From<&UserAccountID> for UserAccountID {
         UserAccountID(a.0.to_owned())
     }
 }
+
+macro_rules! id_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(pub String);
+
+        impl FromStr for $name {
+            type Err = ParseError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(Self(s.to_owned()))
+            }
+        }
+
+        impl fmt::Display for $name {
+            #[inline]
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+            #[inline]
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl From<&str> for $name {
+            #[inline]
+            fn from(s: &str) -> Self {
+                Self(s.to_owned())
+            }
+        }
+
+        impl From<String> for $name {
+            #[inline]
+            fn from(s: String) -> Self {
+                Self(s)
+            }
+        }
+
+        impl From<&String> for $name {
+            #[inline]
+            fn from(s: &String) -> Self {
+                Self(s.to_owned())
+            }
+        }
+
+        impl From<&$name> for $name {
+            #[inline]
+            fn from(id: &$name) -> Self {
+                id.clone()
+            }
+        }
+    };
+}
+
+id_type!(
+    /// Unique ID of an annotation
+    ///
+    /// Kept distinct from [`GroupID`] so that the two can't be swapped by accident.
+    /// ```
+    /// # use hypothesis::AnnotationID;
+    /// let id: AnnotationID = "Qe7fpc5ZEeuEPbdDmNzbAw".parse().unwrap();
+    /// assert_eq!(id.len(), 22);
+    /// assert_eq!(id.to_string(), "Qe7fpc5ZEeuEPbdDmNzbAw");
+    /// ```
+    AnnotationID
+);
+
+id_type!(
+    /// Unique ID of a group, e.g. "__world__" for the public group
+    ///
+    /// Kept distinct from [`AnnotationID`] so that the two can't be swapped by accident.
+    /// ```
+    /// # use hypothesis::GroupID;
+    /// let id = GroupID::from("__world__");
+    /// assert_eq!(&*id, "__world__");
+    /// ```
+    GroupID
+);
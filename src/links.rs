@@ -0,0 +1,61 @@
+//! Shareable links to annotations and annotated documents on hypothes.is
+//!
+//! The API includes `html` and `incontext` links with each annotation, but annotations read from
+//! other sources (e.g. exports or a [`Store`](../store/struct.Store.html)) may not have them;
+//! `Annotation::permalink` and `Annotation::incontext_link` fall back to the functions here.
+
+use crate::AnnotationID;
+
+/// Pages of single annotations
+pub const ANNOTATION_PAGE_URL: &str = "https://hypothes.is/a";
+/// Redirects to an annotation shown on its document
+pub const BOUNCER_URL: &str = "https://hyp.is";
+/// Proxy that opens a document with the Hypothesis client loaded
+pub const VIA_URL: &str = "https://via.hypothes.is";
+
+/// The page of the annotation with `id`
+///
+/// # Example
+/// ```
+/// use hypothesis::links::permalink;
+/// assert_eq!(permalink(&"NkyAIJ4gEeqHTQ".into()), "https://hypothes.is/a/NkyAIJ4gEeqHTQ");
+/// ```
+pub fn permalink(id: &AnnotationID) -> String {
+    format!("{}/{}", ANNOTATION_PAGE_URL, id)
+}
+
+/// Link that opens `uri` scrolled to the annotation with `id`
+///
+/// Web pages are linked to without their scheme, as by the API. For other URIs (e.g. PDF
+/// fingerprints) the link leaves finding the document to the bouncer.
+///
+/// # Example
+/// ```
+/// use hypothesis::links::incontext;
+/// assert_eq!(
+///     incontext("https://example.com/post?page=2", &"NkyAIJ4gEeqHTQ".into()),
+///     "https://hyp.is/NkyAIJ4gEeqHTQ/example.com/post?page=2"
+/// );
+/// assert_eq!(incontext("urn:x-pdf:abc123", &"NkyAIJ4gEeqHTQ".into()), "https://hyp.is/NkyAIJ4gEeqHTQ");
+/// ```
+pub fn incontext(uri: &str, id: &AnnotationID) -> String {
+    let uri = uri.trim();
+    match ["https://", "http://"]
+        .iter()
+        .find_map(|scheme| uri.strip_prefix(scheme))
+    {
+        Some(location) => format!("{}/{}/{}", BOUNCER_URL, id, location),
+        None => format!("{}/{}", BOUNCER_URL, id),
+    }
+}
+
+/// Link that opens `uri` through the Via proxy, with annotations shown
+///
+/// # Example
+/// ```
+/// use hypothesis::links::via_proxy;
+/// assert_eq!(via_proxy("https://example.com/post"), "https://via.hypothes.is/https://example.com/post");
+/// ```
+pub fn via_proxy(uri: &str) -> String {
+    format!("{}/{}", VIA_URL, uri.trim())
+}
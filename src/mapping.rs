@@ -0,0 +1,128 @@
+//! Mapping files for copying / migrating annotations between groups and accounts
+//!
+//! A mapping file records which old group maps to which new group, and which tag marks
+//! annotations originally made by which user, so cross-account operations are repeatable and
+//! reviewable. It's stored as JSON:
+//! ```json
+//! {
+//!   "groups": { "oldGroupId": "newGroupId" },
+//!   "default_group": "__world__",
+//!   "users": { "acct:alice@hypothes.is": "from:alice" },
+//!   "annotations": { "originalId": "copyId" }
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{Annotation, InputAnnotation, Permissions};
+use crate::errors::HypothesisError;
+use crate::{AnnotationID, GroupID, Hypothesis, UserAccountID};
+
+/// Old group -> new group and old user -> provenance tag mapping
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Mapping {
+    /// Old group ID -> new group ID
+    #[serde(default)]
+    pub groups: HashMap<GroupID, GroupID>,
+    /// Group for annotations whose group isn't in `groups`.
+    /// If None, such annotations keep their group.
+    #[serde(default)]
+    pub default_group: Option<GroupID>,
+    /// Old user -> tag added to copies of their annotations
+    #[serde(default)]
+    pub users: HashMap<UserAccountID, String>,
+    /// Original annotation ID -> ID of its copy, kept up to date by
+    /// [`Hypothesis::mirror`](../struct.Hypothesis.html#method.mirror)
+    #[serde(default)]
+    pub annotations: HashMap<AnnotationID, AnnotationID>,
+}
+
+impl Mapping {
+    /// Reads a mapping file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HypothesisError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes a mapping file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HypothesisError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Group that copies of annotations from `group` go to
+    pub fn map_group(&self, group: &GroupID) -> GroupID {
+        self.groups
+            .get(group)
+            .or(self.default_group.as_ref())
+            .unwrap_or(group)
+            .to_owned()
+    }
+
+    /// A copy of `annotation`, made by `owner`, with its group mapped and provenance tag added
+    ///
+    /// Copies of private annotations are private to `owner`; copies of shared annotations are
+    /// shared with the group they go to.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::annotations::Permissions;
+    /// use hypothesis::mapping::Mapping;
+    /// # let annotation: hypothesis::annotations::Annotation = serde_json::from_value(serde_json::json!({
+    /// #     "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #     "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "",
+    /// #     "tags": ["rust"], "group": "oldGroup", "hidden": false, "flagged": false, "links": {},
+    /// #     "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// # })).unwrap();
+    /// let mapping: Mapping = serde_json::from_str(r#"{
+    ///     "groups": {"oldGroup": "newGroup"},
+    ///     "users": {"acct:alice@hypothes.is": "from:alice"}
+    /// }"#).unwrap();
+    /// let owner = "acct:bob@hypothes.is".parse()?;
+    /// let copy = mapping.apply(&annotation, &owner);
+    /// assert_eq!(&*copy.group, "newGroup");
+    /// assert_eq!(copy.tags, Some(vec!["rust".to_string(), "from:alice".to_string()]));
+    ///
+    /// // a private annotation stays private
+    /// let mut private = annotation.clone();
+    /// private.permissions = Permissions::private(&private.user);
+    /// let copy = mapping.apply(&private, &owner);
+    /// assert_eq!(copy.permissions, Some(Permissions::private(&owner)));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn apply(&self, annotation: &Annotation, owner: &UserAccountID) -> InputAnnotation {
+        let mut input = annotation.to_input();
+        if let Some(tag) = self.users.get(&annotation.user) {
+            let tags = input.tags.get_or_insert_with(Vec::new);
+            if !tags.contains(tag) {
+                tags.push(tag.to_owned());
+            }
+        }
+        input.group = self.map_group(&annotation.group);
+        // replies would stay in their parent's group, so copies are top-level annotations
+        input.references = None;
+        input.document = annotation.document.clone();
+        // left unset, the API shares the copy with its new group
+        input.permissions = annotation.is_private().then(|| Permissions::private(owner));
+        input
+    }
+}
+
+impl Hypothesis {
+    /// Create copies of `annotations` according to `mapping`, see [`Mapping::apply`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = annotations.len())))]
+    pub async fn copy_annotations(
+        &self,
+        annotations: &[Annotation],
+        mapping: &Mapping,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let inputs: Vec<_> = annotations
+            .iter()
+            .map(|a| mapping.apply(a, self.user()))
+            .collect();
+        self.create_annotations(&inputs).await
+    }
+}
@@ -0,0 +1,181 @@
+//! Prometheus metrics for the API calls the client makes
+//!
+//! [`Metrics`] collects request counts, failures, rate-limit hits and latencies through the
+//! request hook and renders them in the Prometheus text exposition format,
+//! to be served from a `/metrics` endpoint by the embedding application.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use std::sync::Arc;
+//! use hypothesis::Hypothesis;
+//! use hypothesis::metrics::Metrics;
+//! let metrics = Arc::new(Metrics::default());
+//! let mut api = Hypothesis::new("username", "developer_key")?;
+//! api.set_request_hook(metrics.hook());
+//! # metrics.record(&hypothesis::hooks::RequestInfo {
+//! #     method: reqwest::Method::GET,
+//! #     url: "https://api.hypothes.is/api/annotations/NkyAIJ4gEeqHTQ".parse().unwrap(),
+//! #     status: Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+//! #     latency: std::time::Duration::from_millis(250),
+//! #     api_version: None,
+//! # });
+//! // e.g. in the handler of GET /metrics
+//! let body = metrics.render();
+//! # assert!(body.contains(r#"hypothesis_rate_limited_total{method="GET",endpoint="/annotations/:id"} 1"#));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use reqwest::StatusCode;
+
+use crate::hooks::RequestInfo;
+use crate::quota::QuotaUsage;
+
+/// Totals for one method and endpoint
+#[derive(Debug, Clone, Default)]
+struct EndpointMetrics {
+    /// Requests by response status, "none" if no response was received
+    requests: BTreeMap<String, u64>,
+    /// Requests without a response or with a 4xx / 5xx status
+    failures: u64,
+    /// Responses with status 429 Too Many Requests
+    rate_limited: u64,
+    latency_seconds: f64,
+}
+
+/// Collects metrics of the API calls reported to it, see the [module docs](index.html)
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// By (method, endpoint)
+    endpoints: Mutex<BTreeMap<(String, String), EndpointMetrics>>,
+}
+
+impl Metrics {
+    /// Counts an API call
+    pub fn record(&self, info: &RequestInfo) {
+        let key = (info.method.to_string(), info.endpoint());
+        let mut endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        let metrics = endpoints.entry(key).or_default();
+        let status = info
+            .status
+            .map_or_else(|| "none".to_owned(), |status| status.as_u16().to_string());
+        *metrics.requests.entry(status).or_default() += 1;
+        if info
+            .status
+            .is_none_or(|status| status.is_client_error() || status.is_server_error())
+        {
+            metrics.failures += 1;
+        }
+        if info.status == Some(StatusCode::TOO_MANY_REQUESTS) {
+            metrics.rate_limited += 1;
+        }
+        metrics.latency_seconds += info.latency.as_secs_f64();
+    }
+
+    /// A request hook recording every API call into these metrics, for
+    /// [`Hypothesis::set_request_hook`](../struct.Hypothesis.html#method.set_request_hook)
+    pub fn hook(self: &Arc<Self>) -> impl Fn(&RequestInfo) + Send + Sync + 'static {
+        let metrics = Arc::clone(self);
+        move |info| metrics.record(info)
+    }
+
+    /// The metrics in the Prometheus text exposition format, empty before the first API call
+    pub fn render(&self) -> String {
+        let endpoints = self.endpoints.lock().unwrap_or_else(|e| e.into_inner());
+        if endpoints.is_empty() {
+            return String::new();
+        }
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+        let labels = |(method, endpoint): &(String, String)| {
+            format!("method=\"{}\",endpoint=\"{}\"", method, endpoint)
+        };
+        family(
+            "hypothesis_requests_total",
+            "counter",
+            "API calls by response status",
+            &endpoints
+                .iter()
+                .flat_map(|(key, metrics)| {
+                    metrics.requests.iter().map(move |(status, count)| {
+                        (
+                            format!("{},status=\"{}\"", labels(key), status),
+                            count.to_string(),
+                        )
+                    })
+                })
+                .collect::<Vec<_>>(),
+        );
+        family(
+            "hypothesis_request_failures_total",
+            "counter",
+            "API calls without a response or with a 4xx / 5xx status",
+            &endpoints
+                .iter()
+                .map(|(key, metrics)| (labels(key), metrics.failures.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        family(
+            "hypothesis_rate_limited_total",
+            "counter",
+            "API calls rejected with 429 Too Many Requests",
+            &endpoints
+                .iter()
+                .map(|(key, metrics)| (labels(key), metrics.rate_limited.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        family(
+            "hypothesis_request_duration_seconds_sum",
+            "counter",
+            "Total time until the response headers were received",
+            &endpoints
+                .iter()
+                .map(|(key, metrics)| (labels(key), metrics.latency_seconds.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        out
+    }
+}
+
+/// Calls per endpoint within the quota window of `usage` as a Prometheus gauge, see the
+/// [`quota`](../quota/index.html) module
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use hypothesis::Hypothesis;
+/// use hypothesis::metrics::render_quota;
+/// let api = Hypothesis::new("username", "developer_key")?;
+/// let body = render_quota(&api.quota_usage());
+/// assert!(body.starts_with("# HELP hypothesis_quota_window_calls"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn render_quota(usage: &QuotaUsage) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP hypothesis_quota_window_calls API calls in the last {} seconds",
+        usage.window_length.as_secs()
+    );
+    let _ = writeln!(out, "# TYPE hypothesis_quota_window_calls gauge");
+    for (endpoint, calls) in &usage.window.by_endpoint {
+        let _ = writeln!(
+            out,
+            "hypothesis_quota_window_calls{{endpoint=\"{}\"}} {}",
+            endpoint, calls
+        );
+    }
+    out
+}
@@ -0,0 +1,239 @@
+//! Keep one group or account in sync with another, e.g. to publish a private group's reviewed
+//! annotations in a public group
+//!
+//! Which copy belongs to which original is recorded in the `annotations` of a
+//! [`Mapping`](../mapping/struct.Mapping.html) file, which also maps groups and adds provenance
+//! tags to the copies as in [`Hypothesis::copy_annotations`](../struct.Hypothesis.html#method.copy_annotations).
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{Annotation, InputAnnotation, SearchQuery};
+use crate::diff::Identity;
+use crate::errors::HypothesisError;
+use crate::mapping::Mapping;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::{AnnotationID, GroupID, Hypothesis, UserAccountID};
+
+/// Options for [`Hypothesis::mirror`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MirrorOptions {
+    /// Mapping file, created if it doesn't exist yet
+    pub mapping: PathBuf,
+    /// Also copy new and updated annotations from the target back to the source
+    pub both_ways: bool,
+    /// Only report what would be copied, without changing annotations or the mapping file
+    pub dry_run: bool,
+}
+
+/// What a [`Hypothesis::mirror`] run did, by ID of the original annotations
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MirrorSummary {
+    /// Copied for the first time
+    pub copied: Vec<AnnotationID>,
+    /// Changed since they were copied, so their copies were updated
+    pub updated: Vec<AnnotationID>,
+    /// Already had a copy with the same uri and quote (see [`Identity::Anchor`]), which is now
+    /// recorded in the mapping file
+    pub linked: Vec<(AnnotationID, AnnotationID)>,
+}
+
+/// A change to make on the receiving side
+enum Step {
+    Copy {
+        original: AnnotationID,
+        copy: InputAnnotation,
+    },
+    Update {
+        original: AnnotationID,
+        copy: AnnotationID,
+        changes: InputAnnotation,
+    },
+}
+
+/// Steps to mirror `from` into `to` with copies made by `owner`, adding matches by anchor to
+/// `summary.linked`
+fn plan(
+    from: &[Annotation],
+    to: &[Annotation],
+    mapping: &Mapping,
+    group: Option<&GroupID>,
+    owner: &UserAccountID,
+    summary: &mut MirrorSummary,
+) -> Vec<Step> {
+    // originals and copies are each other's partners, whichever way they were copied
+    let mut partners: HashMap<&AnnotationID, &AnnotationID> = HashMap::new();
+    for (original, copy) in &mapping.annotations {
+        partners.insert(original, copy);
+        partners.insert(copy, original);
+    }
+    let to_by_id: HashMap<&AnnotationID, &Annotation> = to.iter().map(|a| (&a.id, a)).collect();
+    let mut to_by_anchor: HashMap<String, &Annotation> = HashMap::new();
+    for annotation in to.iter().filter(|a| !partners.contains_key(&a.id)) {
+        to_by_anchor
+            .entry(Identity::Anchor.key(annotation))
+            .or_insert(annotation);
+    }
+    let mut steps = Vec::new();
+    for annotation in from {
+        let mut copy = mapping.apply(annotation, owner);
+        if let Some(group) = group {
+            copy.group = group.clone();
+        }
+        match partners.get(&annotation.id) {
+            Some(partner) => {
+                // a deleted copy stays deleted
+                let Some(partner) = to_by_id.get(partner) else {
+                    continue;
+                };
+                let changed = copy.text.as_ref() != Some(&partner.text)
+                    || copy.tags.as_ref() != Some(&partner.tags);
+                if changed && annotation.updated > partner.updated {
+                    steps.push(Step::Update {
+                        original: annotation.id.clone(),
+                        copy: partner.id.clone(),
+                        changes: InputAnnotation {
+                            text: copy.text,
+                            tags: copy.tags,
+                            ..Default::default()
+                        },
+                    });
+                }
+            }
+            None => match to_by_anchor.remove(&Identity::Anchor.key(annotation)) {
+                Some(existing) => summary
+                    .linked
+                    .push((annotation.id.clone(), existing.id.clone())),
+                None => steps.push(Step::Copy {
+                    original: annotation.id.clone(),
+                    copy,
+                }),
+            },
+        }
+    }
+    steps
+}
+
+impl Hypothesis {
+    /// Copy annotations matching `source_scope` that are new or changed since the last run to the
+    /// group of `target_scope` (or the group given by the mapping file if it has none), and
+    /// with `options.both_ways` the other way round too.
+    ///
+    /// An annotation changed since it was copied if its text or tags differ from its copy's and
+    /// it was updated more recently. Copies that were deleted aren't made again.
+    /// `progress` is told about every annotation copied or updated, and can
+    /// [cancel](../progress/index.html#cancellation) the run. If the run fails or is cancelled
+    /// part-way, the mapping file still records the copies made, so running it again continues
+    /// without copying them twice.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::mirror::MirrorOptions;
+    /// let api = Hypothesis::from_env()?;
+    /// let reviewed = SearchQuery::builder().group("privateGroupId").tag("reviewed").build()?;
+    /// let public = SearchQuery::builder().group("publicGroupId").build()?;
+    /// let options = MirrorOptions {
+    ///     mapping: "mirror.json".into(),
+    ///     ..Default::default()
+    /// };
+    /// let summary = api.mirror(&reviewed, &public, &options, ()).await?;
+    /// println!("{} copied, {} updated", summary.copied.len(), summary.updated.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(source = %source_scope.summary(), target = %target_scope.summary())))]
+    pub async fn mirror(
+        &self,
+        source_scope: &SearchQuery,
+        target_scope: &SearchQuery,
+        options: &MirrorOptions,
+        progress: impl ProgressObserver,
+    ) -> Result<MirrorSummary, HypothesisError> {
+        let mut mapping = if options.mapping.exists() {
+            Mapping::load(&options.mapping)?
+        } else {
+            Mapping::default()
+        };
+        let source = self
+            .search_annotations_return_all(&mut source_scope.clone())
+            .await?;
+        let target = self
+            .search_annotations_return_all(&mut target_scope.clone())
+            .await?;
+        let group = |scope: &SearchQuery| (!scope.group.is_empty()).then(|| scope.group.clone());
+        let mut summary = MirrorSummary::default();
+        let mut steps = plan(
+            &source,
+            &target,
+            &mapping,
+            group(target_scope).as_ref(),
+            self.user(),
+            &mut summary,
+        );
+        if options.both_ways {
+            // pairs found by anchor aren't matched again the other way
+            for (original, copy) in &summary.linked {
+                mapping.annotations.insert(original.clone(), copy.clone());
+            }
+            steps.extend(plan(
+                &target,
+                &source,
+                &mapping,
+                group(source_scope).as_ref(),
+                self.user(),
+                &mut summary,
+            ));
+        }
+        for (original, copy) in &summary.linked {
+            mapping.annotations.insert(original.clone(), copy.clone());
+        }
+        let mut tracker = Tracker::new(progress, steps.len());
+        let mut outcome = Ok(());
+        for step in steps {
+            if let Err(error) = tracker.check_cancelled() {
+                outcome = Err(error);
+                break;
+            }
+            match step {
+                Step::Copy { original, copy } => {
+                    if !options.dry_run {
+                        match tracker.record(self.create_annotation(&copy).await) {
+                            Ok(copy) => mapping.annotations.insert(original.clone(), copy.id),
+                            Err(error) => {
+                                outcome = Err(error);
+                                break;
+                            }
+                        };
+                    }
+                    summary.copied.push(original);
+                }
+                Step::Update {
+                    original,
+                    copy,
+                    changes,
+                } => {
+                    if !options.dry_run {
+                        if let Err(error) =
+                            tracker.record(self.patch_annotation(&copy, &changes).await)
+                        {
+                            outcome = Err(error);
+                            break;
+                        }
+                    }
+                    summary.updated.push(original);
+                }
+            }
+        }
+        // copies made before a failure or cancellation are kept, so that they aren't made again
+        if !options.dry_run {
+            mapping.save(&options.mapping)?;
+        }
+        outcome.map(|()| summary)
+    }
+}
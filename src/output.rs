@@ -0,0 +1,222 @@
+//! Lists of annotations, groups or other records as a table, JSON, JSON lines or CSV, with a
+//! choice of fields
+//!
+//! Fields are named by their JSON key, and fields of nested objects by dotted paths such as
+//! `document.title` or `user_info.display_name`. Without a selection, all top-level fields are
+//! written, in alphabetical order.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use hypothesis::output::{write_records, OutputFormat};
+//! use serde_json::json;
+//! let records = vec![
+//!     json!({"id": "a1", "tags": ["rust", "async"], "text": "Pin explained"}),
+//!     json!({"id": "b22", "tags": [], "text": "Line one\nline two"}),
+//! ];
+//! let format: OutputFormat = "table".parse()?;
+//! let mut table = Vec::new();
+//! write_records(&records, format, &["id", "tags", "text"], &mut table)?;
+//! assert_eq!(String::from_utf8(table).unwrap(), "\
+//! id   tags         text
+//! a1   rust, async  Pin explained
+//! b22               Line one line two
+//! ");
+//! let mut jsonl = Vec::new();
+//! write_records(&records, OutputFormat::Jsonl, &["id"], &mut jsonl)?;
+//! assert_eq!(String::from_utf8(jsonl).unwrap(), "{\"id\":\"a1\"}\n{\"id\":\"b22\"}\n");
+//! // CSV keeps line breaks, in quoted cells
+//! let mut csv = Vec::new();
+//! write_records(&records, OutputFormat::Csv, &["id", "text"], &mut csv)?;
+//! assert_eq!(String::from_utf8(csv).unwrap(), "id,text\na1,Pin explained\nb22,\"Line one\nline two\"\n");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::errors::HypothesisError;
+
+/// Longest table cell, longer values are cut
+pub const MAX_CELL_CHARS: usize = 80;
+
+/// How records are written
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Aligned columns with a header, for people
+    #[default]
+    Table,
+    /// A single JSON array
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// CSV with a header row
+    Csv,
+}
+
+impl OutputFormat {
+    const ALL: [Self; 4] = [Self::Table, Self::Json, Self::Jsonl, Self::Csv];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Jsonl => "jsonl",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = HypothesisError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                HypothesisError::FormatError(format!(
+                    "unknown output format {:?}, expected table, json, jsonl or csv",
+                    s
+                ))
+            })
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Splits a comma-separated field selection such as "id,uri,text,tags"
+///
+/// # Example
+/// ```
+/// use hypothesis::output::parse_fields;
+/// assert_eq!(parse_fields("id, uri,,document.title"), vec!["id", "uri", "document.title"]);
+/// ```
+pub fn parse_fields(fields: &str) -> Vec<&str> {
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+/// The value at a dotted `path`, Null if there is none
+fn field<'a>(record: &'a Value, path: &str) -> &'a Value {
+    path.split('.')
+        .try_fold(record, |value, key| value.get(key))
+        .unwrap_or(&Value::Null)
+}
+
+/// The value as text: strings as they are, lists joined by commas, other values as JSON
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        Value::Array(values) => values.iter().map(cell).collect::<Vec<_>>().join(", "),
+        other => other.to_string(),
+    }
+}
+
+/// Writes `records` in `format` with only the given `fields`, or all top-level ones if empty
+pub fn write_records<T: Serialize>(
+    records: &[T],
+    format: OutputFormat,
+    fields: &[&str],
+    mut writer: impl Write,
+) -> Result<(), HypothesisError> {
+    let records = records
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let columns: Vec<String> = if fields.is_empty() {
+        records
+            .first()
+            .and_then(Value::as_object)
+            .map(|object| object.keys().cloned().collect())
+            .unwrap_or_default()
+    } else {
+        fields.iter().map(|&field| field.to_owned()).collect()
+    };
+    let selected = |record: &Value| -> Value {
+        if fields.is_empty() {
+            return record.clone();
+        }
+        let object: Map<String, Value> = columns
+            .iter()
+            .map(|column| (column.clone(), field(record, column).clone()))
+            .collect();
+        Value::Object(object)
+    };
+    match format {
+        OutputFormat::Json => {
+            let records: Vec<Value> = records.iter().map(selected).collect();
+            serde_json::to_writer_pretty(&mut writer, &records)?;
+            writeln!(writer)?;
+        }
+        OutputFormat::Jsonl => {
+            for record in &records {
+                serde_json::to_writer(&mut writer, &selected(record))?;
+                writeln!(writer)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.write_record(&columns)?;
+            for record in &records {
+                writer.write_record(columns.iter().map(|column| cell(field(record, column))))?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = records
+                .iter()
+                .map(|record| {
+                    columns
+                        .iter()
+                        .map(|column| {
+                            // one line per row
+                            let cell = cell(field(record, column))
+                                .split_whitespace()
+                                .collect::<Vec<_>>()
+                                .join(" ");
+                            if cell.chars().count() <= MAX_CELL_CHARS {
+                                return cell;
+                            }
+                            let cut: String = cell.chars().take(MAX_CELL_CHARS - 1).collect();
+                            cut + "…"
+                        })
+                        .collect()
+                })
+                .collect();
+            let widths: Vec<usize> = columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    rows.iter()
+                        .map(|row| row[i].chars().count())
+                        .chain([column.chars().count()])
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect();
+            for row in std::iter::once(&columns).chain(&rows) {
+                let line: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, &width)| format!("{:<width$}", value, width = width))
+                    .collect();
+                writeln!(writer, "{}", line.join("  ").trim_end())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Fingerprints of local PDF files, to annotate them under the same `urn:x-pdf:` URI that the
+//! Hypothesis client uses when the PDF is opened in a browser
+//!
+//! The fingerprint is computed as by PDF.js: the first file identifier in the trailer's `/ID`
+//! entry, or the MD5 hash of the first kilobyte of the file if there is none.
+//!
+//! # Example
+//! ```no_run
+//! # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use hypothesis::annotations::InputAnnotation;
+//! use hypothesis::pdf::PdfFile;
+//! let pdf = PdfFile::open("paper.pdf")?;
+//! let annotation = InputAnnotation::builder()
+//!     .uri(pdf.uri())
+//!     .document(pdf.document("https://example.com/paper.pdf"))
+//!     .text("Read this first")
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use crate::annotations::{Document, HighWire, Link};
+use crate::errors::HypothesisError;
+
+/// Bytes hashed for PDFs without a file identifier
+const FINGERPRINT_FIRST_BYTES: usize = 1024;
+
+/// A PDF and its fingerprint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdfFile {
+    /// Lowercase hex
+    pub fingerprint: String,
+}
+
+impl PdfFile {
+    /// Reads the PDF at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HypothesisError> {
+        Ok(Self::from_bytes(&fs::read(path)?))
+    }
+
+    /// Fingerprints the contents of a PDF
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::pdf::PdfFile;
+    /// let pdf = PdfFile::from_bytes(b"%PDF-1.4\n...\ntrailer\n<< /Size 1 /ID [<8A3F0E2B> <8A3F0E2B>] >>\n%%EOF");
+    /// assert_eq!(pdf.fingerprint, "8a3f0e2b");
+    /// assert_eq!(pdf.uri(), "urn:x-pdf:8a3f0e2b");
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let fingerprint = match file_id(bytes).filter(|id| id.iter().any(|&b| b != 0)) {
+            Some(id) => id.iter().map(|b| format!("{:02x}", b)).collect(),
+            None => format!(
+                "{:x}",
+                md5::compute(&bytes[..bytes.len().min(FINGERPRINT_FIRST_BYTES)])
+            ),
+        };
+        Self { fingerprint }
+    }
+
+    /// The URI annotations on this PDF are made on
+    pub fn uri(&self) -> String {
+        format!("urn:x-pdf:{}", self.fingerprint)
+    }
+
+    /// Document metadata linking the fingerprint to the URL the PDF is served from,
+    /// as the Hypothesis client sends it
+    pub fn document(&self, pdf_url: &str) -> Document {
+        Document {
+            highwire: Some(HighWire {
+                pdf_url: vec![pdf_url.to_owned()],
+                ..Default::default()
+            }),
+            link: vec![
+                Link {
+                    href: self.uri(),
+                    link_type: String::new(),
+                },
+                Link {
+                    href: pdf_url.to_owned(),
+                    link_type: String::new(),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+/// First string of the last `/ID` entry, i.e. that of the latest trailer
+fn file_id(bytes: &[u8]) -> Option<Vec<u8>> {
+    let start = bytes.windows(3).rposition(|w| w == b"/ID")? + 3;
+    let mut rest = skip_whitespace(&bytes[start..]);
+    rest = skip_whitespace(rest.strip_prefix(b"[")?);
+    let id = match rest.first()? {
+        b'<' => hex_string(&rest[1..])?,
+        b'(' => literal_string(&rest[1..])?,
+        _ => return None,
+    };
+    (!id.is_empty()).then_some(id)
+}
+
+fn skip_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+/// Contents of `<...>` up to the closing `>`, a missing last digit counts as 0
+fn hex_string(bytes: &[u8]) -> Option<Vec<u8>> {
+    let end = bytes.iter().position(|&b| b == b'>')?;
+    let digits: Vec<u8> = bytes[..end]
+        .iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .map(|&b| (b as char).to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+            .collect(),
+    )
+}
+
+/// Contents of `(...)` up to the matching `)`, with escapes resolved
+fn literal_string(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut string = Vec::new();
+    let mut depth = 0;
+    let mut bytes = bytes.iter().copied().peekable();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next()? {
+                b'n' => string.push(b'\n'),
+                b'r' => string.push(b'\r'),
+                b't' => string.push(b'\t'),
+                b'b' => string.push(8),
+                b'f' => string.push(12),
+                // line continuation
+                b'\r' | b'\n' => {}
+                octal @ b'0'..=b'7' => {
+                    let mut value = u32::from(octal - b'0');
+                    for _ in 0..2 {
+                        match bytes.peek() {
+                            Some(&digit @ b'0'..=b'7') => {
+                                value = value * 8 + u32::from(digit - b'0');
+                                bytes.next();
+                            }
+                            _ => break,
+                        }
+                    }
+                    string.push(value as u8);
+                }
+                other => string.push(other),
+            },
+            b'(' => {
+                depth += 1;
+                string.push(b);
+            }
+            b')' if depth == 0 => return Some(string),
+            b')' => {
+                depth -= 1;
+                string.push(b);
+            }
+            _ => string.push(b),
+        }
+    }
+    None
+}
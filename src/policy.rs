@@ -0,0 +1,119 @@
+//! Client-level URL allow / deny lists
+//!
+//! A safety net for automated annotators: the client checks the URI of every annotation it's
+//! asked to create or update against its [`UrlPolicy`] and refuses with a
+//! [`HypothesisError::PolicyError`](crate::errors::HypothesisError::PolicyError) instead of
+//! sending the request.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors;
+
+/// URL patterns that annotations may (not) be created on.
+///
+/// Patterns can contain `*` wildcards, matching any sequence of characters.
+/// Patterns containing "://" are matched against the whole URI, others against the URI's host.
+///
+/// A URI is rejected if it matches any `deny` pattern, or if `allow` is non-empty and the URI
+/// matches none of its patterns.
+///
+/// # Example
+/// ```
+/// use hypothesis::policy::UrlPolicy;
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// let policy = UrlPolicy::builder()
+///     .deny(vec!["*.intranet.example.com".into(), "https://example.com/private/*".into()])
+///     .build()?;
+/// assert!(policy.check("https://example.com/public/page").is_ok());
+/// assert!(policy.check("https://wiki.intranet.example.com/page").is_err());
+/// assert!(policy.check("https://example.com/private/page").is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Builder)]
+#[builder(default, build_fn(name = "builder"))]
+pub struct UrlPolicy {
+    /// If not empty, only URIs matching one of these patterns are allowed
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// URIs matching any of these patterns are never allowed
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl UrlPolicy {
+    pub fn builder() -> UrlPolicyBuilder {
+        UrlPolicyBuilder::default()
+    }
+
+    /// Checks whether annotations may be created on `uri`
+    pub fn check(&self, uri: &str) -> Result<(), PolicyViolation> {
+        if let Some(pattern) = self.deny.iter().find(|p| pattern_matches(p, uri)) {
+            return Err(PolicyViolation::Denied(pattern.to_owned()));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|p| pattern_matches(p, uri)) {
+            return Err(PolicyViolation::NotAllowed);
+        }
+        Ok(())
+    }
+}
+
+impl UrlPolicyBuilder {
+    /// Builds a new `UrlPolicy`.
+    pub fn build(&self) -> Result<UrlPolicy, errors::HypothesisError> {
+        self.builder()
+            .map_err(|e| errors::HypothesisError::BuilderError(e.to_string()))
+    }
+}
+
+/// Why a URI was rejected by a [`UrlPolicy`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// Matched this deny pattern
+    Denied(String),
+    /// Didn't match any allow pattern
+    NotAllowed,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Denied(pattern) => write!(f, "matches deny pattern {:?}", pattern),
+            Self::NotAllowed => write!(f, "doesn't match any allow pattern"),
+        }
+    }
+}
+
+fn pattern_matches(pattern: &str, uri: &str) -> bool {
+    if pattern.contains("://") {
+        wildcard_matches(pattern, uri)
+    } else {
+        url::Url::parse(uri)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| wildcard_matches(pattern, host)))
+            .unwrap_or(false)
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` matches any (possibly empty) sequence
+pub(crate) fn wildcard_matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcard at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
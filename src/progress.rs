@@ -0,0 +1,212 @@
+//! Progress reporting for bulk operations such as tag renames, replacements and mirroring
+//!
+//! Bulk operations take a [`ProgressObserver`], which is told after every item how many were
+//! processed out of how many. Pass
+//! - `()` to ignore progress,
+//! - a closure `|done, total| ...`,
+//! - [`OnProgress`] wrapping a closure that gets the full [`Progress`], including the estimated
+//!   time left,
+//! - or the sending half of a tokio `watch` or unbounded `mpsc` channel, to render progress
+//!   (e.g. as a progress bar) in another task.
+//!
+//! # Cancellation
+//! Pass a [`CancellationToken`], or any observer together with one in [`WithCancellation`], to
+//! be able to stop an operation early, e.g. on Ctrl-C. The operation then finishes the item it
+//! is on, leaves a consistent state (e.g. [`Hypothesis::mirror`](../struct.Hypothesis.html#method.mirror)
+//! saves its mapping file) and fails with
+//! [`HypothesisError::Cancelled`](../errors/enum.HypothesisError.html), which tells how far it got.
+//! Running it again continues with the items that are left: those done before no longer match,
+//! or are left out, like annotations [`Hypothesis::broadcast_reply`](../struct.Hypothesis.html#method.broadcast_reply)
+//! already replied to. Dropping the operation's future
+//! instead can lose the outcome of the item in flight, and mirroring's mapping updates.
+//!
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::errors::HypothesisError;
+//! use hypothesis::progress::{CancellationToken, WithCancellation};
+//! let api = Hypothesis::from_env()?;
+//! let scope = SearchQuery::builder().user(&api.user().0).build()?;
+//! let token = CancellationToken::new();
+//! // e.g. cancelled from a Ctrl-C handler, here after ten minutes
+//! let deadline = token.clone();
+//! tokio::spawn(async move {
+//!     tokio::time::sleep(std::time::Duration::from_secs(600)).await;
+//!     deadline.cancel();
+//! });
+//! let report = |done, total| eprint!("\r{}/{}", done, total);
+//! match api.rename_tag("rustlang", "rust", &scope, WithCancellation(report, token)).await {
+//!     Err(HypothesisError::Cancelled(progress)) => eprintln!("\nstopped after {}", progress.done),
+//!     result => println!("\nrenamed in {} annotations", result?.len()),
+//! }
+//! #     Ok(())
+//! # }
+//! ```
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::progress::{OnProgress, Progress};
+//! let api = Hypothesis::from_env()?;
+//! let scope = SearchQuery::builder().user(&api.user().0).build()?;
+//! let report = OnProgress(|progress: &Progress| {
+//!     eprint!("\r{}/{} (ETA {:?})", progress.done, progress.total, progress.eta());
+//! });
+//! api.rename_tag("rustlang", "rust", &scope, report).await?;
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+pub use tokio_util::sync::CancellationToken;
+
+use crate::errors::HypothesisError;
+
+/// How far a bulk operation got
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Items processed so far. Operations stop at the first item that fails, with its error.
+    pub done: usize,
+    pub total: usize,
+    /// Since the operation started processing items
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Time left if the remaining items take as long as the processed ones, None before the first
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use hypothesis::progress::Progress;
+    /// let progress = Progress { done: 10, total: 40, elapsed: Duration::from_secs(5) };
+    /// assert_eq!(progress.eta(), Some(Duration::from_secs(15)));
+    /// ```
+    pub fn eta(&self) -> Option<Duration> {
+        let done = u32::try_from(self.done).ok().filter(|&done| done > 0)?;
+        let left = u32::try_from(self.total.saturating_sub(self.done)).ok()?;
+        Some(self.elapsed / done * left)
+    }
+}
+
+/// Gets told about the progress of a bulk operation, see the [module docs](index.html)
+pub trait ProgressObserver {
+    /// Called after every processed item
+    fn update(&mut self, progress: &Progress);
+
+    /// Checked before every item, the operation stops if true
+    fn cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Ignores progress
+impl ProgressObserver for () {
+    fn update(&mut self, _progress: &Progress) {}
+}
+
+/// Called with the number of processed items and the total
+impl<F: FnMut(usize, usize)> ProgressObserver for F {
+    fn update(&mut self, progress: &Progress) {
+        self(progress.done, progress.total)
+    }
+}
+
+/// Calls the wrapped closure with the full [`Progress`]
+pub struct OnProgress<F>(pub F);
+
+impl<F: FnMut(&Progress)> ProgressObserver for OnProgress<F> {
+    fn update(&mut self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+/// Keeps the latest progress, receivers that went away are ignored
+impl ProgressObserver for watch::Sender<Progress> {
+    fn update(&mut self, progress: &Progress) {
+        let _ = self.send(*progress);
+    }
+}
+
+/// Sends every update, receivers that went away are ignored
+impl ProgressObserver for mpsc::UnboundedSender<Progress> {
+    fn update(&mut self, progress: &Progress) {
+        let _ = self.send(*progress);
+    }
+}
+
+/// Ignores progress, cancels when the token is cancelled
+impl ProgressObserver for CancellationToken {
+    fn update(&mut self, _progress: &Progress) {}
+
+    fn cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+/// Passes progress to the observer, cancels when the token is cancelled
+pub struct WithCancellation<O>(pub O, pub CancellationToken);
+
+impl<O: ProgressObserver> ProgressObserver for WithCancellation<O> {
+    fn update(&mut self, progress: &Progress) {
+        self.0.update(progress)
+    }
+
+    fn cancelled(&self) -> bool {
+        self.1.is_cancelled() || self.0.cancelled()
+    }
+}
+
+/// Counts processed items for an observer
+pub(crate) struct Tracker<O> {
+    observer: O,
+    start: Instant,
+    progress: Progress,
+}
+
+impl<O: ProgressObserver> Tracker<O> {
+    pub(crate) fn new(observer: O, total: usize) -> Self {
+        Self {
+            observer,
+            start: Instant::now(),
+            progress: Progress {
+                total,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Sets the total once it's known, e.g. from the first page of a search
+    pub(crate) fn set_total(&mut self, total: usize) {
+        self.progress.total = total;
+    }
+
+    /// Fails with [`HypothesisError::Cancelled`] if the observer cancelled the operation, to be
+    /// checked before each item
+    pub(crate) fn check_cancelled(&self) -> Result<(), HypothesisError> {
+        if self.observer.cancelled() {
+            let mut progress = self.progress;
+            progress.elapsed = self.start.elapsed();
+            return Err(HypothesisError::Cancelled(progress));
+        }
+        Ok(())
+    }
+
+    /// Counts an item as done if `result` is a success, and passes `result` on
+    pub(crate) fn record<T, E>(&mut self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_ok() {
+            self.progress.done += 1;
+            self.progress.elapsed = self.start.elapsed();
+            self.observer.update(&self.progress);
+        }
+        result
+    }
+}
@@ -0,0 +1,111 @@
+//! Client-side accounting of API calls, so batch tools can report how much quota a run used
+//!
+//! Every [`Hypothesis`](../struct.Hypothesis.html) client counts the calls it makes, per endpoint,
+//! both in total and within a sliding window (one hour unless set with
+//! [`Hypothesis::set_quota_window`](../struct.Hypothesis.html#method.set_quota_window)).
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use hypothesis::Hypothesis;
+//! let api = Hypothesis::from_env()?;
+//! api.fetch_user_profile().await?;
+//! let usage = api.quota_usage();
+//! println!("{} calls this run, {} in the last hour", usage.total.calls, usage.window.calls);
+//! println!("{}", serde_json::to_string_pretty(&usage)?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::RequestInfo;
+
+/// Calls within the window if not set otherwise
+pub(crate) const DEFAULT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Number of API calls, overall and per endpoint (see [`RequestInfo::endpoint`])
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallCounts {
+    pub calls: u64,
+    pub by_endpoint: BTreeMap<String, u64>,
+}
+
+impl CallCounts {
+    fn add(&mut self, endpoint: &str) {
+        self.calls += 1;
+        *self.by_endpoint.entry(endpoint.to_owned()).or_default() += 1;
+    }
+}
+
+/// API calls made by a client, from [`Hypothesis::quota_usage`](../struct.Hypothesis.html#method.quota_usage)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// Since the client was made
+    pub total: CallCounts,
+    /// Within the last `window_length`
+    pub window: CallCounts,
+    pub window_length: Duration,
+}
+
+/// Counts the calls reported to it
+#[derive(Debug)]
+pub(crate) struct QuotaTracker {
+    window_length: Duration,
+    total: CallCounts,
+    /// Time and endpoint of the calls within the window, oldest first
+    recent: VecDeque<(Instant, String)>,
+}
+
+impl Default for QuotaTracker {
+    fn default() -> Self {
+        Self {
+            window_length: DEFAULT_WINDOW,
+            total: CallCounts::default(),
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl QuotaTracker {
+    pub(crate) fn record(&mut self, info: &RequestInfo) {
+        let endpoint = info.endpoint();
+        self.total.add(&endpoint);
+        self.recent.push_back((Instant::now(), endpoint));
+        self.expire();
+    }
+
+    /// Calls older than the new window are only kept if they were still within the old one
+    pub(crate) fn set_window(&mut self, window_length: Duration) {
+        self.window_length = window_length;
+        self.expire();
+    }
+
+    pub(crate) fn usage(&mut self) -> QuotaUsage {
+        self.expire();
+        let mut window = CallCounts::default();
+        for (_, endpoint) in &self.recent {
+            window.add(endpoint);
+        }
+        QuotaUsage {
+            total: self.total.clone(),
+            window,
+            window_length: self.window_length,
+        }
+    }
+
+    /// Forgets calls that left the window
+    fn expire(&mut self) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() > self.window_length)
+        {
+            self.recent.pop_front();
+        }
+    }
+}
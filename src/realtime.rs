@@ -0,0 +1,629 @@
+//! Follow new and updated annotations as a stream of events
+//!
+//! Events are found by incremental polling of the search endpoint (`search_after` on
+//! `updated`), which works behind proxies that block WebSocket connections.
+//! Deletions can't be detected this way.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::sync::watch;
+
+use crate::annotations::{Annotation, Order, PageCursor, SearchQuery, Sort};
+use crate::errors::HypothesisError;
+use crate::progress::CancellationToken;
+use crate::{GroupID, Hypothesis, UserAccountID};
+
+/// A change to an annotation
+///
+/// Serializes as `{"action": "create", "annotation": {...}}`, with the actions named as in
+/// [`Actions`], e.g. to pipe events to another program as JSON lines.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action", content = "annotation")]
+pub enum Event {
+    #[serde(rename = "create")]
+    Created(Annotation),
+    #[serde(rename = "update")]
+    Updated(Annotation),
+    /// With the last known state of the annotation.
+    /// Only replayed from a [`Store`](../store/struct.Store.html), polling can't detect deletions.
+    #[serde(rename = "delete")]
+    Deleted(Annotation),
+}
+
+impl Event {
+    fn new(annotation: Annotation) -> Self {
+        if annotation.created == annotation.updated {
+            Self::Created(annotation)
+        } else {
+            Self::Updated(annotation)
+        }
+    }
+
+    /// The annotation the event is about
+    pub fn annotation(&self) -> &Annotation {
+        match self {
+            Self::Created(annotation) | Self::Updated(annotation) | Self::Deleted(annotation) => {
+                annotation
+            }
+        }
+    }
+
+    fn into_annotation(self) -> Annotation {
+        match self {
+            Self::Created(annotation) | Self::Updated(annotation) | Self::Deleted(annotation) => {
+                annotation
+            }
+        }
+    }
+}
+
+/// How the clauses of a [`StreamFilter`] are combined
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchPolicy {
+    /// Events matching any clause
+    IncludeAny,
+    /// Events matching all clauses
+    #[default]
+    IncludeAll,
+    /// Events matching none of the clauses
+    ExcludeAny,
+    /// Events not matching all clauses
+    ExcludeAll,
+}
+
+/// Annotation field a [`Clause`] looks at
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    #[serde(rename = "/uri")]
+    Uri,
+    #[serde(rename = "/group")]
+    Group,
+    #[serde(rename = "/user")]
+    User,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    /// `value` is a single string
+    Equals,
+    /// `value` is a list of strings
+    OneOf,
+}
+
+/// A single condition of a [`StreamFilter`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub field: Field,
+    pub operator: Operator,
+    pub value: Value,
+    pub case_sensitive: bool,
+}
+
+impl Clause {
+    /// True if the field of `annotation` satisfies the clause
+    pub fn matches(&self, annotation: &Annotation) -> bool {
+        let field = match self.field {
+            Field::Uri => annotation.uri.as_str(),
+            Field::Group => &annotation.group,
+            Field::User => &annotation.user.0,
+        };
+        let equals = |value: &Value| {
+            value.as_str().is_some_and(|value| {
+                if self.case_sensitive {
+                    value == field
+                } else {
+                    value.eq_ignore_ascii_case(field)
+                }
+            })
+        };
+        match self.operator {
+            Operator::Equals => equals(&self.value),
+            Operator::OneOf => self
+                .value
+                .as_array()
+                .is_some_and(|values| values.iter().any(equals)),
+        }
+    }
+}
+
+/// Which kinds of events a [`StreamFilter`] lets through
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Actions {
+    pub create: bool,
+    pub update: bool,
+    pub delete: bool,
+}
+
+impl Default for Actions {
+    fn default() -> Self {
+        Self {
+            create: true,
+            update: true,
+            delete: true,
+        }
+    }
+}
+
+/// Clause-based event filter, in the format of the Hypothesis streamer's `filter` message
+///
+/// # Example
+/// ```
+/// use hypothesis::realtime::StreamFilter;
+/// let filter = StreamFilter::new()
+///     .uri_one_of(&["https://example.com", "https://example.org"])
+///     .group("__world__");
+/// assert_eq!(serde_json::to_value(&filter).unwrap()["clauses"][1], serde_json::json!({
+///     "field": "/group", "operator": "equals", "value": "__world__", "case_sensitive": true
+/// }));
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StreamFilter {
+    pub match_policy: MatchPolicy,
+    pub clauses: Vec<Clause>,
+    pub actions: Actions,
+}
+
+impl StreamFilter {
+    /// A filter that lets every event through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Change how the clauses are combined (default: all must match)
+    pub fn match_policy(mut self, match_policy: MatchPolicy) -> Self {
+        self.match_policy = match_policy;
+        self
+    }
+
+    /// Only annotations on one of `uris` (compared case-insensitively)
+    pub fn uri_one_of(mut self, uris: &[&str]) -> Self {
+        self.clauses.push(Clause {
+            field: Field::Uri,
+            operator: Operator::OneOf,
+            value: uris.iter().map(|&uri| Value::from(uri)).collect(),
+            case_sensitive: false,
+        });
+        self
+    }
+
+    /// Only annotations in `group`
+    pub fn group(mut self, group: impl Into<GroupID>) -> Self {
+        self.clauses.push(Clause {
+            field: Field::Group,
+            operator: Operator::Equals,
+            value: Value::from(group.into().0),
+            case_sensitive: true,
+        });
+        self
+    }
+
+    /// Only annotations by `user`
+    pub fn user(mut self, user: &UserAccountID) -> Self {
+        self.clauses.push(Clause {
+            field: Field::User,
+            operator: Operator::Equals,
+            value: Value::from(user.0.as_str()),
+            case_sensitive: true,
+        });
+        self
+    }
+
+    /// Which kinds of events to let through (default: all)
+    pub fn actions(mut self, actions: Actions) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// True if `event` passes the filter
+    pub fn matches(&self, event: &Event) -> bool {
+        let action = match event {
+            Event::Created(_) => self.actions.create,
+            Event::Updated(_) => self.actions.update,
+            Event::Deleted(_) => self.actions.delete,
+        };
+        if !action || self.clauses.is_empty() {
+            return action;
+        }
+        let annotation = event.annotation();
+        let mut matches = self.clauses.iter().map(|clause| clause.matches(annotation));
+        match self.match_policy {
+            MatchPolicy::IncludeAny => matches.any(|m| m),
+            MatchPolicy::IncludeAll => matches.all(|m| m),
+            MatchPolicy::ExcludeAny => !matches.any(|m| m),
+            MatchPolicy::ExcludeAll => !matches.all(|m| m),
+        }
+    }
+
+    /// Narrows `query` with the group and user clauses, where that doesn't change the result.
+    ///
+    /// Returns false if a clause asks for another group or user than `query`, so that no
+    /// annotation can match both.
+    fn narrow(&self, query: &mut SearchQuery) -> bool {
+        if self.match_policy != MatchPolicy::IncludeAll {
+            return true;
+        }
+        for clause in &self.clauses {
+            if let (Operator::Equals, Some(value)) = (clause.operator, clause.value.as_str()) {
+                let scope = match clause.field {
+                    Field::Group if clause.case_sensitive => &mut query.group.0,
+                    Field::User if clause.case_sensitive => &mut query.user,
+                    _ => continue,
+                };
+                if scope.is_empty() {
+                    *scope = value.into();
+                } else if scope != value {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// What a buffered event stream does when a poll finds more events than fit in its buffer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    /// Keep only the newest events
+    DropOldest,
+    /// Stop reading until the consumer catches up, then continue where it left off
+    PauseReads,
+    /// Yield [`HypothesisError::BufferOverflow`] and end the stream
+    Error,
+}
+
+/// Totals of an event stream that was shut down
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StreamSummary {
+    pub polls: u64,
+    pub failed_polls: u64,
+    /// Events yielded by the stream
+    pub events: u64,
+    /// Pass to [`Poller::since`] to resume where the stream stopped
+    #[serde(with = "time::serde::rfc3339")]
+    pub resume_from: OffsetDateTime,
+}
+
+/// Stops an event stream from another task, e.g. on SIGTERM, and returns its summary `S`.
+/// Made with [`Poller::shutdown_handle`], or
+/// [`SyncDaemon::shutdown_handle`](../store/struct.SyncDaemon.html#method.shutdown_handle) for
+/// a [`SyncSummary`](../store/struct.SyncSummary.html).
+///
+/// # Example
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+/// use futures::StreamExt;
+/// use hypothesis::Hypothesis;
+/// use hypothesis::annotations::SearchQuery;
+/// let api = Hypothesis::from_env()?;
+/// let query = SearchQuery::builder().tag("inbox").build()?;
+/// let poller = api.poll_events(&query, Duration::from_secs(30));
+/// let handle = poller.shutdown_handle();
+/// let stopper = tokio::spawn(async move {
+///     tokio::time::sleep(Duration::from_secs(3600)).await;
+///     handle.shutdown().await
+/// });
+/// let mut events = Box::pin(poller.into_stream());
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event?.annotation().id);
+/// }
+/// if let Some(summary) = stopper.await? {
+///     println!("stopped after {} events, resume from {}", summary.events, summary.resume_from);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle<S = StreamSummary> {
+    pub(crate) stop: Arc<watch::Sender<bool>>,
+    pub(crate) summary: watch::Receiver<Option<S>>,
+}
+
+impl<S: Clone> ShutdownHandle<S> {
+    /// Asks the stream to stop and waits until it has.
+    ///
+    /// The poll in progress is completed and the events it found are still yielded, after which
+    /// the stream ends. A sync in progress stops after its current page, see
+    /// [`SyncDaemon::shutdown_handle`](../store/struct.SyncDaemon.html#method.shutdown_handle). The stream has to keep being consumed for that to happen, so call this
+    /// from another task than the one reading the stream.
+    /// Returns None if the stream was dropped before it ended.
+    pub async fn shutdown(&self) -> Option<S> {
+        let _ = self.stop.send(true);
+        let mut summary = self.summary.clone();
+        loop {
+            if let Some(summary) = summary.borrow().clone() {
+                return Some(summary);
+            }
+            if summary.changed().await.is_err() {
+                return summary.borrow().clone();
+            }
+        }
+    }
+}
+
+/// Polls for annotations matching a query that changed since the last poll.
+/// Made with [`Hypothesis::poll_events`].
+pub struct Poller<'a> {
+    api: &'a Hypothesis,
+    query: SearchQuery,
+    interval: Duration,
+    max_backoff: Duration,
+    filter: StreamFilter,
+    /// The filter and the query can't both match an annotation
+    disjoint: bool,
+    buffer: Option<(usize, Overflow)>,
+    since: OffsetDateTime,
+    /// Follows the annotations found so far, across polls
+    cursor: PageCursor,
+    /// Annotations found but not yet reported, after a poll stopped at its limit
+    unread: VecDeque<Annotation>,
+    cancel: CancellationToken,
+    stop: (Arc<watch::Sender<bool>>, watch::Receiver<bool>),
+    summary: (
+        watch::Sender<Option<StreamSummary>>,
+        watch::Receiver<Option<StreamSummary>>,
+    ),
+}
+
+impl<'a> Poller<'a> {
+    /// Only report changes made after `since` (default: when the poller was made)
+    pub fn since(mut self, since: OffsetDateTime) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Longest wait between retries after failed polls (default: 10 minutes)
+    ///
+    /// After each consecutive failure the wait doubles, starting from the polling interval.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Only report events passing `filter`.
+    ///
+    /// Group and user clauses are sent along with the search where possible, the rest is
+    /// checked on the client. If they ask for another group or user than the query, there are
+    /// no events.
+    pub fn filter(mut self, filter: StreamFilter) -> Self {
+        self.disjoint = !filter.narrow(&mut self.query);
+        self.filter = filter;
+        self
+    }
+
+    /// Hold at most `capacity` events in the stream made by [`Poller::into_stream`],
+    /// handling the rest according to `overflow` (default: unbounded)
+    pub fn buffer(mut self, capacity: usize, overflow: Overflow) -> Self {
+        self.buffer = Some((capacity.max(1), overflow));
+        self
+    }
+
+    /// Stop when `token` is cancelled: [`Poller::poll`] stops paging and returns the events
+    /// found so far, and the stream made by [`Poller::into_stream`] ends like after
+    /// [`ShutdownHandle::shutdown`]. The next poll continues where the cancelled one stopped.
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// A handle to stop the stream made by [`Poller::into_stream`]
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            stop: self.stop.0.clone(),
+            summary: self.summary.1.clone(),
+        }
+    }
+
+    /// Wait before the next poll after `failures` consecutive failed polls
+    fn delay(&self, failures: u32) -> Duration {
+        self.interval
+            .saturating_mul(2u32.saturating_pow(failures))
+            .min(self.max_backoff.max(self.interval))
+    }
+
+    /// Events for all matching annotations changed since the last poll, oldest first
+    pub async fn poll(&mut self) -> Result<Vec<Event>, HypothesisError> {
+        Ok(self.fetch(None).await?.0)
+    }
+
+    /// Like `poll`, but holds at most `capacity` events of `buffer`: with
+    /// [`Overflow::DropOldest`] the oldest are dropped while paging, otherwise it stops there.
+    /// Also returns true if there were more events than that.
+    async fn fetch(
+        &mut self,
+        buffer: Option<(usize, Overflow)>,
+    ) -> Result<(Vec<Event>, bool), HypothesisError> {
+        let limit = match buffer {
+            Some((capacity, overflow)) if overflow != Overflow::DropOldest => Some(capacity),
+            _ => None,
+        };
+        let keep = match buffer {
+            Some((capacity, Overflow::DropOldest)) => Some(capacity),
+            _ => None,
+        };
+        if self.disjoint {
+            return Ok((Vec::new(), false));
+        }
+        let mut query = self.query.clone();
+        query.sort = Sort::Updated;
+        query.order = Order::Asc;
+        query.offset = 0;
+        query.search_after = match self.cursor.search_after(&query)? {
+            Some(search_after) => search_after,
+            None => self.since.format(&Rfc3339).map_err(time::Error::Format)?,
+        };
+        let mut events = VecDeque::new();
+        loop {
+            while let Some(annotation) = self.unread.pop_front() {
+                let updated = annotation.updated;
+                let event = Event::new(annotation);
+                if self.filter.matches(&event) {
+                    if limit.is_some_and(|limit| events.len() >= limit) {
+                        self.unread.push_front(event.into_annotation());
+                        return Ok((events.into(), true));
+                    }
+                    events.push_back(event);
+                    if keep.is_some_and(|keep| events.len() > keep) {
+                        events.pop_front();
+                    }
+                }
+                self.since = updated;
+            }
+            if self.cancel.is_cancelled() {
+                return Ok((events.into(), false));
+            }
+            let page = self.api.search_page(&query).await?;
+            match self.cursor.advance(&mut query, page)? {
+                Some(page) => self.unread.extend(page),
+                None => return Ok((events.into(), false)),
+            }
+        }
+    }
+
+    /// Polls every `interval` and yields the events one by one.
+    ///
+    /// Errors are yielded as they occur and polling continues afterwards, with exponential
+    /// backoff while polls keep failing. Nothing is lost across failures: the next successful
+    /// poll covers everything changed since the last successful one.
+    ///
+    /// Use [`Poller::shutdown_handle`] first to be able to stop the stream cleanly.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Event, HypothesisError>> + 'a {
+        let state = StreamState {
+            summary: StreamSummary {
+                polls: 0,
+                failed_polls: 0,
+                events: 0,
+                resume_from: self.since,
+            },
+            poller: self,
+            pending: VecDeque::new(),
+            failures: None,
+            paused: false,
+            done: false,
+        };
+        futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    state.summary.events += 1;
+                    return Some((Ok(event), state));
+                }
+                if state.done || *state.poller.stop.1.borrow() || state.poller.cancel.is_cancelled()
+                {
+                    state.summary.resume_from = state.poller.since;
+                    let _ = state.poller.summary.0.send(Some(state.summary));
+                    return None;
+                }
+                // no wait before the first poll, or to continue paused reads
+                if let (Some(failures), false) = (state.failures, state.paused) {
+                    let delay = state.poller.delay(failures);
+                    let mut stop = state.poller.stop.1.clone();
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = stop.changed() => continue,
+                        _ = state.poller.cancel.cancelled() => continue,
+                    }
+                }
+                state.summary.polls += 1;
+                let overflow = state.poller.buffer;
+                match state.poller.fetch(overflow).await {
+                    Ok((events, more)) => {
+                        state.failures = Some(0);
+                        state.paused = false;
+                        match overflow {
+                            Some((_, Overflow::PauseReads)) => state.paused = more,
+                            Some((capacity, Overflow::Error)) if more => {
+                                state.done = true;
+                                return Some((
+                                    Err(HypothesisError::BufferOverflow(capacity)),
+                                    state,
+                                ));
+                            }
+                            _ => {}
+                        }
+                        state.pending.extend(events);
+                    }
+                    Err(e) => {
+                        state.summary.failed_polls += 1;
+                        state.failures = Some(state.failures.map_or(1, |f| f + 1));
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+}
+
+struct StreamState<'a> {
+    poller: Poller<'a>,
+    summary: StreamSummary,
+    pending: VecDeque<Event>,
+    /// Consecutive failed polls, None before the first poll
+    failures: Option<u32>,
+    /// The last poll stopped at the buffer capacity
+    paused: bool,
+    done: bool,
+}
+
+impl Hypothesis {
+    /// Follow annotations matching `query`, polling every `interval`.
+    ///
+    /// The `sort`, `order` and `search_after` fields of `query` are overwritten.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::realtime::Event;
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let query = SearchQuery::builder().group(&group_id).build()?;
+    /// let mut events = Box::pin(api.poll_events(&query, Duration::from_secs(30)).into_stream());
+    /// while let Some(event) = events.next().await {
+    ///     match event? {
+    ///         Event::Created(annotation) => println!("new: {}", annotation.text),
+    ///         Event::Updated(annotation) => println!("edited: {}", annotation.text),
+    ///         Event::Deleted(_) => {}
+    ///     }
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn poll_events(&self, query: &SearchQuery, interval: Duration) -> Poller<'_> {
+        Poller {
+            api: self,
+            query: query.clone(),
+            interval,
+            max_backoff: Duration::from_secs(600),
+            filter: StreamFilter::default(),
+            disjoint: false,
+            buffer: None,
+            since: OffsetDateTime::now_utc(),
+            cursor: PageCursor::default(),
+            unread: VecDeque::new(),
+            cancel: CancellationToken::new(),
+            stop: {
+                let (sender, receiver) = watch::channel(false);
+                (Arc::new(sender), receiver)
+            },
+            summary: watch::channel(None),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Search-and-replace in annotation texts, e.g. after a URL, name or term referenced in many
+//! notes changed
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{Annotation, InputAnnotation, SearchQuery};
+use crate::errors::HypothesisError;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::{AnnotationID, Hypothesis};
+
+/// The text of an annotation before and after a replacement
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextChange {
+    pub id: AnnotationID,
+    pub before: String,
+    pub after: String,
+}
+
+impl TextChange {
+    /// The change applied to `annotation`, None if `pattern` doesn't match its text.
+    /// `replacement` can refer to capture groups, see [`Regex::replace_all`].
+    ///
+    /// # Example
+    /// ```
+    /// use regex::Regex;
+    /// use hypothesis::replace::TextChange;
+    /// # let annotation: hypothesis::annotations::Annotation = serde_json::from_value(serde_json::json!({
+    /// #     "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #     "user": "acct:alice@hypothes.is", "uri": "https://example.com",
+    /// #     "text": "Summary\nSee http://old.example.com/paper", "tags": [], "group": "__world__",
+    /// #     "hidden": false, "flagged": false, "links": {},
+    /// #     "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// # })).unwrap();
+    /// let pattern = Regex::new(r"https?://old\.example\.com/(\w+)").unwrap();
+    /// let change = TextChange::new(&annotation, &pattern, "https://new.example.com/$1").unwrap();
+    /// assert_eq!(change.after, "Summary\nSee https://new.example.com/paper");
+    /// assert_eq!(change.diff(), "- See http://old.example.com/paper\n+ See https://new.example.com/paper\n");
+    /// ```
+    pub fn new(annotation: &Annotation, pattern: &Regex, replacement: &str) -> Option<Self> {
+        let after = pattern.replace_all(&annotation.text, replacement);
+        (after != annotation.text).then(|| Self {
+            id: annotation.id.to_owned(),
+            before: annotation.text.to_owned(),
+            after: after.into_owned(),
+        })
+    }
+
+    /// The changed lines, old ones prefixed with "- " and new ones with "+ "
+    pub fn diff(&self) -> String {
+        let before: Vec<_> = self.before.lines().collect();
+        let after: Vec<_> = self.after.lines().collect();
+        // lines before and after the first / last changed line stay the same
+        let common_start = before
+            .iter()
+            .zip(&after)
+            .take_while(|(b, a)| b == a)
+            .count();
+        let common_end = before[common_start..]
+            .iter()
+            .rev()
+            .zip(after[common_start..].iter().rev())
+            .take_while(|(b, a)| b == a)
+            .count();
+        let mut diff = String::new();
+        for line in &before[common_start..before.len() - common_end] {
+            diff.push_str(&format!("- {}\n", line));
+        }
+        for line in &after[common_start..after.len() - common_end] {
+            diff.push_str(&format!("+ {}\n", line));
+        }
+        diff
+    }
+}
+
+impl Hypothesis {
+    /// Replace matches of `pattern` with `replacement` in the text of all annotations matching
+    /// `scope`, reporting to `progress` after every update.
+    ///
+    /// Returns the changes made, or with `dry_run` the changes that would be made.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use regex::Regex;
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// let api = Hypothesis::from_env()?;
+    /// let scope = SearchQuery::builder().user(&api.user().0).build()?;
+    /// let pattern = Regex::new(r"\bJ\. Doe\b")?;
+    /// for change in api.replace_text(&scope, &pattern, "J. Roe", true, |_, _| {}).await? {
+    ///     println!("{}:\n{}", change.id, change.diff());
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(pattern = %pattern, scope = %scope.summary())))]
+    pub async fn replace_text(
+        &self,
+        scope: &SearchQuery,
+        pattern: &Regex,
+        replacement: &str,
+        dry_run: bool,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<TextChange>, HypothesisError> {
+        let changes: Vec<_> = self
+            .search_annotations_return_all(&mut scope.clone())
+            .await?
+            .iter()
+            .filter_map(|annotation| TextChange::new(annotation, pattern, replacement))
+            .collect();
+        if !dry_run {
+            let mut tracker = Tracker::new(progress, changes.len());
+            for change in &changes {
+                tracker.check_cancelled()?;
+                let patch = InputAnnotation::builder()
+                    .text(change.after.as_str())
+                    .build()?;
+                tracker.record(self.patch_annotation(&change.id, &patch).await)?;
+            }
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,195 @@
+//! Exports of all annotations matching a query that can be interrupted and continued, for
+//! multi-hour exports of large groups
+//!
+//! [`Hypothesis::export_resumable`] hands the annotations to a sink page by page and, after
+//! each page, saves an [`ExportCheckpoint`] with the search cursor to a file. If the export is
+//! interrupted (killed, Ctrl-C, network down), running it again with the same checkpoint file
+//! continues after the last page the sink got.
+//! [`Hypothesis::export_resumable_enriched`] also runs [enrichers](../enrich/index.html) on each
+//! page before handing it to the sink.
+//!
+//! # Example
+//! ```no_run
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::fs::OpenOptions;
+//! use std::io::Write;
+//! use hypothesis::Hypothesis;
+//! use hypothesis::annotations::SearchQuery;
+//! let api = Hypothesis::from_env()?;
+//! let query = SearchQuery::builder().group("bigGroupId").limit(200).build()?;
+//! // appends, as a resumed export continues the same file
+//! let mut file = OpenOptions::new().create(true).append(true).open("export.jsonl")?;
+//! let exported = api
+//!     .export_resumable(&query, "export.checkpoint.json", |page| {
+//!         for annotation in page {
+//!             writeln!(file, "{}", serde_json::to_string(annotation)?)?;
+//!         }
+//!         Ok(())
+//!     })
+//!     .await?;
+//! println!("{} annotations exported", exported);
+//! #     Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{Annotation, Order, PageCursor, SearchQuery, Sort};
+use crate::enrich::{enrich_annotations, EnrichedAnnotation, Enricher};
+use crate::errors::HypothesisError;
+use crate::Hypothesis;
+
+/// How far an export got
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportCheckpoint {
+    /// The exported query, with `search_after` at the last annotation exported
+    pub query: SearchQuery,
+    /// Annotations exported so far
+    pub exported: usize,
+    /// The last annotations exported, which the next page may include again
+    #[serde(default)]
+    pub cursor: PageCursor,
+}
+
+impl ExportCheckpoint {
+    /// Reads a checkpoint file, None if there is none
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, HypothesisError> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes a checkpoint file, replacing the previous one in one step so that an interruption
+    /// never leaves a partly written file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HypothesisError> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// True if the checkpoint is of an export of `query`
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::resume::ExportCheckpoint;
+    /// let query = SearchQuery::builder().group("abc").build()?;
+    /// let mut checkpoint = ExportCheckpoint {
+    ///     query: query.clone(),
+    ///     exported: 200,
+    ///     cursor: Default::default(),
+    /// };
+    /// checkpoint.query.search_after = "2023-01-02T10:00:00Z".into();
+    /// assert!(checkpoint.resumes(&query));
+    /// assert!(!checkpoint.resumes(&SearchQuery::builder().group("xyz").build()?));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn resumes(&self, query: &SearchQuery) -> bool {
+        let mut start = self.query.clone();
+        start.search_after.clone_from(&query.search_after);
+        start.offset = query.offset;
+        start == *query
+    }
+}
+
+impl Hypothesis {
+    /// Fetches all annotations matching `query`, oldest update first, handing them to `sink` a
+    /// page at a time, and saves an [`ExportCheckpoint`] to `checkpoint` after each page. If
+    /// `checkpoint` has the checkpoint of an earlier, interrupted export of the same query, the
+    /// export continues from there. The checkpoint file is removed once the export is complete.
+    ///
+    /// Returns the number of annotations exported, including those of earlier runs.
+    ///
+    /// A page is only counted as exported once `sink` returns, and the checkpoint is saved right
+    /// after, so dropping the returned future (e.g. on Ctrl-C) or an error from the API or
+    /// `sink` loses no annotations; a sink failing part-way through a page gets the whole page
+    /// again on the next run. Fails with [`HypothesisError::QueryError`] if `checkpoint` is of
+    /// a different query.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
+    pub async fn export_resumable(
+        &self,
+        query: &SearchQuery,
+        checkpoint: impl AsRef<Path>,
+        mut sink: impl FnMut(&[Annotation]) -> Result<(), HypothesisError>,
+    ) -> Result<usize, HypothesisError> {
+        self.export_pages(query, checkpoint.as_ref(), &[], 1, |page| {
+            let page: Vec<Annotation> = page.into_iter().map(|a| a.annotation).collect();
+            sink(&page)
+        })
+        .await
+    }
+
+    /// Like [`Hypothesis::export_resumable`], running `enrichers` on each page, `limit`
+    /// annotations at a time (see [`enrich_annotations`]), before handing it to `sink`.
+    ///
+    /// An enricher failing stops the export like an API error; the page is enriched again on
+    /// the next run.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(query = %query.summary())))]
+    pub async fn export_resumable_enriched(
+        &self,
+        query: &SearchQuery,
+        checkpoint: impl AsRef<Path>,
+        enrichers: &[&dyn Enricher],
+        limit: usize,
+        mut sink: impl FnMut(&[EnrichedAnnotation]) -> Result<(), HypothesisError>,
+    ) -> Result<usize, HypothesisError> {
+        self.export_pages(query, checkpoint.as_ref(), enrichers, limit, |page| {
+            sink(&page)
+        })
+        .await
+    }
+
+    async fn export_pages(
+        &self,
+        query: &SearchQuery,
+        path: &Path,
+        enrichers: &[&dyn Enricher],
+        limit: usize,
+        mut sink: impl FnMut(Vec<EnrichedAnnotation>) -> Result<(), HypothesisError>,
+    ) -> Result<usize, HypothesisError> {
+        let mut query = query.clone();
+        query.sort = Sort::Updated;
+        query.order = Order::Asc;
+        let mut state = match ExportCheckpoint::load(path)? {
+            Some(state) if state.resumes(&query) => state,
+            Some(_) => {
+                return Err(HypothesisError::QueryError(format!(
+                    "{} is the checkpoint of an export of another query",
+                    path.display()
+                )))
+            }
+            None => ExportCheckpoint {
+                query,
+                exported: 0,
+                cursor: PageCursor::default(),
+            },
+        };
+        loop {
+            let page = self.search_page(&state.query).await?;
+            let Some(page) = state.cursor.advance(&mut state.query, page)? else {
+                break;
+            };
+            let count = page.len();
+            if count > 0 {
+                sink(enrich_annotations(page, enrichers, limit).await?)?;
+            }
+            state.exported += count;
+            state.save(path)?;
+        }
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(state.exported)
+    }
+}
@@ -0,0 +1,178 @@
+//! Retention rules for groups used as transient scratch spaces
+//!
+//! A [`RetentionPolicy`] lists rules like "delete annotations in group X tagged `ephemeral`
+//! that haven't been updated in 30 days". Run it with [`Hypothesis::apply_retention`], e.g. from
+//! a cron job. Policies are stored as JSON:
+//! ```json
+//! {
+//!   "rules": [
+//!     { "group": "scratchGroupId", "tag": "ephemeral", "older_than_days": 30, "action": "delete" },
+//!     { "group": "courseGroupId", "older_than_days": 365, "action": { "archive": "archive/course" } }
+//!   ]
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::annotations::{Annotation, SearchQuery};
+use crate::errors::HypothesisError;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::store::{Checkpoint, Store};
+use crate::{AnnotationID, GroupID, Hypothesis};
+
+/// What happens to annotations matched by a [`RetentionRule`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    Delete,
+    /// Save them as a checkpoint in the [`Store`] in this directory, then delete them
+    Archive(PathBuf),
+}
+
+/// Annotations of a group (optionally with a tag) not updated for a number of days
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RetentionRule {
+    pub group: GroupID,
+    /// Only annotations with this tag, all annotations of the group if None
+    #[serde(default)]
+    pub tag: Option<String>,
+    pub older_than_days: u32,
+    pub action: RetentionAction,
+}
+
+impl RetentionRule {
+    /// True if `annotation` falls under the rule at time `now`
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::retention::{RetentionAction, RetentionRule};
+    /// # let annotation: hypothesis::annotations::Annotation = serde_json::from_value(serde_json::json!({
+    /// #     "id": "a", "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #     "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "",
+    /// #     "tags": ["ephemeral"], "group": "scratch", "hidden": false, "flagged": false, "links": {},
+    /// #     "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// # })).unwrap();
+    /// let rule = RetentionRule {
+    ///     group: "scratch".into(),
+    ///     tag: Some("ephemeral".into()),
+    ///     older_than_days: 30,
+    ///     action: RetentionAction::Delete,
+    /// };
+    /// let updated = annotation.updated;
+    /// assert!(rule.matches(&annotation, updated + time::Duration::days(31)));
+    /// assert!(!rule.matches(&annotation, updated + time::Duration::days(29)));
+    /// ```
+    pub fn matches(&self, annotation: &Annotation, now: OffsetDateTime) -> bool {
+        annotation.group == self.group
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tag| annotation.tags.contains(tag))
+            && annotation.updated < now - Duration::days(self.older_than_days.into())
+    }
+}
+
+/// Rules applied by [`Hypothesis::apply_retention`]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+}
+
+impl RetentionPolicy {
+    /// Reads a policy file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HypothesisError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes a policy file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HypothesisError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Outcome of [`Hypothesis::apply_retention`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RetentionSummary {
+    /// Annotations that were (or, in a dry run, would be) deleted
+    pub deleted: Vec<AnnotationID>,
+    /// Checkpoints saved by archiving rules (empty in a dry run)
+    pub archived: Vec<Checkpoint>,
+}
+
+impl Hypothesis {
+    /// Apply the rules of `policy` in order.
+    ///
+    /// Annotations of archiving rules are saved before they are deleted.
+    /// With `dry_run`, only reports which annotations would be deleted.
+    /// `progress` is told about every rule applied.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::retention::RetentionPolicy;
+    /// let api = Hypothesis::from_env()?;
+    /// let policy = RetentionPolicy::load("retention.json")?;
+    /// let summary = api.apply_retention(&policy, true, ()).await?;
+    /// println!("Would delete {} annotations", summary.deleted.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(rules = policy.rules.len())))]
+    pub async fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        dry_run: bool,
+        progress: impl ProgressObserver,
+    ) -> Result<RetentionSummary, HypothesisError> {
+        let mut summary = RetentionSummary::default();
+        let now = OffsetDateTime::now_utc();
+        let mut tracker = Tracker::new(progress, policy.rules.len());
+        for rule in &policy.rules {
+            tracker.check_cancelled()?;
+            tracker.record(self.apply_rule(rule, now, dry_run, &mut summary).await)?;
+        }
+        Ok(summary)
+    }
+
+    /// Deletes (and archives) the annotations matching `rule`, adding them to `summary`
+    async fn apply_rule(
+        &self,
+        rule: &RetentionRule,
+        now: OffsetDateTime,
+        dry_run: bool,
+        summary: &mut RetentionSummary,
+    ) -> Result<(), HypothesisError> {
+        let mut query = SearchQuery::builder();
+        query.group(rule.group.clone()).limit(200);
+        if let Some(tag) = &rule.tag {
+            query.tag(tag);
+        }
+        let matched: Vec<_> = self
+            .search_annotations_return_all(&mut query.build()?)
+            .await?
+            .into_iter()
+            .filter(|annotation| rule.matches(annotation, now))
+            .collect();
+        if matched.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<_> = matched.iter().map(|a| a.id.to_owned()).collect();
+        if !dry_run {
+            if let RetentionAction::Archive(dir) = &rule.action {
+                summary
+                    .archived
+                    .push(Store::open(dir)?.save_checkpoint(&matched)?);
+            }
+            self.delete_annotations(&ids).await?;
+        }
+        summary.deleted.extend(ids);
+        Ok(())
+    }
+}
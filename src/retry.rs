@@ -0,0 +1,121 @@
+//! Retries of failed API calls and spacing between calls
+//!
+//! Every API call of a [`Hypothesis`](../struct.Hypothesis.html) client is retried according to
+//! its [`RetryPolicy`] (set with
+//! [`Hypothesis::set_retry_policy`](../struct.Hypothesis.html#method.set_retry_policy)):
+//! calls that failed with a [retryable](../errors/enum.HypothesisError.html#method.is_retryable)
+//! error are sent again after an exponential backoff, or after the time the API asked for in a
+//! `Retry-After` header. Calls that create or change something (POST, PATCH) are only sent again
+//! when the API rate limited them, as other failures may have happened after the change was made.
+//!
+//! The policy can also space calls at least `min_interval` apart, shared by the clones of a
+//! client, to stay below the API's rate limit in the first place.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use std::time::Duration;
+//! use hypothesis::Hypothesis;
+//! use hypothesis::retry::RetryPolicy;
+//! let mut api = Hypothesis::new("username", "developer_key")?;
+//! api.set_retry_policy(RetryPolicy {
+//!     max_retries: 5,
+//!     min_interval: Duration::from_millis(200),
+//!     ..Default::default()
+//! });
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::errors::HypothesisError;
+
+/// When and how often failed API calls are sent again, see the [module docs](index.html)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt (default: 3)
+    pub max_retries: u32,
+    /// Wait before the first retry, doubling with every further one (default: 500ms)
+    pub initial_backoff: Duration,
+    /// Longest wait before a retry, also for `Retry-After` (default: 30s)
+    pub max_backoff: Duration,
+    /// Shortest time between the start of two calls (default: none)
+    pub min_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            min_interval: Duration::ZERO,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send every call once
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Wait before retry number `retry` (from 0), `retry_after` if the API asked for it
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use hypothesis::retry::RetryPolicy;
+    /// let policy = RetryPolicy::default();
+    /// assert_eq!(policy.backoff(0, None), Duration::from_millis(500));
+    /// assert_eq!(policy.backoff(2, None), Duration::from_secs(2));
+    /// assert_eq!(policy.backoff(10, None), Duration::from_secs(30));
+    /// assert_eq!(policy.backoff(0, Some(Duration::from_secs(5))), Duration::from_secs(5));
+    /// ```
+    pub fn backoff(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .unwrap_or_else(|| {
+                self.initial_backoff
+                    .saturating_mul(2u32.saturating_pow(retry))
+            })
+            .min(self.max_backoff)
+    }
+
+    /// True if a `method` call that failed with `error` after `retries` retries is sent again
+    pub(crate) fn retries(&self, method: &Method, error: &HypothesisError, retries: u32) -> bool {
+        let idempotent = !matches!(*method, Method::POST | Method::PATCH);
+        retries < self.max_retries
+            && (error.is_rate_limited() || (idempotent && error.is_retryable()))
+    }
+}
+
+/// Spaces calls `min_interval` apart, shared by the clones of a client
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    /// Earliest start of the next call
+    next: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Waits until a call may start
+    pub(crate) async fn wait(&self, min_interval: Duration) {
+        if min_interval.is_zero() {
+            return;
+        }
+        let mut next = self.next.lock().await;
+        if let Some(at) = *next {
+            tokio::time::sleep_until(at).await;
+        }
+        *next = Some(Instant::now() + min_interval);
+    }
+}
@@ -0,0 +1,171 @@
+//! Group member lists as CSV, e.g. to sync a course group with the class roster
+//!
+//! [`write_members`] exports a group's members with the columns of [`Member`].
+//! A roster to import only needs a `userid` column with either full "acct:{username}@{authority}"
+//! IDs or usernames; other columns (like those of an export) are ignored:
+//! ```csv
+//! userid,display_name
+//! acct:student_1@lms.example.com,Student One
+//! student_2,Student Two
+//! ```
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::HypothesisError;
+use crate::groups::Member;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::{GroupID, Hypothesis, UserAccountID};
+
+/// Writes `members` as CSV
+pub fn write_members(members: &[Member], writer: impl Write) -> Result<(), HypothesisError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for member in members {
+        writer.serialize(member)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the user IDs of a CSV roster, usernames are taken to be of `authority`
+///
+/// # Example
+/// ```
+/// use hypothesis::UserAccountID;
+/// use hypothesis::roster::read_roster;
+/// let csv = "userid,display_name\nacct:student_1@lms.example.com,Student One\nstudent_2,Student Two\n";
+/// let roster = read_roster(csv.as_bytes(), "lms.example.com").unwrap();
+/// assert_eq!(roster, vec![
+///     UserAccountID::new("student_1", "lms.example.com"),
+///     UserAccountID::new("student_2", "lms.example.com"),
+/// ]);
+/// ```
+pub fn read_roster(
+    reader: impl Read,
+    authority: &str,
+) -> Result<Vec<UserAccountID>, HypothesisError> {
+    #[derive(Deserialize)]
+    struct Row {
+        userid: String,
+    }
+    csv::Reader::from_reader(reader)
+        .deserialize::<Row>()
+        .map(|row| {
+            let userid = row?.userid.trim().to_owned();
+            Ok(if userid.starts_with("acct:") {
+                UserAccountID(userid)
+            } else {
+                UserAccountID::new(&userid, authority)
+            })
+        })
+        .collect()
+}
+
+/// Members to add to and remove from a group to match a roster
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RosterDiff {
+    /// On the roster but not in the group
+    pub add: Vec<UserAccountID>,
+    /// In the group but not on the roster
+    pub remove: Vec<UserAccountID>,
+}
+
+impl RosterDiff {
+    /// Compares the current `members` of a group with `roster`
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::UserAccountID;
+    /// use hypothesis::groups::Member;
+    /// use hypothesis::roster::RosterDiff;
+    /// let members = vec![Member {
+    ///     authority: "lms.example.com".into(),
+    ///     username: "dropped".into(),
+    ///     userid: "acct:dropped@lms.example.com".into(),
+    ///     display_name: None,
+    /// }];
+    /// let roster = vec![UserAccountID::new("enrolled", "lms.example.com")];
+    /// let diff = RosterDiff::new(&members, &roster);
+    /// assert_eq!(diff.add, roster);
+    /// assert_eq!(diff.remove, vec![UserAccountID::new("dropped", "lms.example.com")]);
+    /// ```
+    pub fn new(members: &[Member], roster: &[UserAccountID]) -> Self {
+        let current: HashSet<_> = members.iter().map(|m| m.userid.as_str()).collect();
+        let wanted: HashSet<_> = roster.iter().map(|u| u.0.as_str()).collect();
+        Self {
+            add: roster
+                .iter()
+                .filter(|u| !current.contains(u.0.as_str()))
+                .cloned()
+                .collect(),
+            remove: members
+                .iter()
+                .filter(|m| !wanted.contains(m.userid.as_str()))
+                .map(|m| UserAccountID(m.userid.to_owned()))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+impl Hypothesis {
+    /// Writes the members of a group as CSV, see [`write_members`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id)))]
+    pub async fn export_group_members(
+        &self,
+        id: &GroupID,
+        writer: impl Write,
+    ) -> Result<(), HypothesisError> {
+        write_members(&self.get_group_members(id).await?, writer)
+    }
+
+    /// Adds and removes members of a group so they match `roster`.
+    ///
+    /// Returns the changes made, or with `dry_run` the changes that would be made.
+    /// `progress` is told about every member added or removed.
+    /// The client's own [user](Hypothesis::user) is never removed, even if it is not on `roster`,
+    /// so it keeps access to the group.
+    /// Only available to authority clients, for users of their own authority.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{GroupID, Hypothesis};
+    /// use hypothesis::roster::read_roster;
+    /// let api = Hypothesis::with_authority("lms_bot", "client_key", "lms.example.com")?;
+    /// let roster = read_roster(std::fs::File::open("roster.csv")?, api.authority())?;
+    /// let diff = api.sync_group_members(&GroupID::from("courseGroupId"), &roster, true, ()).await?;
+    /// println!("Would add {} and remove {} members", diff.add.len(), diff.remove.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id = %id, roster = roster.len())))]
+    pub async fn sync_group_members(
+        &self,
+        id: &GroupID,
+        roster: &[UserAccountID],
+        dry_run: bool,
+        progress: impl ProgressObserver,
+    ) -> Result<RosterDiff, HypothesisError> {
+        let mut diff = RosterDiff::new(&self.get_group_members(id).await?, roster);
+        diff.remove.retain(|userid| userid != self.user());
+        if !dry_run {
+            let mut tracker = Tracker::new(progress, diff.add.len() + diff.remove.len());
+            for userid in &diff.add {
+                tracker.check_cancelled()?;
+                tracker.record(self.add_group_member(id, userid).await)?;
+            }
+            for userid in &diff.remove {
+                tracker.check_cancelled()?;
+                tracker.record(self.remove_group_member(id, userid).await)?;
+            }
+        }
+        Ok(diff)
+    }
+}
@@ -0,0 +1,759 @@
+//! Local snapshots of annotations and the changes between them
+//!
+//! Each sync run saves the annotations it saw as a [`Checkpoint`] in the store's directory
+//! (one JSON file per checkpoint). [`Store::changes_between`] then gives exporters, notifiers
+//! etc. a single, consistent feed of what was created, updated and deleted between two runs,
+//! and [`Store::search`] searches the latest snapshot for what the API can't express, such as
+//! regular expressions or a phrase that may be in the quote or a tag.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::Stream;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::sync::watch;
+
+use crate::annotations::{search_terms, unquote, Annotation, Order, PageCursor, SearchQuery, Sort};
+use crate::enrich::{enrich_annotations, EnrichedAnnotation, Enricher};
+use crate::errors::HypothesisError;
+use crate::filter::Filter;
+use crate::progress::{Progress, ProgressObserver, Tracker};
+use crate::realtime::{Event, ShutdownHandle};
+use crate::toc::quote;
+use crate::{AnnotationID, Hypothesis};
+
+/// A saved snapshot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Checkpoint {
+    /// Sequence number, increasing with every saved checkpoint
+    pub id: u64,
+    /// When the snapshot was saved
+    #[serde(with = "time::serde::rfc3339")]
+    pub taken: OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    checkpoint: Checkpoint,
+    annotations: Vec<Annotation>,
+    /// Output of the enrichers run on the annotations, by annotation ID
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    enrichments: HashMap<AnnotationID, HashMap<String, serde_json::Value>>,
+}
+
+/// The checkpoint of a [`Snapshot`], read without its annotations
+#[derive(Deserialize)]
+struct Header {
+    checkpoint: Checkpoint,
+}
+
+/// Directory of annotation snapshots
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Opens (and creates if needed) a store in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, HypothesisError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn snapshot_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// IDs of the saved snapshots, in increasing order, from the file names
+    fn snapshot_ids(&self) -> Result<Vec<u64>, HypothesisError> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(id) = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u64>().ok())
+                {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// All saved checkpoints, oldest first
+    pub fn checkpoints(&self) -> Result<Vec<Checkpoint>, HypothesisError> {
+        self.snapshot_ids()?
+            .into_iter()
+            .map(|id| self.read_checkpoint(id))
+            .collect()
+    }
+
+    /// Most recently saved checkpoint
+    pub fn latest_checkpoint(&self) -> Result<Option<Checkpoint>, HypothesisError> {
+        self.snapshot_ids()?
+            .last()
+            .map(|&id| self.read_checkpoint(id))
+            .transpose()
+    }
+
+    /// Saves the annotations seen in a sync run as a new checkpoint.
+    ///
+    /// The snapshot is written to a temporary file first and then renamed, so an interrupted
+    /// save doesn't leave a truncated checkpoint behind.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::store::Store;
+    /// use hypothesis::testing::fixtures;
+    /// # let dir = std::env::temp_dir().join("hypothesis-save-doctest");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let store = Store::open(&dir)?;
+    /// assert_eq!(store.save_checkpoint(&fixtures::annotations(2))?.id, 0);
+    /// // left by a save that was interrupted
+    /// std::fs::write(dir.join("1.json.partial"), "{\"checkpoint\": ")?;
+    /// let second = store.save_checkpoint(&fixtures::annotations(3))?;
+    /// assert_eq!(second.id, 1);
+    /// assert_eq!(store.latest_checkpoint()?, Some(second));
+    /// assert_eq!(store.load(&second)?.len(), 3);
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_checkpoint(
+        &self,
+        annotations: &[Annotation],
+    ) -> Result<Checkpoint, HypothesisError> {
+        self.save_snapshot(annotations.to_vec(), HashMap::new())
+    }
+
+    /// Saves annotations along with their [enrichments](../enrich/index.html) as a new
+    /// checkpoint, see [`Store::save_checkpoint`] and [`Store::load_enriched`]
+    pub fn save_enriched(
+        &self,
+        annotations: Vec<EnrichedAnnotation>,
+    ) -> Result<Checkpoint, HypothesisError> {
+        let mut enrichments = HashMap::new();
+        let annotations = annotations
+            .into_iter()
+            .map(|enriched| {
+                if !enriched.enrichments.is_empty() {
+                    enrichments.insert(enriched.annotation.id.clone(), enriched.enrichments);
+                }
+                enriched.annotation
+            })
+            .collect();
+        self.save_snapshot(annotations, enrichments)
+    }
+
+    fn save_snapshot(
+        &self,
+        annotations: Vec<Annotation>,
+        enrichments: HashMap<AnnotationID, HashMap<String, serde_json::Value>>,
+    ) -> Result<Checkpoint, HypothesisError> {
+        let id = self.snapshot_ids()?.last().map_or(0, |id| id + 1);
+        let checkpoint = Checkpoint {
+            id,
+            taken: OffsetDateTime::now_utc(),
+        };
+        let snapshot = Snapshot {
+            checkpoint,
+            annotations,
+            enrichments,
+        };
+        let path = self.snapshot_path(id);
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, serde_json::to_vec(&snapshot)?)?;
+        fs::rename(&partial, path)?;
+        Ok(checkpoint)
+    }
+
+    fn read_snapshot(&self, id: u64) -> Result<Snapshot, HypothesisError> {
+        let bytes = fs::read(self.snapshot_path(id))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Checkpoint of a snapshot, skipping over its annotations without deserializing them
+    fn read_checkpoint(&self, id: u64) -> Result<Checkpoint, HypothesisError> {
+        let bytes = fs::read(self.snapshot_path(id))?;
+        Ok(serde_json::from_slice::<Header>(&bytes)?.checkpoint)
+    }
+
+    /// Annotations saved at `checkpoint`
+    pub fn load(&self, checkpoint: &Checkpoint) -> Result<Vec<Annotation>, HypothesisError> {
+        Ok(self.read_snapshot(checkpoint.id)?.annotations)
+    }
+
+    /// Annotations saved at `checkpoint` with their enrichments, if they were saved with any
+    pub fn load_enriched(
+        &self,
+        checkpoint: &Checkpoint,
+    ) -> Result<Vec<EnrichedAnnotation>, HypothesisError> {
+        let Snapshot {
+            annotations,
+            mut enrichments,
+            ..
+        } = self.read_snapshot(checkpoint.id)?;
+        Ok(annotations
+            .into_iter()
+            .map(|annotation| EnrichedAnnotation {
+                enrichments: enrichments.remove(&annotation.id).unwrap_or_default(),
+                annotation,
+            })
+            .collect())
+    }
+
+    /// Annotations created, updated and deleted between two checkpoints
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::annotations::Annotation;
+    /// use hypothesis::store::Store;
+    /// # fn annotation(id: &str, text: &str) -> Annotation {
+    /// #     serde_json::from_value(serde_json::json!({
+    /// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": text,
+    /// #         "tags": [], "group": "__world__", "hidden": false, "flagged": false, "links": {},
+    /// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// #     })).unwrap()
+    /// # }
+    /// # let dir = std::env::temp_dir().join("hypothesis-store-doctest");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let store = Store::open(&dir)?;
+    /// let first = store.save_checkpoint(&[annotation("a", "old text"), annotation("b", "")])?;
+    /// let second = store.save_checkpoint(&[annotation("a", "new text"), annotation("c", "")])?;
+    ///
+    /// let changes = store.changes_between(&first, &second)?;
+    /// assert_eq!(&*changes.created[0].id, "c");
+    /// assert_eq!(&*changes.deleted[0].id, "b");
+    /// assert_eq!(changes.updated[0].fields[0].field, "text");
+    /// assert_eq!(changes.updated[0].fields[0].after, "new text");
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn changes_between(
+        &self,
+        checkpoint_a: &Checkpoint,
+        checkpoint_b: &Checkpoint,
+    ) -> Result<Changes, HypothesisError> {
+        Ok(changes(
+            &self.load(checkpoint_a)?,
+            &self.load(checkpoint_b)?,
+        ))
+    }
+
+    /// Re-emits the changes recorded in checkpoints taken at or after `from` as events, oldest
+    /// first, so consumers of the [`realtime`](../realtime/index.html) stream can bootstrap
+    /// from history before going live.
+    ///
+    /// Without a checkpoint before `from`, everything in the first checkpoint counts as created.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+    /// use hypothesis::annotations::Annotation;
+    /// use hypothesis::realtime::Event;
+    /// use hypothesis::store::Store;
+    /// # fn annotation(id: &str, text: &str) -> Annotation {
+    /// #     serde_json::from_value(serde_json::json!({
+    /// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+    /// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": text,
+    /// #         "tags": [], "group": "__world__", "hidden": false, "flagged": false, "links": {},
+    /// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []}, "target": [],
+    /// #     })).unwrap()
+    /// # }
+    /// # let dir = std::env::temp_dir().join("hypothesis-store-replay-doctest");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let store = Store::open(&dir)?;
+    /// store.save_checkpoint(&[annotation("a", "old text")])?;
+    /// store.save_checkpoint(&[annotation("b", "")])?;
+    ///
+    /// let events = store.replay_events(time::OffsetDateTime::UNIX_EPOCH)?;
+    /// assert!(matches!(&events[0], Event::Created(a) if &*a.id == "a"));
+    /// assert!(matches!(&events[1], Event::Created(a) if &*a.id == "b"));
+    /// assert!(matches!(&events[2], Event::Deleted(a) if &*a.id == "a"));
+    /// # std::fs::remove_dir_all(&dir)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn replay_events(&self, from: OffsetDateTime) -> Result<Vec<Event>, HypothesisError> {
+        let checkpoints = self.checkpoints()?;
+        let start = checkpoints
+            .iter()
+            .position(|checkpoint| checkpoint.taken >= from)
+            .unwrap_or(checkpoints.len());
+        let mut previous = match start.checked_sub(1) {
+            Some(i) => self.load(&checkpoints[i])?,
+            None => Vec::new(),
+        };
+        let mut events = Vec::new();
+        for checkpoint in &checkpoints[start..] {
+            let current = self.load(checkpoint)?;
+            let Changes {
+                created,
+                updated,
+                deleted,
+            } = changes(&previous, &current);
+            let mut changed: Vec<_> = created
+                .into_iter()
+                .map(Event::Created)
+                .chain(
+                    updated
+                        .into_iter()
+                        .map(|change| Event::Updated(change.after)),
+                )
+                .collect();
+            changed.sort_by_key(|event| event.annotation().updated);
+            events.extend(changed);
+            events.extend(deleted.into_iter().map(Event::Deleted));
+            previous = current;
+        }
+        Ok(events)
+    }
+
+    /// Annotations in the latest checkpoint matching `terms` and `filter`, see [`search`]
+    pub fn search(&self, terms: &str, filter: &Filter) -> Result<Vec<Annotation>, HypothesisError> {
+        let annotations = match self.latest_checkpoint()? {
+            Some(checkpoint) => self.load(&checkpoint)?,
+            None => Vec::new(),
+        };
+        search(&annotations, terms, filter)
+    }
+}
+
+impl Hypothesis {
+    /// Saves the annotations matching `scope` as a new checkpoint in `store`.
+    ///
+    /// After the first run only annotations updated since the latest one in the previous
+    /// checkpoint are downloaded and merged into it. Deleted annotations aren't noticed that way:
+    /// pass `full` to search everything again, e.g. on every tenth run.
+    ///
+    /// `progress` is told about the downloaded annotations and can cancel the sync between pages,
+    /// see the [progress module](../progress/index.html). A cancelled incremental sync still saves
+    /// what it downloaded, from which the next run continues; a cancelled full sync saves nothing.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::store::Store;
+    /// let api = Hypothesis::from_env()?;
+    /// let store = Store::open("annotations")?;
+    /// let scope = SearchQuery::builder().user(&api.user().0).limit(200).build()?;
+    /// let checkpoint = api.sync_store(&store, &scope, false, ()).await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(scope = %scope.summary(), full = full)))]
+    pub async fn sync_store(
+        &self,
+        store: &Store,
+        scope: &SearchQuery,
+        full: bool,
+        progress: impl ProgressObserver,
+    ) -> Result<Checkpoint, HypothesisError> {
+        self.sync_store_enriched(store, scope, full, &[], 1, progress)
+            .await
+    }
+
+    /// Like [`Hypothesis::sync_store`], running `enrichers` on the downloaded annotations,
+    /// `limit` annotations at a time (see
+    /// [`enrich_annotations`](../enrich/fn.enrich_annotations.html)), and saving their output
+    /// with them (see [`Store::load_enriched`]).
+    ///
+    /// Only downloaded annotations are enriched: after the first run, annotations that didn't
+    /// change keep the enrichments of the previous checkpoint. An enricher failing fails the
+    /// sync like an API error.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(scope = %scope.summary(), full = full)))]
+    pub async fn sync_store_enriched(
+        &self,
+        store: &Store,
+        scope: &SearchQuery,
+        full: bool,
+        enrichers: &[&dyn Enricher],
+        limit: usize,
+        progress: impl ProgressObserver,
+    ) -> Result<Checkpoint, HypothesisError> {
+        let previous = match store.latest_checkpoint()? {
+            Some(checkpoint) if !full => Some(store.load_enriched(&checkpoint)?),
+            _ => None,
+        };
+        let mut query = scope.clone();
+        if let Some(previous) = &previous {
+            query.sort = Sort::Updated;
+            query.order = Order::Asc;
+            // others may have been updated at the same time as the latest one
+            if let Some(latest) = previous.iter().map(|a| a.annotation.updated).max() {
+                PageCursor::start_at(&mut query, latest)?;
+            }
+        }
+        let mut first = true;
+        let mut tracker = Tracker::new(progress, 0);
+        let mut cursor = PageCursor::default();
+        let mut updated = Vec::new();
+        let cancelled = loop {
+            if let Err(cancelled) = tracker.check_cancelled() {
+                break Some(cancelled);
+            }
+            let page = self.search_page(&query).await?;
+            // the first page has the total
+            if std::mem::take(&mut first) {
+                tracker.set_total(page.total);
+            }
+            match cursor.advance(&mut query, page)? {
+                Some(page) => {
+                    for annotation in enrich_annotations(page, enrichers, limit).await? {
+                        tracker.record(Ok::<_, HypothesisError>(()))?;
+                        updated.push(annotation);
+                    }
+                }
+                None => break None,
+            }
+        };
+        match (previous, cancelled) {
+            (Some(previous), cancelled) => {
+                let checkpoint = store.save_enriched(merge(previous, updated))?;
+                cancelled.map_or(Ok(checkpoint), Err)
+            }
+            (None, None) => store.save_enriched(updated),
+            (None, Some(cancelled)) => Err(cancelled),
+        }
+    }
+
+    /// A [`SyncDaemon`] that runs [`Hypothesis::sync_store`] for `scope` every `interval`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    /// use futures::StreamExt;
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// use hypothesis::store::Store;
+    /// let api = Hypothesis::from_env()?;
+    /// let scope = SearchQuery::builder().user(&api.user().0).limit(200).build()?;
+    /// let daemon = api.sync_daemon(Store::open("annotations")?, &scope, Duration::from_secs(600));
+    /// let handle = daemon.shutdown_handle();
+    /// tokio::spawn(async move {
+    ///     tokio::time::sleep(Duration::from_secs(24 * 3600)).await;
+    ///     if let Some(summary) = handle.shutdown().await {
+    ///         eprintln!("stopped after {} runs at {:?}", summary.runs, summary.checkpoint);
+    ///     }
+    /// });
+    /// let mut checkpoints = Box::pin(daemon.into_stream());
+    /// while let Some(checkpoint) = checkpoints.next().await {
+    ///     match checkpoint {
+    ///         Ok(checkpoint) => println!("saved checkpoint {}", checkpoint.id),
+    ///         Err(e) => eprintln!("sync failed: {}", e),
+    ///     }
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn sync_daemon(
+        &self,
+        store: Store,
+        scope: &SearchQuery,
+        interval: Duration,
+    ) -> SyncDaemon<'_> {
+        SyncDaemon {
+            api: self,
+            store,
+            scope: scope.clone(),
+            interval,
+            full_every: 10,
+            stop: {
+                let (sender, receiver) = watch::channel(false);
+                (Arc::new(sender), receiver)
+            },
+            summary: watch::channel(None),
+        }
+    }
+}
+
+/// Totals of a [`SyncDaemon`] that was shut down
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncSummary {
+    pub runs: u64,
+    pub failed_runs: u64,
+    /// Latest checkpoint in the store, including one saved by a sync cut short by the shutdown
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// Keeps a [`Store`] in sync with a search, with a full sync every so many runs.
+/// Made with [`Hypothesis::sync_daemon`].
+pub struct SyncDaemon<'a> {
+    api: &'a Hypothesis,
+    store: Store,
+    scope: SearchQuery,
+    interval: Duration,
+    full_every: u32,
+    stop: (Arc<watch::Sender<bool>>, watch::Receiver<bool>),
+    summary: (
+        watch::Sender<Option<SyncSummary>>,
+        watch::Receiver<Option<SyncSummary>>,
+    ),
+}
+
+impl<'a> SyncDaemon<'a> {
+    /// Search everything again on every `runs`-th run, which notices deleted annotations
+    /// (default: 10, 0 for never)
+    pub fn full_every(mut self, runs: u32) -> Self {
+        self.full_every = runs;
+        self
+    }
+
+    /// A handle to stop the stream made by [`SyncDaemon::into_stream`].
+    ///
+    /// A sync in progress stops after the page it is on and, unless it's a full sync, saves
+    /// what it downloaded as a checkpoint before the stream ends.
+    pub fn shutdown_handle(&self) -> ShutdownHandle<SyncSummary> {
+        ShutdownHandle {
+            stop: self.stop.0.clone(),
+            summary: self.summary.1.clone(),
+        }
+    }
+
+    /// Syncs right away and then every interval, yielding the saved checkpoints.
+    ///
+    /// Errors are yielded as they occur and syncing continues at the next interval.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Checkpoint, HypothesisError>> + 'a {
+        let state = (self, SyncSummary::default(), false);
+        futures::stream::unfold(state, |(daemon, mut summary, started)| async move {
+            let mut stop = daemon.stop.1.clone();
+            if started && !*stop.borrow() {
+                tokio::select! {
+                    _ = tokio::time::sleep(daemon.interval) => {}
+                    _ = stop.changed() => {}
+                }
+            }
+            let result = if *stop.borrow() {
+                None
+            } else {
+                summary.runs += 1;
+                let full =
+                    daemon.full_every > 0 && summary.runs % u64::from(daemon.full_every) == 0;
+                let stopped = Stopped(stop);
+                Some(
+                    daemon
+                        .api
+                        .sync_store(&daemon.store, &daemon.scope, full, stopped)
+                        .await,
+                )
+            };
+            match result {
+                Some(Ok(checkpoint)) => Some((Ok(checkpoint), (daemon, summary, true))),
+                // stopped, possibly part-way through a sync
+                None | Some(Err(HypothesisError::Cancelled(_))) => {
+                    summary.checkpoint = daemon.store.latest_checkpoint().ok().flatten();
+                    let _ = daemon.summary.0.send(Some(summary));
+                    None
+                }
+                Some(Err(e)) => {
+                    summary.failed_runs += 1;
+                    Some((Err(e), (daemon, summary, true)))
+                }
+            }
+        })
+    }
+}
+
+/// Cancels a sync once the daemon is asked to stop
+struct Stopped(watch::Receiver<bool>);
+
+impl ProgressObserver for Stopped {
+    fn update(&mut self, _progress: &Progress) {}
+
+    fn cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+}
+
+/// `previous` with the annotations in `updated` replaced or added
+fn merge(
+    previous: Vec<EnrichedAnnotation>,
+    updated: Vec<EnrichedAnnotation>,
+) -> Vec<EnrichedAnnotation> {
+    let order: Vec<AnnotationID> = updated.iter().map(|a| a.annotation.id.clone()).collect();
+    let mut updated: HashMap<AnnotationID, EnrichedAnnotation> = updated
+        .into_iter()
+        .map(|a| (a.annotation.id.clone(), a))
+        .collect();
+    let mut annotations: Vec<_> = previous
+        .into_iter()
+        .map(|a| updated.remove(&a.annotation.id).unwrap_or(a))
+        .collect();
+    annotations.extend(order.iter().filter_map(|id| updated.remove(id)));
+    annotations
+}
+
+/// Quote, text and tags of `annotation`
+fn searchable(annotation: &Annotation) -> impl Iterator<Item = &str> {
+    quote(annotation)
+        .into_iter()
+        .chain([annotation.text.as_str()])
+        .chain(annotation.tags.iter().map(String::as_str))
+}
+
+/// The `annotations` that pass `filter` and contain each of `terms` in their quote, text or
+/// a tag, most recently updated first.
+///
+/// Terms are words or "quoted phrases" and are matched ignoring case.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// use regex::Regex;
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::filter::Filter;
+/// use hypothesis::store::search;
+/// # fn annotation(id: &str, quote: &str, text: &str, tags: &[&str]) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": text,
+/// #         "tags": tags, "group": "__world__", "hidden": false, "flagged": false, "links": {},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "target": [{"source": "https://example.com", "selector": [
+/// #             {"type": "TextQuoteSelector", "exact": quote, "prefix": "", "suffix": ""}]}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("a", "Ownership rules in Rust", "", &["memory safety"]),
+///     annotation("b", "Rules of thumb", "see ownership", &[]),
+///     annotation("c", "Issue #1234 was fixed", "", &["bug"]),
+/// ];
+/// let found = search(&annotations, "\"ownership rules\" \"memory safety\"", &Filter::all())?;
+/// assert_eq!(found.len(), 1);
+/// assert_eq!(&*found[0].id, "a");
+///
+/// let filter = Filter::any_matches(Regex::new(r"#\d+").unwrap());
+/// assert_eq!(&*search(&annotations, "", &filter)?[0].id, "c");
+/// # Ok(())
+/// # }
+/// ```
+pub fn search(
+    annotations: &[Annotation],
+    terms: &str,
+    filter: &Filter,
+) -> Result<Vec<Annotation>, HypothesisError> {
+    let terms: Vec<String> = search_terms(terms)?
+        .into_iter()
+        .map(|term| unquote(term).to_lowercase())
+        .collect();
+    let mut found: Vec<Annotation> = annotations
+        .iter()
+        .filter(|annotation| filter.matches(annotation))
+        .filter(|annotation| {
+            let fields: Vec<String> = searchable(annotation).map(str::to_lowercase).collect();
+            terms
+                .iter()
+                .all(|term| fields.iter().any(|field| field.contains(term.as_str())))
+        })
+        .cloned()
+        .collect();
+    found.sort_by_key(|annotation| std::cmp::Reverse(annotation.updated));
+    Ok(found)
+}
+
+/// Difference between two sets of annotations
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Changes {
+    /// Present only in the newer set
+    pub created: Vec<Annotation>,
+    /// Present in both sets but different
+    pub updated: Vec<AnnotationChange>,
+    /// Present only in the older set
+    pub deleted: Vec<Annotation>,
+}
+
+impl Changes {
+    /// True if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// An annotation that changed, along with the fields that changed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnnotationChange {
+    pub before: Annotation,
+    pub after: Annotation,
+    pub fields: Vec<FieldChange>,
+}
+
+/// Old and new (JSON) value of a single annotation field
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// Field name as serialized, e.g. "text" or "tags"
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// Compares two sets of annotations by ID.
+/// Results are ordered as in `after` (created and updated) and `before` (deleted).
+pub fn changes(before: &[Annotation], after: &[Annotation]) -> Changes {
+    let old: HashMap<&AnnotationID, &Annotation> = before.iter().map(|a| (&a.id, a)).collect();
+    let new: HashMap<&AnnotationID, &Annotation> = after.iter().map(|a| (&a.id, a)).collect();
+    let mut changes = Changes::default();
+    for annotation in after {
+        match old.get(&annotation.id) {
+            None => changes.created.push(annotation.clone()),
+            Some(previous) if *previous != annotation => changes.updated.push(AnnotationChange {
+                before: (*previous).clone(),
+                after: annotation.clone(),
+                fields: field_changes(previous, annotation),
+            }),
+            Some(_) => (),
+        }
+    }
+    changes.deleted = before
+        .iter()
+        .filter(|a| !new.contains_key(&a.id))
+        .cloned()
+        .collect();
+    changes
+}
+
+/// Compares the serialized top-level fields of two annotations
+pub fn field_changes(before: &Annotation, after: &Annotation) -> Vec<FieldChange> {
+    let as_map = |a: &Annotation| match serde_json::to_value(a) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    let (before, mut after) = (as_map(before), as_map(after));
+    let mut fields = Vec::new();
+    for (field, old_value) in before {
+        let new_value = after.remove(&field).unwrap_or(serde_json::Value::Null);
+        if old_value != new_value {
+            fields.push(FieldChange {
+                field,
+                before: old_value,
+                after: new_value,
+            });
+        }
+    }
+    for (field, new_value) in after {
+        fields.push(FieldChange {
+            field,
+            before: serde_json::Value::Null,
+            after: new_value,
+        });
+    }
+    fields
+}
@@ -0,0 +1,180 @@
+//! Tag management utilities (listing / rename / merge / bulk add / bulk remove)
+//!
+//! Each operation searches for the matching annotations and then patches their tags one by one,
+//! reporting to a [`ProgressObserver`](../progress/trait.ProgressObserver.html) after every update.
+
+use std::collections::HashMap;
+
+use crate::annotations::{Annotation, SearchQuery};
+use crate::errors::HypothesisError;
+use crate::progress::{ProgressObserver, Tracker};
+use crate::Hypothesis;
+
+impl Hypothesis {
+    /// Count how often each tag is used across all annotations matching `scope`
+    /// (e.g. a user's or a group's annotations).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// #     let group_id = dotenv::var("TEST_GROUP_ID")?;
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let scope = SearchQuery::builder().group(&group_id).build()?;
+    /// let mut tags: Vec<_> = api.list_tags(&scope).await?.into_iter().collect();
+    /// // Most used first
+    /// tags.sort_by(|a, b| b.1.cmp(&a.1));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(scope = %scope.summary())))]
+    pub async fn list_tags(
+        &self,
+        scope: &SearchQuery,
+    ) -> Result<HashMap<String, usize>, HypothesisError> {
+        let annotations = self
+            .search_annotations_return_all(&mut scope.clone())
+            .await?;
+        Ok(count_tags(&annotations))
+    }
+
+    /// Rename the tag `old` to `new` on all annotations matching `scope`.
+    ///
+    /// Renaming to a tag that's already present on an annotation merges the two tags.
+    /// Returns the updated annotations.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// use hypothesis::annotations::SearchQuery;
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let scope = SearchQuery::builder().user(&api.user().0).build()?;
+    /// let renamed = api
+    ///     .rename_tag("rustlang", "rust", &scope, |done, total| println!("{}/{}", done, total))
+    ///     .await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(old = %old, new = %new, scope = %scope.summary())))]
+    pub async fn rename_tag(
+        &self,
+        old: &str,
+        new: &str,
+        scope: &SearchQuery,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let mut query = scope.clone();
+        query.tag = old.into();
+        self.patch_tags(query, |tags| rename_tag(tags, old, new), progress)
+            .await
+    }
+
+    /// Add `tag` to all annotations matching `query` which don't have it yet.
+    /// Returns the updated annotations.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(tag = %tag, query = %query.summary())))]
+    pub async fn add_tag_to_matching(
+        &self,
+        query: &SearchQuery,
+        tag: &str,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        self.patch_tags(query.clone(), |tags| add_tag(tags, tag), progress)
+            .await
+    }
+
+    /// Remove `tag` from all annotations matching `query`.
+    /// Returns the updated annotations.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(tag = %tag, query = %query.summary())))]
+    pub async fn remove_tag_from_matching(
+        &self,
+        query: &SearchQuery,
+        tag: &str,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let mut query = query.clone();
+        query.tag = tag.into();
+        self.patch_tags(query, |tags| remove_tag(tags, tag), progress)
+            .await
+    }
+
+    /// Applies `patch` to the tags of every annotation matching `query`,
+    /// updating only those annotations for which `patch` reports a change.
+    async fn patch_tags(
+        &self,
+        mut query: SearchQuery,
+        patch: impl Fn(&mut Vec<String>) -> bool,
+        progress: impl ProgressObserver,
+    ) -> Result<Vec<Annotation>, HypothesisError> {
+        let changed: Vec<_> = self
+            .search_annotations_return_all(&mut query)
+            .await?
+            .into_iter()
+            .filter_map(|mut annotation| patch(&mut annotation.tags).then_some(annotation))
+            .collect();
+        let mut tracker = Tracker::new(progress, changed.len());
+        let mut updated = Vec::with_capacity(changed.len());
+        for annotation in &changed {
+            tracker.check_cancelled()?;
+            updated.push(tracker.record(self.update_annotation(annotation).await)?);
+        }
+        Ok(updated)
+    }
+}
+
+/// Count how often each tag is used in `annotations`
+pub fn count_tags(annotations: &[Annotation]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for tag in annotations.iter().flat_map(|a| &a.tags) {
+        *counts.entry(tag.to_owned()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Replaces `old` with `new`, merging the two if `new` is already present.
+/// Returns true if `tags` changed.
+fn rename_tag(tags: &mut Vec<String>, old: &str, new: &str) -> bool {
+    if old == new || !tags.iter().any(|t| t == old) {
+        return false;
+    }
+    let has_new = tags.iter().any(|t| t == new);
+    let mut renamed = false;
+    tags.retain_mut(|t| {
+        if t != old {
+            return true;
+        }
+        if has_new || renamed {
+            return false;
+        }
+        *t = new.to_owned();
+        renamed = true;
+        true
+    });
+    true
+}
+
+/// Returns true if `tags` changed.
+fn add_tag(tags: &mut Vec<String>, tag: &str) -> bool {
+    if tags.iter().any(|t| t == tag) {
+        false
+    } else {
+        tags.push(tag.to_owned());
+        true
+    }
+}
+
+/// Returns true if `tags` changed.
+fn remove_tag(tags: &mut Vec<String>, tag: &str) -> bool {
+    let before = tags.len();
+    tags.retain(|t| t != tag);
+    tags.len() != before
+}
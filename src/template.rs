@@ -0,0 +1,171 @@
+//! Templates for new annotations
+//!
+//! An [`AnnotationTemplate`] holds defaults such as a team's group and tagging convention, loaded
+//! from a file and applied with
+//! [`InputAnnotationBuilder::apply_template`](../annotations/struct.InputAnnotationBuilder.html#method.apply_template).
+//!
+//! To write an annotation in a text editor, the way `git commit` asks for a commit message,
+//! [`annotation_template`] makes a buffer to open in `$EDITOR`, with the fields of the
+//! annotation as `key: value` lines, followed by a blank line and the (multi-line) text.
+//! Lines starting with `#` before the blank line are ignored, in the text they are kept as
+//! Markdown headings. [`parse_annotation_template`] reads the saved buffer
+//! back, and gives None if the text and quote were left empty, to cancel.
+//!
+//! ```text
+//! # Annotation on https://example.com/post
+//! uri: https://example.com/post
+//! group: __world__
+//! tags: rust, async
+//! quote: the highlighted passage
+//!
+//! First paragraph of the note.
+//!
+//! Second paragraph.
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{InputAnnotation, Selector, Target};
+use crate::errors::HypothesisError;
+use crate::GroupID;
+
+/// Defaults for new annotations
+///
+/// Templates are read from JSON files with [`AnnotationTemplate::load`]; as they implement
+/// `Deserialize`, they can be read from TOML or YAML with the respective serde crates too.
+///
+/// # Example
+/// ```
+/// use hypothesis::UserAccountID;
+/// use hypothesis::annotations::InputAnnotation;
+/// use hypothesis::template::AnnotationTemplate;
+/// let template: AnnotationTemplate = serde_json::from_str(
+///     r#"{"group": "Qe7fpc5Z", "tags": ["research", "to-review"], "private": true}"#,
+/// ).unwrap();
+/// let me = UserAccountID::new("alice", "hypothes.is");
+/// let annotation = InputAnnotation::builder()
+///     .uri("https://example.com/paper")
+///     .text("Compare with the 2019 results")
+///     .tags(vec!["methods".into()])
+///     .apply_template(&template, &me)
+///     .build()
+///     .unwrap();
+/// assert_eq!(&*annotation.group, "Qe7fpc5Z");
+/// assert_eq!(annotation.tags.unwrap(), vec!["methods", "research", "to-review"]);
+/// assert!(annotation.permissions.unwrap().is_private());
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AnnotationTemplate {
+    /// Group of annotations that don't set one
+    pub group: Option<GroupID>,
+    /// Added to the tags of every annotation
+    pub tags: Vec<String>,
+    /// Make annotations that don't set permissions private to their creator
+    pub private: bool,
+}
+
+impl AnnotationTemplate {
+    /// Reads a template file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, HypothesisError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Writes a template file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), HypothesisError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Header keys of the template, in order
+const KEYS: [&str; 4] = ["uri", "group", "tags", "quote"];
+
+/// A buffer to edit a new annotation on `uri` in `group`
+pub fn annotation_template(uri: &str, group: &GroupID) -> String {
+    format!(
+        "# Annotation on {uri}\n\
+         # Lines starting with '#' up here are ignored, an empty text and quote cancel.\n\
+         # Tags are separated by commas, the text goes below the blank line.\n\
+         uri: {uri}\n\
+         group: {group}\n\
+         tags: \n\
+         quote: \n\
+         \n",
+        uri = uri,
+        group = group
+    )
+}
+
+/// Reads an edited [`annotation_template`], None if it was left empty
+///
+/// Lines starting with `#` are comments in the header only, the text keeps them as headings.
+///
+/// # Example
+/// ```
+/// use hypothesis::template::{annotation_template, parse_annotation_template};
+/// let mut buffer = annotation_template("https://example.com/post", &"__world__".into());
+/// buffer = buffer.replace("tags: ", "tags: rust, async");
+/// buffer.push_str("First line\n\n# Heading\n\nSecond paragraph\n");
+/// let annotation = parse_annotation_template(&buffer).unwrap().unwrap();
+/// assert_eq!(annotation.uri, "https://example.com/post");
+/// assert_eq!(annotation.tags, Some(vec!["rust".into(), "async".into()]));
+/// assert_eq!(annotation.text.as_deref(), Some("First line\n\n# Heading\n\nSecond paragraph"));
+/// let untouched = annotation_template("https://example.com/post", &"__world__".into());
+/// assert!(parse_annotation_template(&untouched).unwrap().is_none());
+/// ```
+pub fn parse_annotation_template(buffer: &str) -> Result<Option<InputAnnotation>, HypothesisError> {
+    let mut lines = buffer.lines();
+    let mut annotation = InputAnnotation::default();
+    let mut quote = String::new();
+    for line in lines.by_ref() {
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .filter(|(key, _)| KEYS.contains(&key.trim()))
+            .ok_or_else(|| {
+                HypothesisError::ValidationError(format!(
+                    "expected one of {} followed by ':' before the blank line, got {:?}",
+                    KEYS.join(", "),
+                    line
+                ))
+            })?;
+        let value = value.trim();
+        match key.trim() {
+            "uri" => annotation.uri = value.to_owned(),
+            "group" => annotation.group = value.into(),
+            "tags" => {
+                annotation.tags = Some(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_owned)
+                        .collect(),
+                )
+            }
+            _ => quote = value.to_owned(),
+        }
+    }
+    let text = lines.collect::<Vec<_>>().join("\n").trim().to_owned();
+    if text.is_empty() && quote.is_empty() {
+        return Ok(None);
+    }
+    annotation.text = Some(text);
+    if !quote.is_empty() {
+        annotation.target = Target {
+            source: annotation.uri.clone(),
+            selector: vec![Selector::new_quote(&quote, "", "")],
+        };
+    }
+    annotation.validate()?;
+    Ok(Some(annotation))
+}
@@ -0,0 +1,137 @@
+//! Canned API payloads: annotations, groups, profiles and error bodies as the API returns them
+//!
+//! Everything belongs to [`user`], "acct:tester@hypothes.is", and times start at [`BASE_TIME`],
+//! so tests can assert on fixed values.
+//!
+//! # Example
+//! ```
+//! use hypothesis::testing::fixtures;
+//! let annotations = fixtures::annotations(3);
+//! assert_eq!(annotations[2].id.0, "annotation0002");
+//! assert!(annotations[0].updated < annotations[1].updated);
+//! assert_eq!(annotations[0].user, fixtures::user());
+//! assert_eq!(fixtures::error(404)["status"], "failure");
+//! ```
+
+use std::collections::HashMap;
+
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::annotations::Annotation;
+use crate::groups::{Group, Member};
+use crate::profile::UserProfile;
+use crate::{UserAccountID, DEFAULT_AUTHORITY};
+
+/// Username of the fixture user
+pub const USERNAME: &str = "tester";
+
+/// Creation and update time of [`annotation`], the first of [`annotations`]
+pub const BASE_TIME: &str = "2023-01-02T10:00:00Z";
+
+/// "acct:tester@hypothes.is"
+pub fn user() -> UserAccountID {
+    UserAccountID::new(USERNAME, DEFAULT_AUTHORITY)
+}
+
+/// A public annotation by [`user`] on <https://example.com>, made at [`BASE_TIME`], with a quote
+/// and a tag
+pub fn annotation(id: &str) -> Annotation {
+    serde_json::from_value(json!({
+        "id": id,
+        "created": BASE_TIME,
+        "updated": BASE_TIME,
+        "user": user(),
+        "uri": "https://example.com",
+        "text": format!("Note {}", id),
+        "tags": ["fixture"],
+        "group": "__world__",
+        "permissions": {
+            "read": ["group:__world__"],
+            "admin": [user()],
+            "update": [user()],
+            "delete": [user()],
+        },
+        "target": [{
+            "source": "https://example.com",
+            "selector": [{
+                "type": "TextQuoteSelector",
+                "exact": "Example Domain",
+                "prefix": "",
+                "suffix": " This domain is for use",
+            }],
+        }],
+        "document": {"title": ["Example Domain"]},
+        "links": {
+            "html": format!("https://hypothes.is/a/{}", id),
+            "incontext": format!("https://hyp.is/{}/example.com", id),
+            "json": format!("https://hypothes.is/api/annotations/{}", id),
+        },
+        "user_info": {"display_name": "Tester"},
+        "flagged": false,
+        "hidden": false,
+    }))
+    .expect("fixture is a valid annotation")
+}
+
+/// `count` annotations like [`annotation`] with IDs "annotation0000", "annotation0001", ..., each
+/// updated a minute after the one before
+pub fn annotations(count: usize) -> Vec<Annotation> {
+    let base = OffsetDateTime::parse(BASE_TIME, &Rfc3339).expect("valid fixture time");
+    (0..count)
+        .map(|i| {
+            let mut annotation = annotation(&format!("annotation{:04}", i));
+            annotation.created = base + time::Duration::minutes(i as i64);
+            annotation.updated = annotation.created;
+            annotation
+        })
+        .collect()
+}
+
+/// A private group called `name`
+pub fn group(id: &str, name: &str) -> Group {
+    serde_json::from_value(json!({
+        "id": id,
+        "groupid": null,
+        "name": name,
+        "links": {"html": format!("https://hypothes.is/groups/{}/{}", id, name.to_lowercase())},
+        "organization": "__default__",
+        "scoped": false,
+        "type": "private",
+    }))
+    .expect("fixture is a valid group")
+}
+
+/// A member of a group with the username `username`
+pub fn member(username: &str) -> Member {
+    Member {
+        authority: DEFAULT_AUTHORITY.into(),
+        username: username.into(),
+        userid: UserAccountID::new(username, DEFAULT_AUTHORITY).0,
+        display_name: None,
+    }
+}
+
+/// The profile of [`user`]
+pub fn profile() -> UserProfile {
+    UserProfile {
+        authority: DEFAULT_AUTHORITY.into(),
+        features: HashMap::new(),
+        preferences: HashMap::from([("show_sidebar_tutorial".into(), false)]),
+        userid: Some(user()),
+    }
+}
+
+/// The body of an error response with HTTP status `status`
+pub fn error(status: u16) -> serde_json::Value {
+    let reason = match status {
+        400 => "Invalid request",
+        401 | 403 => "You do not have permission to perform this action",
+        404 => "Either the resource you requested doesn't exist, or you are not currently authorized to see it.",
+        409 => "Conflict with the current state of the resource",
+        429 => "Too many requests, slow down",
+        _ => "Internal server error",
+    };
+    json!({"status": "failure", "reason": reason})
+}
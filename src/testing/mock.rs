@@ -0,0 +1,394 @@
+//! A fake Hypothesis API on a local port, for tests that run without network or account
+//!
+//! [`MockApi`] is a [wiremock](https://docs.rs/wiremock) server answering the annotation, group
+//! and profile endpoints from an in-memory store, seeded with [`super::fixtures`] or
+//! anything else. Annotations created, updated and deleted through the API change the store, and
+//! searches filter, sort and page it like the API does, so paginated calls and bulk operations
+//! can be tested end to end. [`MockApi::fail`] makes an endpoint return an error instead.
+//!
+//! Searches support `limit`, `offset`, `sort` (by `updated` or `created`), `order`,
+//! `search_after`, `user`, `group`, `uri`, `tag`, `text` and `references`. Other endpoints answer
+//! 404 unless mocked on [`MockApi::server`] with the re-exported [`wiremock`].
+//!
+//! # Example
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+//! use hypothesis::annotations::SearchQuery;
+//! use hypothesis::testing::{fixtures, mock::MockApi};
+//! let mock = MockApi::start().await;
+//! mock.add_annotations(fixtures::annotations(450));
+//! let api = mock.client()?;
+//! let mut query = SearchQuery::builder().limit(200).build()?;
+//! assert_eq!(api.search_annotations_return_all(&mut query).await?.len(), 450);
+//!
+//! mock.fail("GET", "search", 503).await;
+//! let error = api.search_annotations(&query).await.unwrap_err();
+//! assert!(error.is_retryable());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use url::Url;
+pub use wiremock;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::annotations::{Annotation, InputAnnotation, Permissions};
+use crate::errors::HypothesisError;
+use crate::groups::{Group, Member};
+use crate::profile::UserProfile;
+use crate::retry::RetryPolicy;
+use crate::testing::fixtures;
+use crate::{GroupID, Hypothesis, UserAccountID};
+
+/// Developer key of [`MockApi::client`]; the fake API doesn't check it
+pub const DEVELOPER_KEY: &str = "mock-developer-key";
+
+/// A fake Hypothesis API, see the [module docs](self). Stops when dropped.
+pub struct MockApi {
+    server: MockServer,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockApi {
+    /// Starts a fake API on a free local port, without annotations or groups and with the
+    /// fixture [profile](fixtures::profile)
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let state = Arc::new(Mutex::new(State {
+            profile: fixtures::profile(),
+            ..State::default()
+        }));
+        // mocks mounted later, e.g. by `fail`, take precedence
+        Mock::given(any())
+            .respond_with(FakeApi(state.clone()))
+            .with_priority(u8::MAX)
+            .mount(&server)
+            .await;
+        Self { server, state }
+    }
+
+    /// Base URL of the fake API, to use as [`Hypothesis::api_url`] or `$HYPOTHESIS_API_URL`
+    pub fn api_url(&self) -> String {
+        format!("{}/api", self.server.uri())
+    }
+
+    /// A client of the fake API for the fixture user, retrying with millisecond backoffs so
+    /// that failing calls don't slow tests down
+    pub fn client(&self) -> Result<Hypothesis, HypothesisError> {
+        let mut api = Hypothesis::new(fixtures::USERNAME, DEVELOPER_KEY)?;
+        api.set_api_url(&self.api_url());
+        api.set_retry_policy(RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        });
+        Ok(api)
+    }
+
+    /// The underlying server, to mount other responses or check the requests it received
+    pub fn server(&self) -> &MockServer {
+        &self.server
+    }
+
+    /// Adds annotations to the store
+    pub fn add_annotations(&self, annotations: impl IntoIterator<Item = Annotation>) {
+        self.state().annotations.extend(annotations);
+    }
+
+    /// The annotations in the store, in the order they were added
+    pub fn annotations(&self) -> Vec<Annotation> {
+        self.state().annotations.clone()
+    }
+
+    /// Adds a group with `members` to the store. Groups the profile's user is a member of are
+    /// also returned by `profile/groups`.
+    pub fn add_group(&self, group: Group, members: impl IntoIterator<Item = Member>) {
+        let mut state = self.state();
+        state
+            .members
+            .insert(group.id.clone(), members.into_iter().collect());
+        state.groups.push(group);
+    }
+
+    /// Replaces the profile returned by `profile`, whose user also makes new annotations and groups
+    pub fn set_profile(&self, profile: UserProfile) {
+        self.state().profile = profile;
+    }
+
+    /// Makes every `method` call of `path` (relative to [`api_url`](Self::api_url), e.g.
+    /// "search" or "annotations/abc") fail with `status` and the [error fixture](fixtures::error)
+    pub async fn fail(&self, method: &str, path: &str, status: u16) {
+        self.failure(method, path, status).mount(&self.server).await;
+    }
+
+    /// Like [`fail`](Self::fail) for the next `times` calls only, e.g. to test retries
+    pub async fn fail_times(&self, method: &str, path: &str, status: u16, times: u64) {
+        self.failure(method, path, status)
+            .up_to_n_times(times)
+            .mount(&self.server)
+            .await;
+    }
+
+    fn failure(&self, http_method: &str, api_path: &str, status: u16) -> Mock {
+        Mock::given(method(http_method))
+            .and(path(format!("/api/{}", api_path.trim_matches('/'))))
+            .respond_with(reply(status, fixtures::error(status)))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// What the fake API stores
+#[derive(Default)]
+struct State {
+    annotations: Vec<Annotation>,
+    groups: Vec<Group>,
+    members: HashMap<GroupID, Vec<Member>>,
+    profile: UserProfile,
+    /// Annotations and groups created through the API, for their IDs
+    created: usize,
+}
+
+impl State {
+    /// The user making calls
+    fn user(&self) -> UserAccountID {
+        self.profile.userid.clone().unwrap_or_else(fixtures::user)
+    }
+
+    fn search(&self, url: &Url) -> ResponseTemplate {
+        let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let tags: Vec<&str> = params
+            .iter()
+            .filter(|(key, _)| key == "tag" || key == "tags")
+            .map(|(_, value)| value.as_str())
+            .collect();
+        let user: Option<UserAccountID> = param("user").and_then(|user| user.parse().ok());
+        let by_created = param("sort") == Some("created");
+        let at = |annotation: &Annotation| {
+            if by_created {
+                annotation.created
+            } else {
+                annotation.updated
+            }
+        };
+        let ascending = param("order") == Some("asc");
+        let after = param("search_after").and_then(|at| OffsetDateTime::parse(at, &Rfc3339).ok());
+        let limit = param("limit")
+            .and_then(|limit| limit.parse().ok())
+            .unwrap_or(20usize)
+            .min(200);
+        let offset = param("offset")
+            .and_then(|offset| offset.parse().ok())
+            .unwrap_or(0);
+        let mut rows: Vec<&Annotation> = self
+            .annotations
+            .iter()
+            .filter(|annotation| {
+                user.as_ref().is_none_or(|user| annotation.user == *user)
+                    && param("group").is_none_or(|group| annotation.group.0 == group)
+                    && param("uri").is_none_or(|uri| annotation.uri == uri)
+                    && param("text").is_none_or(|text| annotation.text.contains(text))
+                    && param("references")
+                        .is_none_or(|id| annotation.references.iter().any(|r| r.0 == id))
+                    && tags
+                        .iter()
+                        .all(|tag| annotation.tags.iter().any(|t| t == tag))
+                    && after.is_none_or(|after| {
+                        if ascending {
+                            at(annotation) > after
+                        } else {
+                            at(annotation) < after
+                        }
+                    })
+            })
+            .collect();
+        // stable, so annotations with the same time stay in the order they were added
+        if ascending {
+            rows.sort_by_key(|annotation| at(annotation));
+        } else {
+            rows.sort_by_key(|annotation| std::cmp::Reverse(at(annotation)));
+        }
+        let total = rows.len();
+        let rows: Vec<_> = rows.into_iter().skip(offset).take(limit).collect();
+        reply(200, json!({"total": total, "rows": rows}))
+    }
+
+    fn create_annotation(&mut self, request: &Request) -> ResponseTemplate {
+        let Ok(input) = request.body_json::<InputAnnotation>() else {
+            return reply(400, fixtures::error(400));
+        };
+        if input.uri.is_empty() {
+            return reply(
+                400,
+                json!({"status": "failure", "reason": "uri: 'uri' is a required property"}),
+            );
+        }
+        self.created += 1;
+        let user = self.user();
+        let mut annotation = fixtures::annotation(&format!("mock{:06}", self.created));
+        annotation.created = OffsetDateTime::now_utc();
+        annotation.updated = annotation.created;
+        if !input.group.is_empty() {
+            annotation.group = input.group;
+        }
+        annotation.permissions = input
+            .permissions
+            .unwrap_or_else(|| Permissions::shared(&annotation.group, &user));
+        annotation.user = user;
+        annotation.text = input.text.unwrap_or_default();
+        annotation.tags = input.tags.unwrap_or_default();
+        annotation.document = input.document;
+        annotation.references = input.references.unwrap_or_default();
+        let mut target = input.target;
+        target.source.clone_from(&input.uri);
+        annotation.target = vec![target];
+        annotation.uri = input.uri;
+        self.annotations.push(annotation.clone());
+        reply(200, annotation)
+    }
+
+    fn update_annotation(&mut self, id: &str, request: &Request) -> ResponseTemplate {
+        let Some(annotation) = self.annotations.iter_mut().find(|a| a.id.0 == id) else {
+            return reply(404, fixtures::error(404));
+        };
+        let Ok(serde_json::Value::Object(changes)) = request.body_json() else {
+            return reply(400, fixtures::error(400));
+        };
+        let Ok(serde_json::Value::Object(mut fields)) = serde_json::to_value(&*annotation) else {
+            return reply(500, fixtures::error(500));
+        };
+        for (field, value) in changes {
+            if !["id", "created", "updated", "user"].contains(&field.as_str()) {
+                fields.insert(field, value);
+            }
+        }
+        match serde_json::from_value::<Annotation>(fields.into()) {
+            Ok(mut updated) => {
+                updated.updated = OffsetDateTime::now_utc();
+                *annotation = updated.clone();
+                reply(200, updated)
+            }
+            Err(e) => reply(400, json!({"status": "failure", "reason": e.to_string()})),
+        }
+    }
+
+    fn delete_annotation(&mut self, id: &str) -> ResponseTemplate {
+        let count = self.annotations.len();
+        self.annotations.retain(|annotation| annotation.id.0 != id);
+        if self.annotations.len() == count {
+            return reply(404, fixtures::error(404));
+        }
+        reply(200, json!({"id": id, "deleted": true}))
+    }
+
+    fn group(&self, id: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.id.0 == id)
+    }
+
+    /// Groups the user is a member of
+    fn user_groups(&self) -> Vec<&Group> {
+        let user = self.user();
+        self.groups
+            .iter()
+            .filter(|group| {
+                self.members
+                    .get(&group.id)
+                    .is_some_and(|members| members.iter().any(|m| m.userid == user.0))
+            })
+            .collect()
+    }
+
+    fn create_group(&mut self, request: &Request) -> ResponseTemplate {
+        let Ok(params) = request.body_json::<HashMap<String, String>>() else {
+            return reply(400, fixtures::error(400));
+        };
+        let Some(name) = params.get("name").filter(|name| !name.is_empty()) else {
+            return reply(
+                400,
+                json!({"status": "failure", "reason": "name: 'name' is a required property"}),
+            );
+        };
+        self.created += 1;
+        let group = fixtures::group(&format!("mock{:04}", self.created), name);
+        let user = self.user();
+        let member = fixtures::member(user.username());
+        self.members.insert(group.id.clone(), vec![member]);
+        self.groups.push(group.clone());
+        reply(200, group)
+    }
+
+    fn leave_group(&mut self, id: &str) -> ResponseTemplate {
+        let user = self.user();
+        match self.members.get_mut(&GroupID::from(id)) {
+            Some(members) if members.iter().any(|m| m.userid == user.0) => {
+                members.retain(|m| m.userid != user.0);
+                ResponseTemplate::new(204)
+            }
+            _ => reply(404, fixtures::error(404)),
+        }
+    }
+}
+
+/// Answers calls from the [`State`] of a [`MockApi`]
+struct FakeApi(Arc<Mutex<State>>);
+
+impl Respond for FakeApi {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let mut state = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(api_path) = request.url.path().strip_prefix("/api/") else {
+            return reply(404, fixtures::error(404));
+        };
+        let segments: Vec<&str> = api_path.trim_end_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["search"]) => state.search(&request.url),
+            ("POST", ["annotations"]) => state.create_annotation(request),
+            ("GET", ["annotations", id]) => found(
+                state
+                    .annotations
+                    .iter()
+                    .find(|annotation| annotation.id.0 == *id),
+            ),
+            ("PATCH", ["annotations", id]) => state.update_annotation(id, request),
+            ("DELETE", ["annotations", id]) => state.delete_annotation(id),
+            ("GET", ["groups"]) => reply(200, &state.groups),
+            ("POST", ["groups"]) => state.create_group(request),
+            ("GET", ["groups", id]) => found(state.group(id)),
+            ("GET", ["groups", id, "members"]) => found(state.members.get(&GroupID::from(*id))),
+            ("DELETE", ["groups", id, "members", "me"]) => state.leave_group(id),
+            ("GET", ["profile"]) => reply(200, &state.profile),
+            ("GET", ["profile", "groups"]) => reply(200, state.user_groups()),
+            _ => reply(404, fixtures::error(404)),
+        }
+    }
+}
+
+/// `body` if there is one, the 404 error fixture otherwise
+fn found(body: Option<impl Serialize>) -> ResponseTemplate {
+    match body {
+        Some(body) => reply(200, body),
+        None => reply(404, fixtures::error(404)),
+    }
+}
+
+/// A JSON response
+fn reply(status: u16, body: impl Serialize) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(body)
+}
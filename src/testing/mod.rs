@@ -0,0 +1,88 @@
+//! Helpers for examples and integration tests
+//!
+//! [`ephemeral_group`] runs a test against a real account. [`fixtures`] has canned API payloads,
+//! and with the "mock" feature `mock::MockApi` serves them from a fake API on a local port, for
+//! tests that need neither network nor account. Add the feature to the dev-dependency:
+//! ```toml
+//! [dev-dependencies]
+//! hypothesis-rs = { version = "0.11", features = ["mock"] }
+//! ```
+
+pub mod fixtures;
+#[cfg(feature = "mock")]
+pub mod mock;
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+
+use futures::FutureExt;
+use time::OffsetDateTime;
+
+use crate::annotations::SearchQuery;
+use crate::errors::HypothesisError;
+use crate::{GroupID, Hypothesis};
+
+/// Runs `test` with the ID of a new private group, then cleans up: the annotations the client's
+/// user made in the group are deleted and the group is left.
+///
+/// Cleanup also happens if `test` panics, after which the panic is resumed.
+///
+/// # Example
+/// ```no_run
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use hypothesis::Hypothesis;
+/// use hypothesis::annotations::InputAnnotation;
+/// use hypothesis::testing::ephemeral_group;
+/// let api = Hypothesis::from_env()?;
+/// ephemeral_group(&api, |group_id| async {
+///     let annotation = api
+///         .create_annotation(&InputAnnotation::builder()
+///             .text("scratch")
+///             .uri("https://example.com")
+///             .group(group_id)
+///             .build()?)
+///         .await?;
+///     assert_eq!(annotation.text, "scratch");
+///     Ok::<_, hypothesis::errors::HypothesisError>(())
+/// })
+/// .await??;
+/// #     Ok(())
+/// # }
+/// ```
+pub async fn ephemeral_group<F, Fut, T>(api: &Hypothesis, test: F) -> Result<T, HypothesisError>
+where
+    F: FnOnce(GroupID) -> Fut,
+    Fut: Future<Output = T>,
+{
+    // group names are at most 25 characters
+    let name = format!("test {}", OffsetDateTime::now_utc().unix_timestamp());
+    let group = api
+        .create_group(&name, Some("Temporary group, left after testing"))
+        .await?;
+    let outcome = AssertUnwindSafe(test(group.id.clone()))
+        .catch_unwind()
+        .await;
+    let cleanup = clean_up(api, &group.id).await;
+    match outcome {
+        Ok(output) => cleanup.map(|_| output),
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
+
+/// Deletes the user's annotations in the group and leaves it
+async fn clean_up(api: &Hypothesis, group_id: &GroupID) -> Result<(), HypothesisError> {
+    let mut query = SearchQuery::builder()
+        .group(group_id.clone())
+        .user(&api.user().0)
+        .limit(200)
+        .build()?;
+    let ids: Vec<_> = api
+        .search_annotations_return_all(&mut query)
+        .await?
+        .into_iter()
+        .map(|annotation| annotation.id)
+        .collect();
+    api.delete_annotations(&ids).await?;
+    api.leave_group(group_id).await
+}
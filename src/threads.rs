@@ -0,0 +1,239 @@
+//! Reply threads and plain-text transcripts of annotation conversations
+
+use std::collections::{HashMap, HashSet};
+
+use crate::annotations::{Annotation, Selector};
+use crate::AnnotationID;
+
+/// An annotation together with its (recursive) replies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thread {
+    pub annotation: Annotation,
+    /// Direct replies, oldest first
+    pub replies: Vec<Thread>,
+}
+
+/// Arrange annotations into reply threads.
+///
+/// A reply's parent is the last entry in its `references`. Replies whose parent isn't in
+/// `annotations` become roots of their own thread. Roots and replies are sorted by creation time.
+pub fn build_threads(annotations: Vec<Annotation>) -> Vec<Thread> {
+    let ids: HashSet<AnnotationID> = annotations.iter().map(|a| a.id.to_owned()).collect();
+    let mut children: HashMap<AnnotationID, Vec<Annotation>> = HashMap::new();
+    let mut roots = Vec::new();
+    for annotation in annotations {
+        match annotation.references.last() {
+            Some(parent) if ids.contains(parent) => children
+                .entry(parent.to_owned())
+                .or_default()
+                .push(annotation),
+            _ => roots.push(annotation),
+        }
+    }
+    fn attach(
+        annotation: Annotation,
+        children: &mut HashMap<AnnotationID, Vec<Annotation>>,
+    ) -> Thread {
+        let mut replies = children.remove(&annotation.id).unwrap_or_default();
+        replies.sort_by_key(|a| a.created);
+        Thread {
+            replies: replies
+                .into_iter()
+                .map(|reply| attach(reply, children))
+                .collect(),
+            annotation,
+        }
+    }
+    roots.sort_by_key(|a| a.created);
+    roots
+        .into_iter()
+        .map(|root| attach(root, &mut children))
+        .collect()
+}
+
+/// Produce a clean, speaker-attributed transcript of an annotation conversation.
+///
+/// Each message is attributed to its author's display name (or username),
+/// the highlighted text of the root annotation is marked as a `>` quote,
+/// replies are indented and Markdown formatting is stripped from the text.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::UserInfo;
+/// use hypothesis::testing::fixtures;
+/// use hypothesis::threads::{build_threads, thread_to_plaintext};
+/// let mut question = fixtures::annotation("a");
+/// question.text = "Is this **right**? Compare [a] and [b](https://example.com/b)".into();
+/// let mut answer = fixtures::annotation("b");
+/// answer.text = "See [the docs](https://example.com/docs)\nfor some_var at https://example.com/a_b".into();
+/// answer.references = vec![question.id.clone()];
+/// answer.user_info = Some(UserInfo { display_name: Some("Zoë".into()), extra: Default::default() });
+/// let threads = build_threads(vec![question, answer]);
+/// assert_eq!(
+///     thread_to_plaintext(&threads[0]),
+///     concat!(
+///         "> Example Domain\n",
+///         "Tester: Is this right? Compare [a] and b\n",
+///         "  Zoë: See the docs\n",
+///         "       for some_var at https://example.com/a_b\n",
+///     )
+/// );
+/// ```
+pub fn thread_to_plaintext(thread: &Thread) -> String {
+    let mut transcript = String::new();
+    for quote in quotes(&thread.annotation) {
+        for line in quote.lines() {
+            transcript.push_str("> ");
+            transcript.push_str(line.trim());
+            transcript.push('\n');
+        }
+    }
+    write_message(thread, 0, &mut transcript);
+    transcript
+}
+
+fn write_message(thread: &Thread, depth: usize, transcript: &mut String) {
+    let indent = "  ".repeat(depth);
+    let mut lines = strip_markdown(&thread.annotation.text);
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    let speaker = speaker(&thread.annotation);
+    for (i, line) in lines.iter().enumerate() {
+        transcript.push_str(&indent);
+        if i == 0 {
+            transcript.push_str(&speaker);
+            transcript.push(':');
+        } else {
+            transcript.push_str(&" ".repeat(speaker.chars().count() + 1));
+        }
+        if !line.is_empty() {
+            transcript.push(' ');
+            transcript.push_str(line);
+        }
+        transcript.push('\n');
+    }
+    for reply in &thread.replies {
+        write_message(reply, depth + 1, transcript);
+    }
+}
+
+/// Display name if available, otherwise the username part of the account ID
+fn speaker(annotation: &Annotation) -> String {
+    annotation
+        .user_info
+        .as_ref()
+        .and_then(|info| info.display_name.to_owned())
+        .unwrap_or_else(|| annotation.user.username().to_owned())
+}
+
+/// Highlighted text of an annotation
+fn quotes(annotation: &Annotation) -> Vec<&str> {
+    annotation
+        .target
+        .iter()
+        .flat_map(|target| &target.selector)
+        .filter_map(|selector| match selector {
+            Selector::TextQuoteSelector(quote) => Some(quote.exact.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Strips common Markdown syntax (headings, emphasis, code, links, images, block quotes, lists)
+/// and drops blank lines
+fn strip_markdown(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            let line = line
+                .trim()
+                .trim_start_matches('#')
+                .trim_start_matches('>')
+                .trim_start();
+            let line = line
+                .strip_prefix("- ")
+                .or_else(|| line.strip_prefix("* "))
+                .unwrap_or(line);
+            strip_inline_markdown(line).trim().to_owned()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// `[text](url)` and `![alt](url)` become `text` / `alt`, and matched pairs of emphasis, strike
+/// through and code markers are removed. Unmatched markers and ones inside words (`snake_case`,
+/// URLs) are kept.
+fn strip_inline_markdown(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    let mut previous = None;
+    while let Some(c) = rest.chars().next() {
+        let start = match c {
+            '[' => Some(1),
+            '!' if rest[1..].starts_with('[') => Some(2),
+            _ => None,
+        };
+        if let Some((label, after)) = start.and_then(|start| link(&rest[start..])) {
+            out.push_str(&strip_inline_markdown(label));
+            rest = after;
+            previous = label.chars().last();
+            continue;
+        }
+        if matches!(c, '*' | '_' | '~' | '`') {
+            let run = &rest[..rest.len() - rest.trim_start_matches(c).len()];
+            if let Some((inner, after)) = delimited(run, &rest[run.len()..], previous) {
+                if c == '`' {
+                    out.push_str(inner);
+                } else {
+                    out.push_str(&strip_inline_markdown(inner));
+                }
+                rest = after;
+                previous = run.chars().last();
+                continue;
+            }
+            out.push_str(run);
+            rest = &rest[run.len()..];
+            previous = Some(c);
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+        previous = Some(c);
+    }
+    out
+}
+
+/// Label and the rest after `label](url)`, if `text` (following the opening bracket) starts so
+fn link(text: &str) -> Option<(&str, &str)> {
+    let close = text.find(']')?;
+    let after = text[close + 1..].strip_prefix('(')?;
+    let end = after.find(')')?;
+    Some((&text[..close], &after[end + 1..]))
+}
+
+/// Text between the delimiter `run` and the same run closing it in `text`, and the rest after
+/// that. `previous` is the character before the opening run: underscores inside words don't
+/// delimit.
+fn delimited<'a>(run: &str, text: &'a str, previous: Option<char>) -> Option<(&'a str, &'a str)> {
+    let inword = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    let underscore = run.starts_with('_');
+    if text.starts_with(char::is_whitespace) || (underscore && inword(previous)) {
+        return None;
+    }
+    let marker = run.chars().next()?;
+    let mut from = 0;
+    while let Some(found) = text[from..].find(run) {
+        let at = from + found;
+        let after = &text[at + run.len()..];
+        let inner = &text[..at];
+        let closes = !inner.is_empty()
+            && !inner.ends_with(|c: char| c.is_whitespace() || c == marker)
+            && !after.starts_with(marker)
+            && (!underscore || !inword(after.chars().next()));
+        if closes {
+            return Some((inner, after));
+        }
+        from = at + run.len();
+    }
+    None
+}
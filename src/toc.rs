@@ -0,0 +1,116 @@
+//! Table of contents of the highlights on a document, e.g. for the top of a literature note
+
+use crate::annotations::{sort_by_position, Annotation, SearchQuery, Selector};
+use crate::errors::HypothesisError;
+use crate::Hypothesis;
+
+/// Quotes longer than this are shortened in the table of contents
+const MAX_QUOTE_CHARS: usize = 60;
+
+/// Highlighted text of an annotation
+pub fn quote(annotation: &Annotation) -> Option<&str> {
+    annotation
+        .target
+        .iter()
+        .flat_map(|target| &target.selector)
+        .find_map(|selector| match selector {
+            Selector::TextQuoteSelector(quote) => Some(quote.exact.as_str()),
+            _ => None,
+        })
+}
+
+/// A numbered Markdown list of the highlights among `annotations` in document order, each with
+/// its position, a shortened quote and a link to the highlight in context.
+/// Replies and page notes (without a quote) are left out.
+///
+/// # Example
+/// ```
+/// use hypothesis::annotations::Annotation;
+/// use hypothesis::toc::table_of_contents;
+/// # fn annotation(id: &str, start: u64, exact: &str) -> Annotation {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "id": id, "created": "2021-01-01T00:00:00Z", "updated": "2021-01-01T00:00:00Z",
+/// #         "user": "acct:alice@hypothes.is", "uri": "https://example.com", "text": "",
+/// #         "tags": [], "group": "__world__", "hidden": false, "flagged": false,
+/// #         "links": {"incontext": format!("https://hyp.is/{}/example.com", id)},
+/// #         "permissions": {"read": [], "delete": [], "admin": [], "update": []},
+/// #         "target": [{"source": "https://example.com", "selector": [
+/// #             {"type": "TextPositionSelector", "start": start, "end": start + 10},
+/// #             {"type": "TextQuoteSelector", "exact": exact, "prefix": "", "suffix": ""}]}],
+/// #     })).unwrap()
+/// # }
+/// let annotations = vec![
+///     annotation("b", 900, "Results are reproducible across all three datasets we evaluated, with small variance"),
+///     annotation("a", 120, "The main claim"),
+/// ];
+/// assert_eq!(
+///     table_of_contents(&annotations),
+///     "1. [The main claim](https://hyp.is/a/example.com) (at 120)\n\
+///      2. [Results are reproducible across all three datasets we…](https://hyp.is/b/example.com) (at 900)\n"
+/// );
+/// ```
+pub fn table_of_contents(annotations: &[Annotation]) -> String {
+    let mut highlights: Vec<_> = annotations
+        .iter()
+        .filter(|annotation| annotation.references.is_empty() && quote(annotation).is_some())
+        .cloned()
+        .collect();
+    sort_by_position(&mut highlights);
+    let mut toc = String::new();
+    for (i, annotation) in highlights.iter().enumerate() {
+        let quote = shorten(quote(annotation).unwrap_or_default());
+        let link = annotation
+            .links
+            .incontext
+            .as_ref()
+            .or(annotation.links.html.as_ref());
+        toc.push_str(&format!("{}. ", i + 1));
+        match link {
+            Some(link) => toc.push_str(&format!("[{}]({})", quote, link)),
+            None => toc.push_str(&quote),
+        }
+        if let Some(position) = annotation.position() {
+            toc.push_str(&format!(" (at {})", position));
+        }
+        toc.push('\n');
+    }
+    toc
+}
+
+/// Collapses whitespace and cuts `quote` at a word boundary if it's too long
+fn shorten(quote: &str) -> String {
+    let words: Vec<_> = quote.split_whitespace().collect();
+    let mut short = String::new();
+    for word in &words {
+        if !short.is_empty() && short.chars().count() + 1 + word.chars().count() > MAX_QUOTE_CHARS {
+            short.push('…');
+            return short;
+        }
+        if !short.is_empty() {
+            short.push(' ');
+        }
+        short.push_str(word);
+    }
+    short
+}
+
+impl Hypothesis {
+    /// The [`table_of_contents`] of the highlights visible to the user on the document at `uri`
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::Hypothesis;
+    /// let api = Hypothesis::from_env()?;
+    /// print!("{}", api.document_toc("https://example.com/paper").await?);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn document_toc(&self, uri: &str) -> Result<String, HypothesisError> {
+        let mut query = SearchQuery::builder().uri(uri).limit(200).build()?;
+        let annotations = self.search_annotations_return_all(&mut query).await?;
+        Ok(table_of_contents(&annotations))
+    }
+}
@@ -0,0 +1,69 @@
+//! URI normalization as done by hypothes.is to decide whether two URIs are the same document
+//!
+//! Web URLs are compared without their scheme (http or https), default port, fragment, trailing
+//! slash and tracking parameters such as `utm_source`, and with the remaining query parameters
+//! sorted. Other URIs (e.g. "urn:x-pdf:..." or "doi:...") are compared as they are.
+
+use url::Url;
+
+/// Prefixes of query parameters that only track where a visitor came from
+pub const TRACKING_PARAM_PREFIXES: &[&str] = &["utm_", "WT."];
+
+/// Query parameters that only track where a visitor came from
+pub const TRACKING_PARAMS: &[&str] = &["via", "fbclid", "gclid", "mc_cid", "mc_eid"];
+
+fn is_tracking_param(name: &str) -> bool {
+    TRACKING_PARAMS.contains(&name)
+        || TRACKING_PARAM_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// The form of `uri` that is the same for all URIs of a document
+///
+/// # Example
+/// ```
+/// use hypothesis::uri::normalize_uri;
+/// assert_eq!(
+///     normalize_uri("HTTPS://Example.com:443/post/?utm_source=feed&b=2&a=1#comments"),
+///     "httpx://example.com/post?a=1&b=2"
+/// );
+/// assert_eq!(normalize_uri("urn:x-pdf:abc123"), "urn:x-pdf:abc123");
+/// ```
+pub fn normalize_uri(uri: &str) -> String {
+    let uri = uri.trim();
+    let url = match Url::parse(uri) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => return uri.to_owned(),
+    };
+    let mut normalized = format!("httpx://{}", url.host_str().unwrap_or_default());
+    if let Some(port) = url.port().filter(|port| ![80, 443].contains(port)) {
+        normalized.push_str(&format!(":{}", port));
+    }
+    normalized.push_str(url.path().trim_end_matches('/'));
+    let mut params: Vec<_> = url
+        .query_pairs()
+        .filter(|(name, _)| !is_tracking_param(name))
+        .collect();
+    if !params.is_empty() {
+        params.sort();
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+        normalized.push('?');
+        normalized.push_str(&query);
+    }
+    normalized
+}
+
+/// True if `a` and `b` are URIs of the same document
+///
+/// # Example
+/// ```
+/// use hypothesis::uri::equivalent;
+/// assert!(equivalent("http://example.com/post/", "https://example.com/post?utm_medium=email"));
+/// assert!(!equivalent("https://example.com/post?page=1", "https://example.com/post?page=2"));
+/// ```
+pub fn equivalent(a: &str, b: &str) -> bool {
+    normalize_uri(a) == normalize_uri(b)
+}
@@ -0,0 +1,81 @@
+//! Objects related to the "users" endpoint (only available to authority clients)
+
+use serde::{Deserialize, Serialize};
+
+use crate::{errors, is_default, UserAccountID};
+
+/// Struct to create users of a third-party authority
+///
+/// # Example
+/// ```
+/// use hypothesis::users::{Identity, NewUser};
+/// # fn main() -> Result<(), hypothesis::errors::HypothesisError> {
+/// let user = NewUser::builder()
+///     .authority("lms.example.com")
+///     .username("student_1")
+///     .email("student_1@example.com")
+///     .display_name("Student One")
+///     .identities(vec![Identity {
+///         provider: "lms.example.com".into(),
+///         provider_unique_id: "1234".into(),
+///     }])
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Builder, PartialEq)]
+#[builder(default, build_fn(name = "builder"))]
+pub struct NewUser {
+    /// Authority the user belongs to, must match the authority of the client credentials
+    #[builder(setter(into))]
+    pub authority: String,
+    /// string [ 3 .. 30 ] characters ^[A-Za-z0-9._]+$
+    #[builder(setter(into))]
+    pub username: String,
+    #[serde(skip_serializing_if = "is_default")]
+    #[builder(setter(into))]
+    pub email: String,
+    /// string <= 30 characters
+    #[serde(skip_serializing_if = "is_default")]
+    #[builder(setter(into))]
+    pub display_name: String,
+    /// Identities of the user with external identity providers
+    #[serde(skip_serializing_if = "is_default")]
+    pub identities: Vec<Identity>,
+}
+
+impl NewUser {
+    pub fn builder() -> NewUserBuilder {
+        NewUserBuilder::default()
+    }
+}
+
+impl NewUserBuilder {
+    /// Builds a new `NewUser`.
+    pub fn build(&self) -> Result<NewUser, errors::HypothesisError> {
+        self.builder()
+            .map_err(|e| errors::HypothesisError::BuilderError(e.to_string()))
+    }
+}
+
+/// A user's identity with an external identity provider
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Identity {
+    /// Unique identifier of the provider
+    pub provider: String,
+    /// Unique identifier of the user with the provider
+    pub provider_unique_id: String,
+}
+
+/// A user of a third-party authority
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub authority: String,
+    pub username: String,
+    /// "acct:{username}@{authority}"
+    pub userid: UserAccountID,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
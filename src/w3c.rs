@@ -0,0 +1,503 @@
+//! [W3C Web Annotation Data Model](https://www.w3.org/TR/annotation-model/) representation of annotations
+//!
+//! Exports annotations as an `AnnotationCollection` split into `AnnotationPage`s, as described in
+//! the [W3C Web Annotation Protocol](https://www.w3.org/TR/annotation-protocol/#container-retrieval),
+//! so that standards-compliant consumers (Mirador, other annotation servers) can ingest them directly.
+//!
+//! [`ContainerReader`] goes the other way, pulling annotations from other servers' (read-only)
+//! Annotation Protocol containers so they can be re-created in Hypothesis.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+use crate::annotations::{Annotation, InputAnnotation, Target};
+use crate::errors::{HypothesisError, RequestContext};
+use crate::{build_client, serde_parse, GroupID, Hypothesis};
+
+/// JSON-LD context of the Web Annotation vocabulary
+pub const ANNOTATION_CONTEXT: &str = "http://www.w3.org/ns/anno.jsonld";
+
+/// Stable IRI of a Hypothesis annotation
+pub fn annotation_iri(annotation: &Annotation) -> String {
+    format!("https://hypothes.is/a/{}", annotation.id)
+}
+
+/// A single Web Annotation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct W3CAnnotation {
+    #[serde(rename = "@context", default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// IRI of the annotation
+    pub id: String,
+    /// "Annotation"
+    #[serde(rename = "type")]
+    pub annotation_type: String,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub created: Option<OffsetDateTime>,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub modified: Option<OffsetDateTime>,
+    /// IRI (or account ID) of the author
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "one_or_many"
+    )]
+    pub body: Vec<TextualBody>,
+    #[serde(deserialize_with = "targets")]
+    pub target: Vec<Target>,
+}
+
+/// > The TextualBody class is used for embedding textual bodies within the Annotation.
+///
+/// [Web Annotation Data Model - Embedded Textual Body](https://www.w3.org/TR/annotation-model/#embedded-textual-body)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextualBody {
+    /// "TextualBody"
+    #[serde(rename = "type", default)]
+    pub body_type: String,
+    #[serde(default)]
+    pub value: String,
+    /// e.g. "commenting" for the annotation text, "tagging" for tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+impl TextualBody {
+    fn new(value: &str, purpose: &str, format: Option<&str>) -> Self {
+        Self {
+            body_type: "TextualBody".into(),
+            value: value.into(),
+            purpose: Some(purpose.into()),
+            format: format.map(String::from),
+        }
+    }
+}
+
+impl From<&Annotation> for W3CAnnotation {
+    fn from(annotation: &Annotation) -> Self {
+        let mut body = Vec::with_capacity(annotation.tags.len() + 1);
+        if !annotation.text.is_empty() {
+            body.push(TextualBody::new(
+                &annotation.text,
+                "commenting",
+                Some("text/markdown"),
+            ));
+        }
+        body.extend(
+            annotation
+                .tags
+                .iter()
+                .map(|tag| TextualBody::new(tag, "tagging", None)),
+        );
+        let target = if annotation.target.is_empty() {
+            vec![Target {
+                source: annotation.uri.to_owned(),
+                selector: vec![],
+            }]
+        } else {
+            annotation
+                .target
+                .iter()
+                .map(|target| Target {
+                    source: if target.source.is_empty() {
+                        annotation.uri.to_owned()
+                    } else {
+                        target.source.to_owned()
+                    },
+                    selector: target.selector.clone(),
+                })
+                .collect()
+        };
+        Self {
+            context: None,
+            id: annotation_iri(annotation),
+            annotation_type: "Annotation".into(),
+            created: Some(annotation.created),
+            modified: Some(annotation.updated),
+            creator: Some(annotation.user.0.to_owned()),
+            body,
+            target,
+        }
+    }
+}
+
+/// > An AnnotationCollection is an ordered list of Annotations
+///
+/// Only references its first and last page, the annotations are in the [`AnnotationPage`]s.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnnotationCollection {
+    #[serde(rename = "@context", default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    pub id: String,
+    /// "AnnotationCollection"
+    #[serde(rename = "type")]
+    pub collection_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Number of annotations in the collection
+    pub total: usize,
+    /// IRI of the first page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first: Option<String>,
+    /// IRI of the last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<String>,
+}
+
+/// One page of an [`AnnotationCollection`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AnnotationPage {
+    #[serde(rename = "@context", default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    pub id: String,
+    /// "AnnotationPage"
+    #[serde(rename = "type")]
+    pub page_type: String,
+    /// IRI of the collection this page belongs to
+    #[serde(rename = "partOf", default, skip_serializing_if = "Option::is_none")]
+    pub part_of: Option<String>,
+    /// Position of this page's first item within the collection
+    #[serde(rename = "startIndex", default)]
+    pub start_index: usize,
+    /// IRI of the next page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    /// IRI of the previous page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+    pub items: Vec<W3CAnnotation>,
+}
+
+/// Splits `annotations` into an [`AnnotationCollection`] with `page_size` annotations per page.
+///
+/// The collection gets the IRI `{base_iri}` and page `i` gets `{base_iri}?page={i}`,
+/// so IRIs are stable across exports of the same (ordered) annotations.
+///
+/// # Example
+/// ```
+/// use hypothesis::w3c::annotation_collection;
+/// # let annotations: Vec<hypothesis::annotations::Annotation> = vec![];
+/// let (collection, pages) = annotation_collection(
+///     &annotations,
+///     "https://example.com/exports/my-group",
+///     Some("My group"),
+///     100,
+/// );
+/// assert_eq!(collection.total, annotations.len());
+/// assert_eq!(collection.first.as_deref(), Some("https://example.com/exports/my-group?page=0"));
+/// assert_eq!(pages.len(), 1);
+/// ```
+pub fn annotation_collection(
+    annotations: &[Annotation],
+    base_iri: &str,
+    label: Option<&str>,
+    page_size: usize,
+) -> (AnnotationCollection, Vec<AnnotationPage>) {
+    let page_size = page_size.max(1);
+    let num_pages = annotations.len().div_ceil(page_size).max(1);
+    let page_iri = |i: usize| format!("{}?page={}", base_iri, i);
+    let pages = (0..num_pages)
+        .map(|i| {
+            let start = (i * page_size).min(annotations.len());
+            let end = ((i + 1) * page_size).min(annotations.len());
+            AnnotationPage {
+                context: Some(ANNOTATION_CONTEXT.into()),
+                id: page_iri(i),
+                page_type: "AnnotationPage".into(),
+                part_of: Some(base_iri.into()),
+                start_index: start,
+                next: (i + 1 < num_pages).then(|| page_iri(i + 1)),
+                prev: (i > 0).then(|| page_iri(i - 1)),
+                items: annotations[start..end]
+                    .iter()
+                    .map(W3CAnnotation::from)
+                    .collect(),
+            }
+        })
+        .collect();
+    let collection = AnnotationCollection {
+        context: Some(ANNOTATION_CONTEXT.into()),
+        id: base_iri.into(),
+        collection_type: "AnnotationCollection".into(),
+        label: label.map(String::from),
+        total: annotations.len(),
+        first: Some(page_iri(0)),
+        last: Some(page_iri(num_pages - 1)),
+    };
+    (collection, pages)
+}
+
+impl W3CAnnotation {
+    /// Converts to an annotation that can be created in Hypothesis, in `group`.
+    ///
+    /// Commenting (and untyped) text bodies become the text, tagging bodies the tags.
+    ///
+    /// # Example
+    /// ```
+    /// use hypothesis::GroupID;
+    /// use hypothesis::w3c::W3CAnnotation;
+    /// let annotation: W3CAnnotation = serde_json::from_str(r#"{
+    ///     "id": "https://annotations.example.com/annotations/1",
+    ///     "type": "Annotation",
+    ///     "body": [
+    ///         {"type": "TextualBody", "value": "A comment"},
+    ///         {"type": "TextualBody", "value": "rust", "purpose": "tagging"}
+    ///     ],
+    ///     "target": "https://example.com/page"
+    /// }"#).unwrap();
+    /// let input = annotation.to_input(&GroupID::from("__world__"));
+    /// assert_eq!(input.uri, "https://example.com/page");
+    /// assert_eq!(input.text.as_deref(), Some("A comment"));
+    /// assert_eq!(input.tags, Some(vec!["rust".to_string()]));
+    /// ```
+    pub fn to_input(&self, group: &GroupID) -> InputAnnotation {
+        let text = self
+            .body
+            .iter()
+            .filter(|body| !body.value.is_empty())
+            .filter(|body| matches!(body.purpose.as_deref(), None | Some("commenting")))
+            .map(|body| body.value.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let tags: Vec<String> = self
+            .body
+            .iter()
+            .filter(|body| body.purpose.as_deref() == Some("tagging"))
+            .map(|body| body.value.to_owned())
+            .collect();
+        let target = self.target.first().cloned().unwrap_or_default();
+        InputAnnotation {
+            uri: target.source.to_owned(),
+            text: (!text.is_empty()).then_some(text),
+            tags: (!tags.is_empty()).then_some(tags),
+            group: group.to_owned(),
+            target,
+            ..Default::default()
+        }
+    }
+}
+
+/// A page, either embedded or referenced by its IRI
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum PageRef {
+    Iri(String),
+    Page(Box<AnnotationPage>),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Container {
+    #[serde(default)]
+    first: Option<PageRef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ContainerPage {
+    #[serde(default)]
+    next: Option<String>,
+    #[serde(default)]
+    items: Vec<W3CAnnotation>,
+}
+
+/// Reads annotations from a [W3C Annotation Protocol](https://www.w3.org/TR/annotation-protocol/)
+/// container on another annotation server
+pub struct ContainerReader {
+    client: reqwest::Client,
+}
+
+impl ContainerReader {
+    /// A reader with its own HTTP client, trusting only the system's root certificates
+    pub fn new() -> Result<Self, HypothesisError> {
+        Ok(Self::with_client(build_client(&[], true)?))
+    }
+
+    /// A reader sending its requests through `client`, e.g. one trusting extra root
+    /// certificates
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    async fn get(&self, iri: &str) -> Result<String, HypothesisError> {
+        let response = self
+            .client
+            .get(iri)
+            .header(
+                reqwest::header::ACCEPT,
+                "application/ld+json; profile=\"http://www.w3.org/ns/anno.jsonld\"",
+            )
+            // ask for embedded annotations instead of just their IRIs
+            .header(
+                "Prefer",
+                "return=representation;include=\"http://www.w3.org/ns/oa#PreferContainedDescriptions\"",
+            )
+            .send()
+            .await
+            .map_err(HypothesisError::ReqwestError)?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(HypothesisError::ReqwestError)?;
+        if !status.is_success() {
+            return Err(HypothesisError::StatusError {
+                status: status.as_u16(),
+                source: Default::default(),
+                raw_text: text,
+                request_id: None,
+                context: Some(Box::new(RequestContext {
+                    method: "GET".into(),
+                    url: iri.into(),
+                    status: status.as_u16(),
+                })),
+            });
+        }
+        Ok(text)
+    }
+
+    /// Fetches all annotations in the container at `iri`, following its pages. A page linking
+    /// back to one already read ends the container.
+    pub async fn fetch_all(&self, iri: &str) -> Result<Vec<W3CAnnotation>, HypothesisError> {
+        let container = serde_parse::<Container>(&self.get(iri).await?)?;
+        let mut visited = HashSet::from([iri.to_owned()]);
+        let mut annotations = Vec::new();
+        let mut next = match container.first {
+            None => None,
+            Some(PageRef::Iri(iri)) => Some(iri),
+            Some(PageRef::Page(page)) => {
+                annotations.extend(page.items);
+                page.next
+            }
+        };
+        while let Some(iri) = next.filter(|iri| visited.insert(iri.to_owned())) {
+            let page = serde_parse::<ContainerPage>(&self.get(&iri).await?)?;
+            annotations.extend(page.items);
+            next = page.next;
+        }
+        Ok(annotations)
+    }
+}
+
+/// Error of [`Hypothesis::import_w3c_container`], with the annotations created before it
+#[derive(Error, Debug)]
+#[error("Import stopped after {} annotations: {source}", .created.len())]
+pub struct ImportError {
+    /// Annotations created before the error, in container order
+    pub created: Vec<Annotation>,
+    #[source]
+    pub source: HypothesisError,
+}
+
+/// An error before any annotation was created
+impl From<HypothesisError> for ImportError {
+    fn from(source: HypothesisError) -> Self {
+        Self {
+            created: Vec::new(),
+            source,
+        }
+    }
+}
+
+impl From<ImportError> for HypothesisError {
+    fn from(error: ImportError) -> Self {
+        error.source
+    }
+}
+
+impl Hypothesis {
+    /// Re-create all annotations of a W3C Annotation Protocol container in `group`, one at a time
+    /// and in container order.
+    ///
+    /// If creating one fails the [`ImportError`] has the annotations created before it, so they
+    /// can be skipped (or deleted) before trying again.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hypothesis::{GroupID, Hypothesis};
+    /// #     dotenv::dotenv()?;
+    /// #     let username = dotenv::var("HYPOTHESIS_NAME")?;
+    /// #     let developer_key = dotenv::var("HYPOTHESIS_KEY")?;
+    /// #     let group_id = GroupID::from(dotenv::var("TEST_GROUP_ID")?);
+    /// let api = Hypothesis::new(&username, &developer_key)?;
+    /// let imported = api
+    ///     .import_w3c_container("https://annotations.example.com/annotations/", &group_id)
+    ///     .await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(iri = %iri, group = %group)))]
+    pub async fn import_w3c_container(
+        &self,
+        iri: &str,
+        group: &GroupID,
+    ) -> Result<Vec<Annotation>, ImportError> {
+        // same root certificates and compression as API calls
+        let reader = ContainerReader::with_client(self.client.clone());
+        let annotations = reader.fetch_all(iri).await?;
+        let mut created = Vec::with_capacity(annotations.len());
+        for annotation in &annotations {
+            match self.create_annotation(&annotation.to_input(group)).await {
+                Ok(annotation) => created.push(annotation),
+                Err(source) => return Err(ImportError { created, source }),
+            }
+        }
+        Ok(created)
+    }
+}
+
+/// W3C allows a single value where an array is expected
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Targets can be plain IRIs, objects, or arrays of either
+fn targets<'de, D>(deserializer: D) -> Result<Vec<Target>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TargetRepr {
+        Iri(String),
+        Target(Target),
+    }
+    Ok(one_or_many::<_, TargetRepr>(deserializer)?
+        .into_iter()
+        .map(|target| match target {
+            TargetRepr::Iri(source) => Target {
+                source,
+                selector: vec![],
+            },
+            TargetRepr::Target(target) => target,
+        })
+        .collect())
+}
@@ -0,0 +1,712 @@
+#![cfg(feature = "mock")]
+// tests and sinks return the crate's error type as is
+#![allow(clippy::result_large_err)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde_json::json;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, ResponseTemplate};
+
+use hypothesis::annotations::{Annotation, InputAnnotation, Order, SearchQuery};
+use hypothesis::auth::AuthorityClient;
+use hypothesis::broadcast::BroadcastOptions;
+use hypothesis::bulk::{BulkAction, BulkGroup, BulkUser};
+use hypothesis::enrich::Enricher;
+use hypothesis::errors::HypothesisError;
+use hypothesis::groups::GroupFilters;
+use hypothesis::progress::{CancellationToken, WithCancellation};
+use hypothesis::realtime::{Overflow, StreamFilter};
+use hypothesis::retry::RetryPolicy;
+use hypothesis::store::Store;
+use hypothesis::testing::mock::MockApi;
+use hypothesis::testing::{ephemeral_group, fixtures};
+use hypothesis::{AnnotationID, Conditional, Hypothesis, UserAccountID};
+
+/// 450 annotations, of which 250 in the middle were updated at the same time
+fn colliding() -> Vec<Annotation> {
+    let mut annotations = fixtures::annotations(450);
+    let tied = annotations[100].updated;
+    for annotation in &mut annotations[100..350] {
+        annotation.updated = tied;
+    }
+    annotations
+}
+
+fn ids(annotations: &[Annotation]) -> Vec<String> {
+    annotations.iter().map(|a| a.id.0.clone()).collect()
+}
+
+/// Upper-cases the text, counting its calls
+#[derive(Default)]
+struct Shout(AtomicUsize);
+
+impl Enricher for Shout {
+    fn name(&self) -> &str {
+        "shout"
+    }
+
+    fn enrich<'a>(
+        &'a self,
+        annotation: &'a Annotation,
+    ) -> BoxFuture<'a, Result<Option<serde_json::Value>, HypothesisError>> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Box::pin(async move { Ok(Some(annotation.text.to_uppercase().into())) })
+    }
+}
+
+#[tokio::test]
+async fn create_fetch_update_delete() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let api = mock.client()?;
+    let created = api
+        .create_annotation(
+            &InputAnnotation::builder()
+                .uri("https://example.com/page")
+                .text("first")
+                .tags(vec!["mock".into()])
+                .build()?,
+        )
+        .await?;
+    assert_eq!(created.user, fixtures::user());
+    assert_eq!(created.target[0].source, "https://example.com/page");
+
+    let mut annotation = api.fetch_annotation(&created.id).await?;
+    assert_eq!(annotation, created);
+    annotation.text = "second".into();
+    let updated = api.update_annotation(&annotation).await?;
+    assert_eq!(updated.text, "second");
+    assert!(updated.updated >= created.updated);
+    assert_eq!(mock.annotations(), vec![updated]);
+
+    assert!(api.delete_annotation(&created.id).await?.deleted);
+    assert!(mock.annotations().is_empty());
+    let error = api.fetch_annotation(&created.id).await.unwrap_err();
+    assert!(error.is_not_found());
+    Ok(())
+}
+
+#[tokio::test]
+async fn pages_through_colliding_timestamps() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(colliding());
+    let api = mock.client()?;
+    for order in [Order::Asc, Order::Desc] {
+        let mut expected = ids(&colliding());
+        if order == Order::Desc {
+            expected.reverse();
+        }
+        let query = SearchQuery::builder().limit(200).order(order).build()?;
+
+        let all = api
+            .search_annotations_return_all(&mut query.clone())
+            .await?;
+        let mut found = ids(&all);
+        // the fake API keeps tied annotations in the order they were added in both orders
+        if query.order == Order::Desc {
+            found[100..350].reverse();
+        }
+        assert_eq!(found, expected);
+
+        let streamed: Vec<_> = api
+            .stream_search(&query)
+            .map(|annotation| annotation.map(|a| a.id.0))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+        assert_eq!(streamed, ids(&all));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn pages_past_invalid_rows() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(fixtures::annotations(3));
+    // the first page is only rows that aren't annotations
+    Mock::given(method("GET"))
+        .and(path("/api/search"))
+        .and(query_param("offset", "0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "total": 5,
+            "rows": [{"id": "deleted1"}, {"id": "deleted2"}],
+        })))
+        .with_priority(1)
+        .mount(mock.server())
+        .await;
+    let api = mock.client()?;
+    let mut query = SearchQuery::builder().limit(2).build()?;
+    let all = api.search_annotations_return_all(&mut query).await?;
+    assert_eq!(ids(&all), vec!["annotation0000"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn polls_and_syncs_through_colliding_timestamps() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let annotations = colliding();
+    mock.add_annotations(annotations[..200].to_vec());
+    let api = mock.client()?;
+    let query = SearchQuery::builder().limit(50).build()?;
+    let since = annotations[0].updated - time::Duration::seconds(1);
+    let mut poller = api
+        .poll_events(&query, std::time::Duration::from_secs(1))
+        .since(since);
+    let dir = std::env::temp_dir().join(format!("mock-sync-{}", std::process::id()));
+    let store = Store::open(&dir)?;
+    let polled: Vec<_> = poller
+        .poll()
+        .await?
+        .into_iter()
+        .map(|e| e.annotation().clone())
+        .collect();
+    assert_eq!(ids(&polled), ids(&annotations[..200]));
+    api.sync_store(&store, &query, true, ()).await?;
+
+    // more annotations updated at the same time as the last ones seen
+    mock.add_annotations(annotations[200..].to_vec());
+    let polled: Vec<_> = poller
+        .poll()
+        .await?
+        .into_iter()
+        .map(|e| e.annotation().clone())
+        .collect();
+    assert_eq!(ids(&polled), ids(&annotations[200..]));
+    let checkpoint = api.sync_store(&store, &query, false, ()).await?;
+    let mut synced = ids(&store.load(&checkpoint)?);
+    synced.sort();
+    assert_eq!(synced, ids(&annotations));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_oldest_events_over_capacity() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let annotations = fixtures::annotations(120);
+    mock.add_annotations(annotations.clone());
+    let api = mock.client()?;
+    let query = SearchQuery::builder().limit(50).build()?;
+    let events: Vec<_> = api
+        .poll_events(&query, std::time::Duration::from_secs(60))
+        .since(annotations[0].updated - time::Duration::seconds(1))
+        .buffer(10, Overflow::DropOldest)
+        .into_stream()
+        .take(10)
+        .map(|event| event.map(|event| event.annotation().clone()))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<_, _>>()?;
+    assert_eq!(ids(&events), ids(&annotations[110..]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn filter_narrows_the_polled_query() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let annotations = fixtures::annotations(3);
+    mock.add_annotations(annotations.clone());
+    let api = mock.client()?;
+    let since = annotations[0].updated - time::Duration::seconds(1);
+    let poll = |query: SearchQuery, filter: StreamFilter| {
+        let api = &api;
+        async move {
+            let mut poller = api
+                .poll_events(&query, std::time::Duration::from_secs(60))
+                .since(since)
+                .filter(filter);
+            poller.poll().await.map(|events| events.len())
+        }
+    };
+    let world = SearchQuery::builder().group("__world__").build()?;
+    let other = SearchQuery::builder().group("other").build()?;
+    assert_eq!(
+        poll(world.clone(), StreamFilter::new().group("__world__")).await?,
+        3
+    );
+    assert_eq!(
+        poll(other, StreamFilter::new().group("__world__")).await?,
+        0
+    );
+    assert_eq!(poll(world, StreamFilter::new().group("other")).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn broadcast_continues_after_cancel() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(fixtures::annotations(3));
+    let api = mock.client()?;
+    let query = SearchQuery::builder().tag("fixture").build()?;
+    let options = BroadcastOptions::default();
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let error = api
+        .broadcast_reply(
+            &query,
+            "Source?",
+            &options,
+            WithCancellation(move |_, _| cancel.cancel(), token),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error.source, HypothesisError::Cancelled(progress) if progress.done == 1));
+    assert_eq!(error.summary.replies.len(), 1);
+
+    mock.fail_times("POST", "annotations", 500, 1).await;
+    let error = api
+        .broadcast_reply(&query, "Source?", &options, ())
+        .await
+        .unwrap_err();
+    assert_eq!(error.source.status_code(), Some(500));
+    assert_eq!(error.summary.already_replied.len(), 1);
+    assert!(error.summary.replies.is_empty());
+
+    let summary = api.broadcast_reply(&query, "Source?", &options, ()).await?;
+    assert_eq!(summary.already_replied.len(), 1);
+    assert_eq!(summary.replies.len(), 2);
+    assert_eq!(mock.annotations().len(), 6);
+    Ok(())
+}
+
+#[tokio::test]
+async fn imports_w3c_container() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let page = |name: &str, uri: &str, next: &str| {
+        let iri = format!("{}/w3c/{}", mock.server().uri(), name);
+        let body = json!({
+            "id": iri,
+            "type": "AnnotationPage",
+            "next": format!("{}/w3c/{}", mock.server().uri(), next),
+            "items": [{
+                "id": format!("{}#1", iri),
+                "type": "Annotation",
+                "body": {"type": "TextualBody", "value": name, "purpose": "commenting"},
+                "target": uri,
+            }],
+        });
+        Mock::given(method("GET"))
+            .and(path(format!("/w3c/{}", name)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+    };
+    let container = format!("{}/w3c/", mock.server().uri());
+    Mock::given(method("GET"))
+        .and(path("/w3c/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": container,
+            "type": "AnnotationCollection",
+            "first": format!("{}page1", container),
+        })))
+        .mount(mock.server())
+        .await;
+    // the last page links back to the first
+    page("page1", "https://example.com", "page2")
+        .mount(mock.server())
+        .await;
+    page("page2", "https://example.com/2", "page1")
+        .mount(mock.server())
+        .await;
+    let api = mock.client()?;
+    let created = api
+        .import_w3c_container(&container, &"__world__".into())
+        .await?;
+    assert_eq!(
+        created.iter().map(|a| a.text.as_str()).collect::<Vec<_>>(),
+        vec!["page1", "page2"]
+    );
+    let requests = mock.server().received_requests().await.unwrap();
+    let container_request = requests.iter().find(|r| r.url.path() == "/w3c/").unwrap();
+    assert!(container_request.headers.contains_key("prefer"));
+    // the API key isn't sent to other servers
+    assert!(!container_request.headers.contains_key("authorization"));
+
+    // a target without a URI is rejected, after the annotation before it was created
+    page("page2", "", "page1")
+        .with_priority(1)
+        .mount(mock.server())
+        .await;
+    let error = api
+        .import_w3c_container(&container, &"__world__".into())
+        .await
+        .unwrap_err();
+    assert_eq!(error.source.status_code(), Some(400));
+    assert_eq!(error.created.len(), 1);
+    assert_eq!(mock.annotations().len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_and_poll_stop_on_cancel() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let annotations = fixtures::annotations(103);
+    mock.add_annotations(annotations[..3].to_vec());
+    let api = mock.client()?;
+    let query = SearchQuery::builder().limit(50).build()?;
+    let dir = std::env::temp_dir().join(format!("mock-cancel-{}", std::process::id()));
+    let store = Store::open(&dir)?;
+    api.sync_store(&store, &query, true, ()).await?;
+
+    mock.add_annotations(annotations[3..].to_vec());
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let observer = WithCancellation(move |_, _| cancel.cancel(), token);
+    let error = api
+        .sync_store(&store, &query, false, observer)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, HypothesisError::Cancelled(progress) if progress.done == 50));
+    // what was downloaded before the cancel is kept: the first page starts with the latest
+    // annotation of the previous checkpoint
+    let checkpoint = store.latest_checkpoint()?.unwrap();
+    assert_eq!(store.load(&checkpoint)?.len(), 52);
+    let checkpoint = api.sync_store(&store, &query, false, ()).await?;
+    assert_eq!(store.load(&checkpoint)?.len(), 103);
+    std::fs::remove_dir_all(dir)?;
+
+    let token = CancellationToken::new();
+    let mut poller = api
+        .poll_events(&query, std::time::Duration::from_secs(60))
+        .since(annotations[0].updated - time::Duration::seconds(1))
+        .cancel_on(token.clone());
+    token.cancel();
+    assert!(poller.poll().await?.is_empty());
+    assert_eq!(poller.into_stream().count().await, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn enriches_exports_and_syncs() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let annotations = fixtures::annotations(5);
+    mock.add_annotations(annotations[..3].to_vec());
+    let api = mock.client()?;
+    let query = SearchQuery::builder().limit(2).build()?;
+    let shout = Shout::default();
+
+    let dir = std::env::temp_dir().join(format!("mock-enrich-{}", std::process::id()));
+    let store = Store::open(&dir)?;
+    let mut exported = Vec::new();
+    let count = api
+        .export_resumable_enriched(&query, dir.join("export.json"), &[&shout], 2, |page| {
+            exported.extend_from_slice(page);
+            Ok(())
+        })
+        .await?;
+    assert_eq!(count, 3);
+    assert_eq!(exported[2].enrichments["shout"], "NOTE ANNOTATION0002");
+
+    api.sync_store_enriched(&store, &query, false, &[&shout], 2, ())
+        .await?;
+    mock.add_annotations(annotations[3..].to_vec());
+    let checkpoint = api
+        .sync_store_enriched(&store, &query, false, &[&shout], 2, ())
+        .await?;
+    // 3 exported, 3 on the first sync, then the latest one again and the 2 new ones
+    assert_eq!(shout.0.load(Ordering::SeqCst), 9);
+    let synced = store.load_enriched(&checkpoint)?;
+    assert_eq!(synced.len(), 5);
+    assert!(synced
+        .iter()
+        .all(|a| a.enrichments["shout"] == a.annotation.text.to_uppercase()));
+    let mut stored = ids(&store.load(&checkpoint)?);
+    stored.sort();
+    assert_eq!(stored, ids(&annotations));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_daemon_shuts_down() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(fixtures::annotations(3));
+    let api = mock.client()?;
+    let query = SearchQuery::builder().build()?;
+    let dir = std::env::temp_dir().join(format!("mock-daemon-{}", std::process::id()));
+    let daemon = api.sync_daemon(
+        Store::open(&dir)?,
+        &query,
+        std::time::Duration::from_secs(3600),
+    );
+    let handle = daemon.shutdown_handle();
+    let mut checkpoints = Box::pin(daemon.into_stream());
+    let first = checkpoints.next().await.unwrap()?;
+    // waiting for the next run
+    let (summary, rest) = tokio::join!(handle.shutdown(), checkpoints.count());
+    let summary = summary.unwrap();
+    assert_eq!(rest, 0);
+    assert_eq!(summary.runs, 1);
+    assert_eq!(summary.checkpoint, Some(first));
+    std::fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn resumes_export_after_failure() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(colliding());
+    let api = mock.client()?;
+    let checkpoint = std::env::temp_dir().join(format!("mock-export-{}.json", std::process::id()));
+    let query = SearchQuery::builder().limit(200).build()?;
+    let mut exported = Vec::new();
+    let mut pages = 0;
+    let error = api
+        .export_resumable(&query, &checkpoint, |page| {
+            pages += 1;
+            if pages == 2 {
+                return Err(HypothesisError::QueryError("sink full".into()));
+            }
+            exported.extend(ids(page));
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, HypothesisError::QueryError(_)));
+    assert!(checkpoint.exists());
+
+    let total = api
+        .export_resumable(&query, &checkpoint, |page| {
+            exported.extend(ids(page));
+            Ok(())
+        })
+        .await?;
+    assert_eq!(total, 450);
+    assert_eq!(exported, ids(&colliding()));
+    assert!(!checkpoint.exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn groups_and_profile() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_group(
+        fixtures::group("Qe7fpc5Z", "Reading"),
+        [
+            fixtures::member(fixtures::USERNAME),
+            fixtures::member("alice"),
+        ],
+    );
+    mock.add_group(
+        fixtures::group("Jx3nbqV9", "Other"),
+        [fixtures::member("bob")],
+    );
+    let api = mock.client()?;
+
+    assert_eq!(api.fetch_user_profile().await?, fixtures::profile());
+    assert_eq!(api.get_groups(&GroupFilters::default()).await?.len(), 2);
+    let groups = api.fetch_user_groups().await?;
+    assert_eq!(groups, vec![fixtures::group("Qe7fpc5Z", "Reading")]);
+    let members = api.get_group_members(&"Qe7fpc5Z".into()).await?;
+    assert_eq!(members[1].username, "alice");
+    // the client's own user stays in the group
+    let diff = api
+        .sync_group_members(&"Qe7fpc5Z".into(), &[], true, ())
+        .await?;
+    assert_eq!(diff.remove, vec![UserAccountID(members[1].userid.clone())]);
+    let error = api
+        .fetch_group(&"missing".into(), Vec::new())
+        .await
+        .unwrap_err();
+    assert!(error.is_not_found());
+
+    let text = ephemeral_group(&api, |group_id| {
+        let api = &api;
+        async move {
+            let annotation = api
+                .create_annotation(
+                    &InputAnnotation::builder()
+                        .uri("https://example.com")
+                        .text("scratch")
+                        .group(group_id)
+                        .build()?,
+                )
+                .await?;
+            Ok::<_, HypothesisError>(annotation.text)
+        }
+    })
+    .await??;
+    assert_eq!(text, "scratch");
+    // cleaned up: the annotation is deleted and the group left
+    assert!(mock.annotations().is_empty());
+    assert_eq!(api.fetch_user_groups().await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_cases() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.add_annotations(fixtures::annotations(1));
+    let mut api = mock.client()?;
+    api.set_retry_policy(RetryPolicy::none());
+    let query = SearchQuery::builder().build()?;
+
+    mock.fail_times("GET", "search", 429, 1).await;
+    let error = api.search_annotations(&query).await.unwrap_err();
+    assert!(error.is_rate_limited());
+    assert_eq!(error.context().unwrap().method, "GET");
+    assert_eq!(api.search_annotations(&query).await?.len(), 1);
+
+    mock.fail("GET", "annotations/annotation0000", 403).await;
+    let error = api
+        .fetch_annotation(&"annotation0000".into())
+        .await
+        .unwrap_err();
+    assert!(error.is_auth());
+    assert_eq!(error.status_code(), Some(403));
+
+    mock.fail("GET", "profile", 500).await;
+    let error = api.fetch_user_profile().await.unwrap_err();
+    assert_eq!(error.status_code(), Some(500));
+
+    let error = api
+        .create_annotation(&InputAnnotation {
+            text: Some("no uri".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(error.status_code(), Some(400));
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_failed_calls() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let mut annotation = fixtures::annotation("quoted");
+    annotation.text = r#"he said "hi""#.into();
+    let mut unquoted = fixtures::annotation("unquoted");
+    unquoted.text = "hi".into();
+    mock.add_annotations([annotation, unquoted]);
+    let api = mock.client()?;
+    let query = SearchQuery::builder().text(r#""hi""#).build()?;
+
+    mock.fail_times("GET", "search", 503, 2).await;
+    assert_eq!(api.search_annotations(&query).await?.len(), 1);
+
+    // only rate limited changes are sent again
+    let input = InputAnnotation::builder()
+        .uri("https://example.com")
+        .text("once")
+        .build()?;
+    mock.fail_times("POST", "annotations", 429, 1).await;
+    api.create_annotation(&input).await?;
+    mock.fail_times("POST", "annotations", 503, 1).await;
+    let error = api.create_annotation(&input).await.unwrap_err();
+    assert_eq!(error.status_code(), Some(503));
+    assert_eq!(mock.annotations().len(), 3);
+
+    mock.fail("GET", "profile", 503).await;
+    let error = api.fetch_user_profile().await.unwrap_err();
+    assert_eq!(error.status_code(), Some(503));
+    let calls = &mock.server().received_requests().await.unwrap();
+    let profile_calls = calls.iter().filter(|r| r.url.path().ends_with("/profile"));
+    assert_eq!(profile_calls.count(), 4);
+    Ok(())
+}
+
+#[tokio::test]
+async fn retries_conditional_fetch() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    mock.fail_times("GET", "annotations/cached", 503, 1).await;
+    Mock::given(method("GET"))
+        .and(path("/api/annotations/cached"))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .mount(mock.server())
+        .await;
+    let api = mock.client()?;
+    let id = AnnotationID::from("cached");
+    let fetched = api
+        .fetch_annotation_conditional(&id, Some("\"v1\""))
+        .await?;
+    assert!(matches!(fetched, Conditional::NotModified));
+    let calls = mock.server().received_requests().await.unwrap();
+    assert_eq!(calls.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn bulk_request_body() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    let results = concat!(
+        r#"{"data": {"id": "acct:student_1@lms.example.com", "type": "user", "meta": {"$anchor": "student_1"}}}"#,
+        "\n",
+        r#"{"data": {"id": "Qe7fpc5Z", "type": "group", "meta": {"$anchor": "course"}}}"#,
+        "\n",
+        r#"{"data": {"id": 42, "type": "group_membership"}}"#,
+        "\n",
+    );
+    Mock::given(method("POST"))
+        .and(path("/api/bulk"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(results))
+        .mount(mock.server())
+        .await;
+    let mut api = Hypothesis::with_authority("lms_bot", "developer_key", "lms.example.com")?;
+    api.set_api_url(&mock.api_url());
+    let client = AuthorityClient::new("client_id", "client_secret", "lms.example.com");
+    let actions = [
+        BulkAction::UpsertUser(BulkUser::new("student_1", "student_1", "Student One")),
+        BulkAction::UpsertGroup(BulkGroup::new("course", "Reading 101", "course-101")),
+        BulkAction::membership("student_1", "course"),
+    ];
+    let created = api.bulk(&client, &actions).await?;
+    assert_eq!(created[1].id, "Qe7fpc5Z");
+
+    let requests = mock.server().received_requests().await.unwrap();
+    let request = requests
+        .iter()
+        .find(|r| r.url.path() == "/api/bulk")
+        .unwrap();
+    let expected = [
+        r#"["configure",{"defaults":[["create","*",{"on_duplicate":"continue"}],["upsert","*",{"merge_query":true}]],"instructions":{"total_instructions":4},"user":{"effective":"acct:lms_bot@lms.example.com"},"view":"basic"}]"#,
+        r#"["upsert",{"data":{"attributes":{"authority":"lms.example.com","display_name":"Student One","identities":[],"username":"student_1"},"meta":{"$anchor":"student_1","query":{"authority":"lms.example.com","username":"student_1"}},"type":"user"}}]"#,
+        r#"["upsert",{"data":{"attributes":{"authority":"lms.example.com","authority_provided_id":"course-101","name":"Reading 101"},"meta":{"$anchor":"course","query":{"authority":"lms.example.com","authority_provided_id":"course-101"}},"type":"group"}}]"#,
+        r#"["create",{"data":{"relationships":{"group":{"data":{"id":{"$ref":"course"},"type":"group"}},"member":{"data":{"id":{"$ref":"student_1"},"type":"user"}}},"type":"group_membership"}}]"#,
+    ];
+    assert_eq!(
+        String::from_utf8_lossy(&request.body),
+        expected.join("\n") + "\n"
+    );
+    assert_eq!(
+        request.headers.get("content-type").unwrap(),
+        hypothesis::bulk::BULK_MEDIA_TYPE
+    );
+    assert_eq!(
+        request.headers.get("authorization").unwrap(),
+        "Basic Y2xpZW50X2lkOmNsaWVudF9zZWNyZXQ="
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn authority_client_gets_token_from_api_url() -> Result<(), HypothesisError> {
+    let mock = MockApi::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "grant-access-token",
+            "token_type": "Bearer",
+            "expires_in": 3600,
+        })))
+        .mount(mock.server())
+        .await;
+    let client = AuthorityClient::new("client_id", "client_secret", "lms.example.com");
+    let mut api = Hypothesis::from_authority_client(&client, "student_1")?;
+    api.set_api_url(&mock.api_url());
+    api.fetch_user_profile().await?;
+    api.fetch_user_profile().await?;
+
+    let requests = mock.server().received_requests().await.unwrap();
+    let paths: Vec<_> = requests.iter().map(|r| r.url.path()).collect();
+    assert_eq!(paths, ["/api/token", "/api/profile", "/api/profile"]);
+    let form = String::from_utf8_lossy(&requests[0].body);
+    assert!(form.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"));
+    assert_eq!(
+        requests[1].headers.get("authorization").unwrap(),
+        "Bearer grant-access-token"
+    );
+    Ok(())
+}